@@ -0,0 +1,14 @@
+-- Create sender_identities table
+CREATE TABLE IF NOT EXISTS sender_identities (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+name TEXT NOT NULL,
+address TEXT NOT NULL,
+daily_cap INTEGER,
+hourly_cap INTEGER,
+min_spacing_seconds INTEGER NOT NULL DEFAULT 0,
+created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+) ;
+
+-- Add sender and scheduling columns to messages table
+ALTER TABLE messages ADD COLUMN sender_id INTEGER REFERENCES sender_identities (id);
+ALTER TABLE messages ADD COLUMN scheduled_at TEXT;
@@ -0,0 +1,20 @@
+-- Add identity details to sender_identities table
+ALTER TABLE sender_identities ADD COLUMN signature TEXT;
+ALTER TABLE sender_identities ADD COLUMN reply_to TEXT;
+ALTER TABLE sender_identities ADD COLUMN transport TEXT NOT NULL DEFAULT '{"kind":"log"}';
+
+-- Create sender_pools table
+CREATE TABLE IF NOT EXISTS sender_pools (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+name TEXT NOT NULL,
+created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+) ;
+
+-- Create sender_pool_members table
+CREATE TABLE IF NOT EXISTS sender_pool_members (
+pool_id INTEGER NOT NULL,
+sender_id INTEGER NOT NULL,
+PRIMARY KEY (pool_id, sender_id),
+FOREIGN KEY (pool_id) REFERENCES sender_pools (id),
+FOREIGN KEY (sender_id) REFERENCES sender_identities (id)
+) ;
@@ -0,0 +1,4 @@
+-- Add the RFC 822 Message-ID of the sent email to messages table
+ALTER TABLE messages ADD COLUMN email_message_id TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_messages_email_message_id ON messages (email_message_id);
@@ -0,0 +1,23 @@
+-- Create sent_emails table, one row per email handed to a transport
+CREATE TABLE IF NOT EXISTS sent_emails (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+message_id INTEGER NOT NULL,
+sender_id INTEGER,
+kind TEXT NOT NULL,
+email_message_id TEXT,
+sent_at TEXT NOT NULL,
+FOREIGN KEY (message_id) REFERENCES messages (id),
+FOREIGN KEY (sender_id) REFERENCES sender_identities (id)
+) ;
+
+CREATE INDEX IF NOT EXISTS idx_sent_emails_email_message_id ON sent_emails (email_message_id);
+CREATE INDEX IF NOT EXISTS idx_sent_emails_sender_sent_at ON sent_emails (sender_id, sent_at);
+
+-- Backfill the initial emails that were already sent
+INSERT INTO sent_emails (message_id, sender_id, kind, email_message_id, sent_at)
+SELECT id, sender_id, 'initial', email_message_id, sent_at
+FROM messages
+WHERE sent_at IS NOT NULL;
+
+-- Add custom follow-up body to messages table
+ALTER TABLE messages ADD COLUMN follow_up_body TEXT;
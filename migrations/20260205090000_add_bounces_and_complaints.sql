@@ -0,0 +1,8 @@
+-- Add bounce details to messages table
+ALTER TABLE messages ADD COLUMN bounced_at TEXT;
+ALTER TABLE messages ADD COLUMN bounce_type TEXT;
+ALTER TABLE messages ADD COLUMN bounce_reason TEXT;
+
+-- Flag lead email addresses that can no longer be sent to
+ALTER TABLE leads ADD COLUMN email_undeliverable_at TEXT;
+ALTER TABLE leads ADD COLUMN email_undeliverable_reason TEXT;
@@ -0,0 +1,10 @@
+-- Create suppressions table
+CREATE TABLE IF NOT EXISTS suppressions (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+kind TEXT NOT NULL,
+value TEXT NOT NULL,
+reason TEXT,
+source TEXT NOT NULL,
+created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+UNIQUE (kind, value)
+) ;
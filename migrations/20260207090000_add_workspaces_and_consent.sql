@@ -0,0 +1,20 @@
+-- Create workspaces table
+CREATE TABLE IF NOT EXISTS workspaces (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+name TEXT NOT NULL,
+physical_address TEXT,
+footer TEXT,
+strict_consent INTEGER NOT NULL DEFAULT 0,
+created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+) ;
+
+-- Existing leads and senders belong to the default workspace
+INSERT INTO workspaces (id, name) VALUES (1, 'Default');
+
+ALTER TABLE leads ADD COLUMN workspace_id INTEGER NOT NULL DEFAULT 1 REFERENCES workspaces (id);
+ALTER TABLE sender_identities ADD COLUMN workspace_id INTEGER NOT NULL DEFAULT 1 REFERENCES workspaces (id);
+
+-- Add consent tracking to leads table
+ALTER TABLE leads ADD COLUMN consent_status TEXT NOT NULL DEFAULT 'unknown';
+ALTER TABLE leads ADD COLUMN consent_source TEXT;
+ALTER TABLE leads ADD COLUMN consent_at TEXT;
@@ -0,0 +1,18 @@
+-- Add channel to messages table
+ALTER TABLE messages ADD COLUMN channel TEXT NOT NULL DEFAULT 'email';
+
+-- Create sms_messages table
+CREATE TABLE IF NOT EXISTS sms_messages (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+message_id INTEGER REFERENCES messages (id),
+direction TEXT NOT NULL,
+kind TEXT,
+from_number TEXT,
+to_number TEXT,
+body TEXT NOT NULL,
+segments INTEGER NOT NULL,
+provider_message_id TEXT,
+created_at TEXT NOT NULL
+) ;
+
+CREATE INDEX IF NOT EXISTS idx_sms_messages_to_number ON sms_messages (direction, to_number);
@@ -0,0 +1,35 @@
+-- Create chat_channels table
+CREATE TABLE IF NOT EXISTS chat_channels (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+name TEXT NOT NULL UNIQUE,
+webhook_url TEXT NOT NULL,
+secret TEXT,
+created_at TEXT NOT NULL
+) ;
+
+-- Create lead_contacts table, a lead's handles on chat channels
+CREATE TABLE IF NOT EXISTS lead_contacts (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+lead_id INTEGER NOT NULL REFERENCES leads (id),
+chat_channel_id INTEGER NOT NULL REFERENCES chat_channels (id),
+handle TEXT NOT NULL,
+created_at TEXT NOT NULL,
+UNIQUE (chat_channel_id, handle)
+) ;
+
+-- Add the contact a chat message goes to
+ALTER TABLE messages ADD COLUMN contact_id INTEGER REFERENCES lead_contacts (id);
+
+-- Create chat_messages table
+CREATE TABLE IF NOT EXISTS chat_messages (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+message_id INTEGER REFERENCES messages (id),
+contact_id INTEGER NOT NULL REFERENCES lead_contacts (id),
+direction TEXT NOT NULL,
+kind TEXT,
+body TEXT NOT NULL,
+external_id TEXT,
+created_at TEXT NOT NULL
+) ;
+
+CREATE INDEX IF NOT EXISTS idx_chat_messages_contact ON chat_messages (contact_id, direction);
@@ -0,0 +1,3 @@
+-- Add rich text bodies to messages table
+ALTER TABLE messages ADD COLUMN body_format TEXT NOT NULL DEFAULT 'text';
+ALTER TABLE messages ADD COLUMN message_html TEXT;
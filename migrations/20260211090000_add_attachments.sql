@@ -0,0 +1,16 @@
+-- Create attachments table, files are stored on disk by content hash
+CREATE TABLE IF NOT EXISTS attachments (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+sha256 TEXT NOT NULL,
+filename TEXT NOT NULL,
+content_type TEXT NOT NULL,
+size INTEGER NOT NULL,
+created_at TEXT NOT NULL
+) ;
+
+-- Create message_attachments table
+CREATE TABLE IF NOT EXISTS message_attachments (
+message_id INTEGER NOT NULL REFERENCES messages (id),
+attachment_id INTEGER NOT NULL REFERENCES attachments (id),
+PRIMARY KEY (message_id, attachment_id)
+) ;
@@ -0,0 +1,8 @@
+-- Add tracking to messages table
+ALTER TABLE messages ADD COLUMN tracking INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE messages ADD COLUMN open_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE messages ADD COLUMN click_count INTEGER NOT NULL DEFAULT 0;
+
+-- Add event details to outreach_log table
+ALTER TABLE outreach_log ADD COLUMN user_agent TEXT;
+ALTER TABLE outreach_log ADD COLUMN url TEXT;
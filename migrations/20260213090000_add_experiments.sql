@@ -0,0 +1,25 @@
+-- Create experiments table
+CREATE TABLE IF NOT EXISTS experiments (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+name TEXT NOT NULL,
+auto_promote_after INTEGER,
+winner_variant_id INTEGER,
+promoted_at TEXT,
+created_at TEXT NOT NULL
+) ;
+
+-- Create experiment_variants table
+CREATE TABLE IF NOT EXISTS experiment_variants (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+experiment_id INTEGER NOT NULL REFERENCES experiments (id),
+name TEXT NOT NULL,
+subject TEXT,
+message TEXT,
+follow_up TEXT,
+weight INTEGER NOT NULL DEFAULT 1,
+created_at TEXT NOT NULL
+) ;
+
+-- Add subject and variant to messages table
+ALTER TABLE messages ADD COLUMN subject TEXT;
+ALTER TABLE messages ADD COLUMN variant_id INTEGER REFERENCES experiment_variants (id);
@@ -0,0 +1,26 @@
+-- Create campaigns table, the sequence (channel, subject, message, follow-up) is stored as JSON
+CREATE TABLE IF NOT EXISTS campaigns (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+name TEXT NOT NULL,
+workspace_id INTEGER NOT NULL DEFAULT 1 REFERENCES workspaces (id),
+status TEXT NOT NULL DEFAULT 'draft',
+sender_pool_id INTEGER REFERENCES sender_pools (id),
+sequence TEXT NOT NULL,
+created_at TEXT NOT NULL,
+started_at TEXT,
+paused_at TEXT,
+archived_at TEXT
+) ;
+
+-- Create campaign_leads table, the campaign's lead list
+CREATE TABLE IF NOT EXISTS campaign_leads (
+campaign_id INTEGER NOT NULL REFERENCES campaigns (id),
+lead_id INTEGER NOT NULL REFERENCES leads (id),
+message_id INTEGER REFERENCES messages (id),
+skipped_reason TEXT,
+added_at TEXT NOT NULL,
+PRIMARY KEY (campaign_id, lead_id)
+) ;
+
+-- Add campaign to messages table
+ALTER TABLE messages ADD COLUMN campaign_id INTEGER REFERENCES campaigns (id);
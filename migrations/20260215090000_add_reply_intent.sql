@@ -0,0 +1,6 @@
+-- Add reply intent to messages table
+ALTER TABLE messages ADD COLUMN reply_intent TEXT;
+ALTER TABLE messages ADD COLUMN reply_intent_source TEXT;
+
+-- Follow-ups and closing wait until this time, set by out-of-office replies
+ALTER TABLE messages ADD COLUMN snoozed_until TEXT;
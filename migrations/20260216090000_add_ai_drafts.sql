@@ -0,0 +1,13 @@
+-- Create ai_drafts table, every generated draft with the prompt and model that produced it
+CREATE TABLE IF NOT EXISTS ai_drafts (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+lead_id INTEGER NOT NULL REFERENCES leads (id),
+channel TEXT NOT NULL DEFAULT 'email',
+subject TEXT,
+body TEXT NOT NULL,
+system_prompt TEXT NOT NULL,
+prompt TEXT NOT NULL,
+model TEXT NOT NULL,
+message_id INTEGER REFERENCES messages (id),
+created_at TEXT NOT NULL
+) ;
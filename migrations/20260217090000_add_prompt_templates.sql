@@ -0,0 +1,16 @@
+-- Create prompt_templates table, every row is a version of the AI reply prompt of a workspace or campaign
+CREATE TABLE IF NOT EXISTS prompt_templates (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+workspace_id INTEGER NOT NULL DEFAULT 1 REFERENCES workspaces (id),
+campaign_id INTEGER REFERENCES campaigns (id),
+version INTEGER NOT NULL,
+system_prompt TEXT NOT NULL,
+persona TEXT,
+tone TEXT,
+forbidden_topics TEXT NOT NULL DEFAULT '[]',
+max_length INTEGER,
+created_at TEXT NOT NULL
+) ;
+
+-- Add the prompt template version AI replies were generated with to messages table
+ALTER TABLE messages ADD COLUMN prompt_template_id INTEGER REFERENCES prompt_templates (id);
@@ -0,0 +1,14 @@
+-- Add the policy AI replies are checked against to workspaces table
+ALTER TABLE workspaces ADD COLUMN ai_policy TEXT NOT NULL DEFAULT '{}';
+
+-- Create ai_reviews table, AI replies held for a human because they broke the workspace's policy
+CREATE TABLE IF NOT EXISTS ai_reviews (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+message_id INTEGER NOT NULL REFERENCES messages (id),
+ai_reply TEXT NOT NULL,
+violations TEXT NOT NULL,
+confidence REAL,
+status TEXT NOT NULL DEFAULT 'pending',
+created_at TEXT NOT NULL,
+reviewed_at TEXT
+) ;
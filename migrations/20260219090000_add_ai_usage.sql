@@ -0,0 +1,17 @@
+-- Create ai_usage table, every call made to the AI provider with its tokens, latency and cost
+CREATE TABLE IF NOT EXISTS ai_usage (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+workspace_id INTEGER NOT NULL DEFAULT 1 REFERENCES workspaces (id),
+purpose TEXT NOT NULL,
+model TEXT NOT NULL,
+message_id INTEGER REFERENCES messages (id),
+prompt_tokens INTEGER NOT NULL DEFAULT 0,
+completion_tokens INTEGER NOT NULL DEFAULT 0,
+latency_ms INTEGER NOT NULL,
+cost REAL NOT NULL DEFAULT 0,
+error TEXT,
+created_at TEXT NOT NULL
+) ;
+
+-- Add the monthly AI budget to workspaces table, in the currency of the price table
+ALTER TABLE workspaces ADD COLUMN ai_monthly_budget REAL;
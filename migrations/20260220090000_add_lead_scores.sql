@@ -0,0 +1,9 @@
+-- Add the score computed from the workspace's scoring rules to leads table
+ALTER TABLE leads ADD COLUMN score INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE leads ADD COLUMN score_updated_at TEXT;
+
+-- Add the rules lead scores are computed with to workspaces table
+ALTER TABLE workspaces ADD COLUMN lead_scoring TEXT NOT NULL DEFAULT '{}';
+
+-- Index leads by score for sorting and filtering
+CREATE INDEX IF NOT EXISTS idx_leads_workspace_score ON leads (workspace_id, score);
@@ -0,0 +1,22 @@
+-- Create custom_fields table, the lead fields a workspace defines beyond name, email and phone
+CREATE TABLE IF NOT EXISTS custom_fields (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+workspace_id INTEGER NOT NULL REFERENCES workspaces (id),
+key TEXT NOT NULL,
+label TEXT NOT NULL,
+field_type TEXT NOT NULL,
+required INTEGER NOT NULL DEFAULT 0,
+options TEXT NOT NULL DEFAULT '[]',
+created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+UNIQUE (workspace_id, key)
+) ;
+
+-- The default workspace starts with the common profile fields
+INSERT INTO custom_fields (workspace_id, key, label, field_type) VALUES
+(1, 'company', 'Company', 'text'),
+(1, 'title', 'Title', 'text'),
+(1, 'linkedin_url', 'LinkedIn URL', 'url'),
+(1, 'industry', 'Industry', 'text');
+
+-- Add custom field values to leads table, a JSON object by field key
+ALTER TABLE leads ADD COLUMN fields TEXT NOT NULL DEFAULT '{}';
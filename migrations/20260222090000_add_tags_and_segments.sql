@@ -0,0 +1,19 @@
+-- Create lead_tags table, free-form lowercase labels on leads
+CREATE TABLE IF NOT EXISTS lead_tags (
+lead_id INTEGER NOT NULL REFERENCES leads (id),
+tag TEXT NOT NULL,
+created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+PRIMARY KEY (lead_id, tag)
+) ;
+
+CREATE INDEX IF NOT EXISTS idx_lead_tags_tag ON lead_tags (tag);
+
+-- Create segments table, saved lead filters evaluated each time they are used
+CREATE TABLE IF NOT EXISTS segments (
+id INTEGER PRIMARY KEY AUTOINCREMENT,
+workspace_id INTEGER NOT NULL REFERENCES workspaces (id),
+name TEXT NOT NULL,
+filter TEXT NOT NULL,
+created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+UNIQUE (workspace_id, name)
+) ;
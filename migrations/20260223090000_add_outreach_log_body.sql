@@ -0,0 +1,2 @@
+-- Add the text of auto-replies to outreach_log table
+ALTER TABLE outreach_log ADD COLUMN body TEXT;
@@ -0,0 +1,2 @@
+-- Add attachments to experiment_variants table, a JSON array of attachment ids
+ALTER TABLE experiment_variants ADD COLUMN attachment_ids TEXT;
@@ -0,0 +1,2 @@
+-- Add settle_days to experiments table, how old a send must be to count towards auto-promotion
+ALTER TABLE experiments ADD COLUMN settle_days INTEGER NOT NULL DEFAULT 3;
@@ -0,0 +1,365 @@
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::config::AiSettings;
+
+pub type AiResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// A provider's answer with the tokens it was billed for.
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub text: String,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+}
+
+/// A language model that can answer a prompt.
+#[async_trait]
+pub trait AiProvider: Send + Sync + std::fmt::Debug {
+    /// Answers `prompt` following the `system` instructions.
+    async fn complete(&self, system: &str, prompt: &str) -> AiResult<Completion>;
+
+    /// Name of the model answering, recorded with generated content.
+    fn model(&self) -> &str;
+}
+
+/// OpenAI's chat completions API. `api_url` can point at any compatible
+/// endpoint or a local stub.
+#[derive(Debug)]
+pub struct OpenAiProvider {
+    client: reqwest::Client,
+    api_url: String,
+    api_key: String,
+    model: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: [ChatMessage<'a>; 2],
+    temperature: f32,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+    usage: Option<ChatUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatUsage {
+    prompt_tokens: i64,
+    completion_tokens: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChoice {
+    message: ChatReply,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatReply {
+    content: Option<String>,
+}
+
+#[async_trait]
+impl AiProvider for OpenAiProvider {
+    async fn complete(&self, system: &str, prompt: &str) -> AiResult<Completion> {
+        let request = ChatRequest {
+            model: &self.model,
+            messages: [
+                ChatMessage {
+                    role: "system",
+                    content: system,
+                },
+                ChatMessage {
+                    role: "user",
+                    content: prompt,
+                },
+            ],
+            temperature: 0.0,
+        };
+
+        let response = self
+            .client
+            .post(format!("{}/chat/completions", self.api_url))
+            .bearer_auth(&self.api_key)
+            .json(&request)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(format!("AI provider returned {}: {}", status, text).into());
+        }
+
+        let response: ChatResponse = response.json().await?;
+        let text = response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or("AI provider returned no answer")?;
+
+        let (prompt_tokens, completion_tokens) = response.usage.map_or((0, 0), |usage| {
+            (usage.prompt_tokens, usage.completion_tokens)
+        });
+        Ok(Completion {
+            text,
+            prompt_tokens,
+            completion_tokens,
+        })
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+/// Answer of the mock provider when no fixture rule matches and the fixtures
+/// set no default.
+const DEFAULT_MOCK_ANSWER: &str =
+    "Thanks for getting back to me. Would a short call this week work for you?";
+
+/// Canned answers of the mock provider, read from a JSON file.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MockFixtures {
+    #[serde(default)]
+    pub rules: Vec<MockRule>,
+    /// Answer when no rule matches.
+    pub default: Option<String>,
+}
+
+/// Answers prompts containing any of the keywords (case-insensitive), or
+/// every prompt when there are none. `system` restricts the rule to calls
+/// whose system prompt contains it, e.g. `classify` for intent
+/// classification.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MockRule {
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    pub system: Option<String>,
+    #[serde(default)]
+    pub response: String,
+    /// Fails the call instead of answering.
+    #[serde(default)]
+    pub fail: bool,
+    /// Replaces the provider's latency for this rule.
+    pub latency_ms: Option<u64>,
+}
+
+impl MockFixtures {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&json).map_err(|e| e.to_string())
+    }
+
+    /// The first rule matching the call.
+    fn rule(&self, system: &str, prompt: &str) -> Option<&MockRule> {
+        let system = system.to_lowercase();
+        let prompt = prompt.to_lowercase();
+
+        self.rules.iter().find(|rule| {
+            rule.system
+                .as_deref()
+                .is_none_or(|part| system.contains(&part.to_lowercase()))
+                && (rule.keywords.is_empty()
+                    || rule
+                        .keywords
+                        .iter()
+                        .any(|keyword| prompt.contains(&keyword.to_lowercase())))
+        })
+    }
+}
+
+/// Answers from fixture rules without any network, so `/ai/reply` and the
+/// other AI features are reproducible in tests and demos.
+#[derive(Debug)]
+pub struct MockProvider {
+    model: String,
+    fixtures: MockFixtures,
+    latency_ms: u64,
+    fail_every: Option<u64>,
+    /// Calls made to this provider, counted to fail every nth.
+    calls: AtomicU64,
+}
+
+#[async_trait]
+impl AiProvider for MockProvider {
+    async fn complete(&self, system: &str, prompt: &str) -> AiResult<Completion> {
+        let call = self.calls.fetch_add(1, Ordering::Relaxed) + 1;
+        let rule = self.fixtures.rule(system, prompt);
+
+        let latency_ms = rule
+            .and_then(|rule| rule.latency_ms)
+            .unwrap_or(self.latency_ms);
+        if latency_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(latency_ms)).await;
+        }
+
+        if rule.is_some_and(|rule| rule.fail) {
+            return Err("Mock AI provider failed on a fixture rule".into());
+        }
+        if self
+            .fail_every
+            .is_some_and(|every| call.is_multiple_of(every))
+        {
+            return Err(format!("Mock AI provider failed on call {}", call).into());
+        }
+
+        let text = match rule {
+            Some(rule) => rule.response.clone(),
+            None => self
+                .fixtures
+                .default
+                .clone()
+                .unwrap_or_else(|| DEFAULT_MOCK_ANSWER.to_string()),
+        };
+
+        // Words stand in for tokens so usage is recorded deterministically.
+        let words = |text: &str| text.split_whitespace().count() as i64;
+        Ok(Completion {
+            prompt_tokens: words(system) + words(prompt),
+            completion_tokens: words(&text),
+            text,
+        })
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+/// The configured provider, or `None` when AI is disabled. It is built once
+/// at startup and shared, so the mock's call count spans every request.
+pub fn provider(settings: &AiSettings) -> Option<Arc<dyn AiProvider>> {
+    match settings {
+        AiSettings::Disabled => None,
+        AiSettings::OpenAi {
+            api_url,
+            api_key,
+            model,
+        } => Some(Arc::new(OpenAiProvider {
+            client: reqwest::Client::new(),
+            api_url: api_url.clone(),
+            api_key: api_key.clone(),
+            model: model.clone(),
+        })),
+        AiSettings::Mock {
+            model,
+            fixtures,
+            latency_ms,
+            fail_every,
+        } => Some(Arc::new(MockProvider {
+            model: model.clone(),
+            fixtures: fixtures.clone(),
+            latency_ms: *latency_ms,
+            fail_every: *fail_every,
+            calls: AtomicU64::new(0),
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn mock(fixtures: &str, latency_ms: u64, fail_every: Option<u64>) -> MockProvider {
+        MockProvider {
+            model: "mock".to_string(),
+            fixtures: serde_json::from_str(fixtures).unwrap(),
+            latency_ms,
+            fail_every,
+            calls: AtomicU64::new(0),
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_from_the_first_matching_rule() {
+        let provider = mock(
+            r#"{
+                "rules": [
+                    {"system": "classify", "keywords": ["price"], "response": "question"},
+                    {"keywords": ["PRICE"], "response": "It is free."}
+                ],
+                "default": "Hello."
+            }"#,
+            0,
+            None,
+        );
+
+        let classified = provider.complete("You classify replies", "What's the price?");
+        assert_eq!(classified.await.unwrap().text, "question");
+        let answered = provider.complete("You write replies", "What's the price?");
+        assert_eq!(answered.await.unwrap().text, "It is free.");
+        let defaulted = provider.complete("You write replies", "Sounds good");
+        assert_eq!(defaulted.await.unwrap().text, "Hello.");
+
+        let completion = provider.complete("Be brief", "Two words").await.unwrap();
+        assert_eq!(
+            (completion.prompt_tokens, completion.completion_tokens),
+            (4, 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_on_fail_rules() {
+        let provider = mock(
+            r#"{"rules": [{"keywords": ["timeout"], "fail": true}]}"#,
+            0,
+            None,
+        );
+
+        assert!(provider.complete("", "this will timeout").await.is_err());
+        assert_eq!(
+            provider.complete("", "hi").await.unwrap().text,
+            DEFAULT_MOCK_ANSWER
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_every_nth_call_of_the_provider() {
+        let provider = mock("{}", 0, Some(3));
+        let other = mock("{}", 0, Some(3));
+
+        let mut failed = Vec::new();
+        for _ in 0..6 {
+            failed.push(provider.complete("", "hi").await.is_err());
+            // Calls to another provider do not shift the count.
+            let _ = other.complete("", "hi").await;
+        }
+        assert_eq!(failed, [false, false, true, false, false, true]);
+    }
+
+    #[tokio::test]
+    async fn waits_for_the_latency() {
+        let provider = mock(
+            r#"{"rules": [{"keywords": ["slow"], "latency_ms": 80, "response": "ok"}]}"#,
+            20,
+            None,
+        );
+
+        let start = Instant::now();
+        provider.complete("", "slow").await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(80));
+
+        let start = Instant::now();
+        provider.complete("", "fast").await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,259 @@
+use sqlx::SqlitePool;
+
+use crate::models::{FunnelQuery, FunnelReport, FunnelStage, MessageStatus};
+
+/// Funnel stages, each with the stage its conversion is measured from.
+/// Follow-ups branch off sent messages that got no reply.
+const STAGES: &[(MessageStatus, Option<MessageStatus>)] = &[
+    (MessageStatus::Enqueued, None),
+    (MessageStatus::Sent, Some(MessageStatus::Enqueued)),
+    (MessageStatus::Replied, Some(MessageStatus::Sent)),
+    (MessageStatus::AiReplied, Some(MessageStatus::Replied)),
+    (MessageStatus::FollowUp, Some(MessageStatus::Sent)),
+    (MessageStatus::Closed, Some(MessageStatus::FollowUp)),
+];
+
+/// Messages matching the filters, bound as `?1` from, `?2` to, `?3`
+/// campaign and `?4` sender.
+const COHORT: &str = r#"
+    SELECT id FROM messages
+    WHERE (?1 IS NULL OR julianday(created_at) >= julianday(?1))
+      AND (?2 IS NULL OR julianday(created_at) < julianday(?2))
+      AND (?3 IS NULL OR campaign_id = ?3)
+      AND (?4 IS NULL OR sender_id = ?4)
+"#;
+
+/// Counts the messages reaching each step of `outreach_log` and the
+/// conversion between steps, plus the median time to reply.
+pub async fn funnel(pool: &SqlitePool, filter: &FunnelQuery) -> Result<FunnelReport, sqlx::Error> {
+    let counts: Vec<(String, i64)> = sqlx::query_as(&format!(
+        r#"
+        SELECT step, COUNT(DISTINCT message_id)
+        FROM outreach_log
+        WHERE message_id IN ({})
+        GROUP BY step
+        "#,
+        COHORT
+    ))
+    .bind(&filter.from)
+    .bind(&filter.to)
+    .bind(filter.campaign_id)
+    .bind(filter.sender_id)
+    .fetch_all(pool)
+    .await?;
+
+    let count_of = |step: MessageStatus| {
+        counts
+            .iter()
+            .find(|(s, _)| s == step.as_str())
+            .map_or(0, |(_, count)| *count)
+    };
+
+    let stages = STAGES
+        .iter()
+        .map(|(step, from)| {
+            let count = count_of(*step);
+            FunnelStage {
+                step: step.as_str().to_string(),
+                count,
+                from_step: from.map(|from| from.as_str().to_string()),
+                conversion_rate: from
+                    .map(count_of)
+                    .filter(|total| *total > 0)
+                    .map(|total| count as f64 / total as f64),
+            }
+        })
+        .collect();
+
+    // SQLite has no MEDIAN: take the middle row, or the average of the two
+    // middle rows for an even count.
+    let median_time_to_reply_seconds: Option<f64> = sqlx::query_scalar(&format!(
+        r#"
+        WITH durations AS (
+            SELECT ROUND((julianday(MIN(r.log_at)) - julianday(MIN(s.log_at))) * 86400.0) AS seconds
+            FROM outreach_log s
+            JOIN outreach_log r ON r.message_id = s.message_id AND r.step = 'replied'
+            WHERE s.step = 'sent' AND s.message_id IN ({})
+            GROUP BY s.message_id
+            HAVING seconds >= 0
+        )
+        SELECT AVG(seconds) FROM (
+            SELECT seconds FROM durations
+            ORDER BY seconds
+            LIMIT 2 - (SELECT COUNT(*) FROM durations) % 2
+            OFFSET (SELECT (COUNT(*) - 1) / 2 FROM durations)
+        )
+        "#,
+        COHORT
+    ))
+    .bind(&filter.from)
+    .bind(&filter.to)
+    .bind(filter.campaign_id)
+    .bind(filter.sender_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(FunnelReport {
+        stages,
+        median_time_to_reply_seconds,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, Utc};
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    async fn pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        for statement in [
+            "INSERT INTO leads (id, name) VALUES (1, 'Ada')",
+            "INSERT INTO sender_identities (id, name, address) VALUES (1, 'Jane', 'jane@example.com')",
+            "INSERT INTO campaigns (id, name, sequence, created_at) VALUES (1, 'Spring', '{}', '2026-03-01')",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        pool
+    }
+
+    fn at(day: u32) -> DateTime<Utc> {
+        format!("2026-03-{:02}T09:00:00Z", day).parse().unwrap()
+    }
+
+    /// A message enqueued on `created`, with its `(step, seconds after
+    /// creation)` log.
+    async fn message(
+        pool: &SqlitePool,
+        created: DateTime<Utc>,
+        campaign_id: Option<i64>,
+        sender_id: Option<i64>,
+        steps: &[(&str, i64)],
+    ) {
+        let message_id: i64 = sqlx::query_scalar(
+            "INSERT INTO messages (leads_id, status, created_at, campaign_id, sender_id) VALUES (1, 'enqueued', ?, ?, ?) RETURNING id",
+        )
+        .bind(created.to_rfc3339())
+        .bind(campaign_id)
+        .bind(sender_id)
+        .fetch_one(pool)
+        .await
+        .unwrap();
+
+        for (step, seconds) in steps {
+            sqlx::query("INSERT INTO outreach_log (message_id, log_at, step) VALUES (?, ?, ?)")
+                .bind(message_id)
+                .bind((created + Duration::seconds(*seconds)).to_rfc3339())
+                .bind(step)
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+    }
+
+    /// A message sent at creation that got a reply after `seconds`.
+    async fn replied(pool: &SqlitePool, created: DateTime<Utc>, seconds: i64) {
+        let steps = [("enqueued", 0), ("sent", 0), ("replied", seconds)];
+        message(pool, created, None, None, &steps).await;
+    }
+
+    fn stage<'a>(report: &'a FunnelReport, step: &str) -> &'a FunnelStage {
+        report.stages.iter().find(|s| s.step == step).unwrap()
+    }
+
+    #[tokio::test]
+    async fn median_time_to_reply_of_odd_and_even_counts() {
+        let pool = pool().await;
+        let all = FunnelQuery::default();
+        assert_eq!(
+            funnel(&pool, &all)
+                .await
+                .unwrap()
+                .median_time_to_reply_seconds,
+            None
+        );
+
+        replied(&pool, at(1), 600).await;
+        replied(&pool, at(1), 60).await;
+        replied(&pool, at(1), 120).await;
+        let report = funnel(&pool, &all).await.unwrap();
+        assert_eq!(report.median_time_to_reply_seconds, Some(120.0));
+
+        replied(&pool, at(1), 300).await;
+        let report = funnel(&pool, &all).await.unwrap();
+        assert_eq!(report.median_time_to_reply_seconds, Some(210.0));
+    }
+
+    #[tokio::test]
+    async fn converts_from_the_previous_stage() {
+        let pool = pool().await;
+        replied(&pool, at(1), 60).await;
+        message(&pool, at(1), None, None, &[("enqueued", 0), ("sent", 0)]).await;
+        let followed_up = [("enqueued", 0), ("sent", 0), ("follow_up", 86_400)];
+        message(&pool, at(1), None, None, &followed_up).await;
+        message(&pool, at(1), None, None, &[("enqueued", 0)]).await;
+
+        let report = funnel(&pool, &FunnelQuery::default()).await.unwrap();
+        assert_eq!(stage(&report, "enqueued").count, 4);
+        assert_eq!(stage(&report, "enqueued").conversion_rate, None);
+        assert_eq!(stage(&report, "sent").conversion_rate, Some(0.75));
+        assert_eq!(stage(&report, "replied").conversion_rate, Some(1.0 / 3.0));
+        assert_eq!(stage(&report, "ai_replied").conversion_rate, Some(0.0));
+        assert_eq!(stage(&report, "follow_up").count, 1);
+        assert_eq!(stage(&report, "closed").conversion_rate, Some(0.0));
+    }
+
+    #[tokio::test]
+    async fn filters_by_date_campaign_and_sender() {
+        let pool = pool().await;
+        let steps = [("enqueued", 0), ("sent", 0), ("replied", 60)];
+        message(&pool, at(1), Some(1), Some(1), &steps).await;
+        message(&pool, at(2), Some(1), None, &steps).await;
+        message(&pool, at(3), None, None, &steps).await;
+
+        let enqueued = |filter: FunnelQuery| {
+            let pool = pool.clone();
+            async move {
+                let report = funnel(&pool, &filter).await.unwrap();
+                stage(&report, "enqueued").count
+            }
+        };
+
+        let from_the_2nd = FunnelQuery {
+            from: Some("2026-03-02".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(enqueued(from_the_2nd).await, 2);
+
+        let before_the_3rd = FunnelQuery {
+            to: Some("2026-03-03".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(enqueued(before_the_3rd).await, 2);
+
+        let only_the_2nd = FunnelQuery {
+            from: Some("2026-03-02T00:00:00Z".to_string()),
+            to: Some("2026-03-03T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(enqueued(only_the_2nd).await, 1);
+
+        let campaign = FunnelQuery {
+            campaign_id: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(enqueued(campaign).await, 2);
+
+        let sender = FunnelQuery {
+            sender_id: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(enqueued(sender).await, 1);
+    }
+}
@@ -0,0 +1,242 @@
+use std::path::PathBuf;
+
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use sqlx::{SqliteConnection, SqlitePool};
+use tracing::info;
+
+use crate::config::Config;
+use crate::email::EmailAttachment;
+use crate::models::{Attachment, MessageAttachment};
+
+/// Content types accepted for upload, with the magic bytes the file must
+/// start with when the format has them.
+const ALLOWED_TYPES: &[(&str, Option<&[u8]>)] = &[
+    ("application/pdf", Some(b"%PDF-")),
+    ("image/png", Some(b"\x89PNG\r\n\x1a\n")),
+    ("image/jpeg", Some(b"\xff\xd8\xff")),
+    ("image/gif", Some(b"GIF8")),
+    ("text/plain", None),
+    ("text/csv", None),
+    (
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        Some(b"PK\x03\x04"),
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        Some(b"PK\x03\x04"),
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        Some(b"PK\x03\x04"),
+    ),
+];
+
+#[derive(Debug)]
+pub enum AttachmentError {
+    Empty,
+    TooLarge(usize),
+    UnsupportedType(String),
+    ContentMismatch(String),
+    Io(std::io::Error),
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for AttachmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttachmentError::Empty => write!(f, "Attachment is empty"),
+            AttachmentError::TooLarge(max) => {
+                write!(f, "Attachment is larger than {} bytes", max)
+            }
+            AttachmentError::UnsupportedType(content_type) => {
+                write!(f, "Attachments of type {} are not allowed", content_type)
+            }
+            AttachmentError::ContentMismatch(content_type) => {
+                write!(f, "Attachment content is not {}", content_type)
+            }
+            AttachmentError::Io(e) => write!(f, "{}", e),
+            AttachmentError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for AttachmentError {
+    fn from(e: std::io::Error) -> Self {
+        AttachmentError::Io(e)
+    }
+}
+
+impl From<sqlx::Error> for AttachmentError {
+    fn from(e: sqlx::Error) -> Self {
+        AttachmentError::Database(e)
+    }
+}
+
+/// Checks an upload against the size and type limits, writes it under its
+/// SHA-256 (identical files are stored once) and records it.
+pub async fn store(
+    pool: &SqlitePool,
+    config: &Config,
+    filename: &str,
+    content_type: &str,
+    data: &[u8],
+) -> Result<Attachment, AttachmentError> {
+    if data.is_empty() {
+        return Err(AttachmentError::Empty);
+    }
+    if data.len() > config.max_attachment_bytes {
+        return Err(AttachmentError::TooLarge(config.max_attachment_bytes));
+    }
+
+    let content_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let (_, magic) = ALLOWED_TYPES
+        .iter()
+        .find(|(allowed, _)| *allowed == content_type)
+        .ok_or_else(|| AttachmentError::UnsupportedType(content_type.clone()))?;
+    if let Some(magic) = magic
+        && !data.starts_with(magic)
+    {
+        return Err(AttachmentError::ContentMismatch(content_type));
+    }
+
+    let sha256 = hex::encode(Sha256::digest(data));
+    let path = path_for(config, &sha256);
+
+    if !tokio::fs::try_exists(&path).await? {
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        // Write then rename so a crash never leaves a partial file under the hash.
+        let partial = path.with_extension("partial");
+        tokio::fs::write(&partial, data).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        info!("Stored attachment {} ({} bytes)", sha256, data.len());
+    }
+
+    let attachment = sqlx::query_as::<_, Attachment>(
+        r#"
+        INSERT INTO attachments (sha256, filename, content_type, size, created_at)
+        VALUES (?, ?, ?, ?, ?)
+        RETURNING id, sha256, filename, content_type, size, created_at
+        "#,
+    )
+    .bind(&sha256)
+    .bind(clean_filename(filename))
+    .bind(&content_type)
+    .bind(data.len() as i64)
+    .bind(Utc::now().to_rfc3339())
+    .fetch_one(pool)
+    .await?;
+
+    Ok(attachment)
+}
+
+pub async fn fetch(
+    pool: &SqlitePool,
+    attachment_id: i64,
+) -> Result<Option<Attachment>, sqlx::Error> {
+    sqlx::query_as::<_, Attachment>(
+        "SELECT id, sha256, filename, content_type, size, created_at FROM attachments WHERE id = ?",
+    )
+    .bind(attachment_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Links the attachments to a message, on the caller's transaction so the
+/// message is never left with only some of its files.
+pub async fn attach(
+    conn: &mut SqliteConnection,
+    message_id: i64,
+    attachment_ids: &[i64],
+) -> Result<(), sqlx::Error> {
+    for attachment_id in attachment_ids {
+        sqlx::query(
+            "INSERT OR IGNORE INTO message_attachments (message_id, attachment_id) VALUES (?, ?)",
+        )
+        .bind(message_id)
+        .bind(attachment_id)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Attachments of every message of a lead.
+pub async fn lead_attachments(
+    pool: &SqlitePool,
+    lead_id: i64,
+) -> Result<Vec<MessageAttachment>, sqlx::Error> {
+    sqlx::query_as::<_, MessageAttachment>(
+        r#"
+        SELECT ma.message_id, a.id, a.sha256, a.filename, a.content_type, a.size, a.created_at
+        FROM message_attachments ma
+        JOIN attachments a ON a.id = ma.attachment_id
+        JOIN messages m ON m.id = ma.message_id
+        WHERE m.leads_id = ?
+        ORDER BY ma.message_id DESC, a.id ASC
+        "#,
+    )
+    .bind(lead_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Reads a message's attachments from disk, ready to be added to an email.
+pub async fn load_for_message(
+    pool: &SqlitePool,
+    config: &Config,
+    message_id: i64,
+) -> Result<Vec<EmailAttachment>, Box<dyn std::error::Error + Send + Sync>> {
+    let attachments = sqlx::query_as::<_, Attachment>(
+        r#"
+        SELECT a.id, a.sha256, a.filename, a.content_type, a.size, a.created_at
+        FROM message_attachments ma
+        JOIN attachments a ON a.id = ma.attachment_id
+        WHERE ma.message_id = ?
+        ORDER BY a.id ASC
+        "#,
+    )
+    .bind(message_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut loaded = Vec::with_capacity(attachments.len());
+    for attachment in attachments {
+        let data = tokio::fs::read(path_for(config, &attachment.sha256))
+            .await
+            .map_err(|e| format!("attachment {}: {}", attachment.id, e))?;
+        loaded.push(EmailAttachment {
+            filename: attachment.filename,
+            content_type: attachment.content_type,
+            data,
+        });
+    }
+
+    Ok(loaded)
+}
+
+fn path_for(config: &Config, sha256: &str) -> PathBuf {
+    config.attachments_dir.join(&sha256[..2]).join(sha256)
+}
+
+fn clean_filename(filename: &str) -> String {
+    let name = filename
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .replace(['"', '\r', '\n'], "");
+    let name = name.trim();
+    if name.is_empty() {
+        "attachment".to_string()
+    } else {
+        name.to_string()
+    }
+}
@@ -0,0 +1,245 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc, Weekday};
+
+/// How long follow-ups wait after an auto-reply without a return date.
+pub const DEFAULT_SNOOZE_DAYS: i64 = 7;
+
+/// Return dates further out than this are ignored as misparsed.
+const MAX_SNOOZE_DAYS: i64 = 90;
+
+/// Phrases that mark a text as an out-of-office notice on their own. Ones a
+/// lead could write in a real reply ("I'll be back to you", "on leave
+/// tomorrow") are left out: replies detected this way are not stored as
+/// replies.
+const OUT_OF_OFFICE_PHRASES: &[&str] = &[
+    "out of office",
+    "out of the office",
+    "automatic reply",
+    "auto-reply",
+    "autoreply",
+    "on vacation",
+    "on holiday",
+    "parental leave",
+    "limited access to email",
+];
+
+/// Words announcing the date the sender is back.
+const RETURN_WORDS: &[&str] = &["back", "return", "returning", "until", "till", "resume"];
+
+const MONTHS: &[(&str, u32)] = &[
+    ("january", 1),
+    ("jan", 1),
+    ("february", 2),
+    ("feb", 2),
+    ("march", 3),
+    ("mar", 3),
+    ("april", 4),
+    ("apr", 4),
+    ("may", 5),
+    ("june", 6),
+    ("jun", 6),
+    ("july", 7),
+    ("jul", 7),
+    ("august", 8),
+    ("aug", 8),
+    ("september", 9),
+    ("sept", 9),
+    ("sep", 9),
+    ("october", 10),
+    ("oct", 10),
+    ("november", 11),
+    ("nov", 11),
+    ("december", 12),
+    ("dec", 12),
+];
+
+const WEEKDAYS: &[(&str, Weekday)] = &[
+    ("monday", Weekday::Mon),
+    ("tuesday", Weekday::Tue),
+    ("wednesday", Weekday::Wed),
+    ("thursday", Weekday::Thu),
+    ("friday", Weekday::Fri),
+    ("saturday", Weekday::Sat),
+    ("sunday", Weekday::Sun),
+];
+
+/// Whether the headers mark the email as sent by an auto-responder
+/// (RFC 3834 `Auto-Submitted`, or the older `X-Autoreply` family).
+pub fn has_auto_reply_headers(parsed: &mail_parser::Message<'_>) -> bool {
+    let header = |name: &str| parsed.header_raw(name).map(|v| v.trim().to_lowercase());
+
+    header("Auto-Submitted").is_some_and(|v| v != "no")
+        || header("X-Autoreply").is_some()
+        || header("X-Autorespond").is_some()
+        || header("Precedence").is_some_and(|v| v == "auto_reply")
+}
+
+pub fn looks_like_out_of_office(text: &str) -> bool {
+    let text = text.to_lowercase();
+    OUT_OF_OFFICE_PHRASES
+        .iter()
+        .any(|phrase| text.contains(phrase))
+}
+
+/// When follow-ups may resume after an auto-reply: the day after the return
+/// date found in the text, or a week from now when there is none.
+pub fn snooze_until(text: &str, now: DateTime<Utc>) -> DateTime<Utc> {
+    match return_date(text, now.date_naive()) {
+        Some(date) => (date + Duration::days(1))
+            .and_hms_opt(0, 0, 0)
+            .map(|midnight| midnight.and_utc())
+            .unwrap_or(now + Duration::days(DEFAULT_SNOOZE_DAYS)),
+        None => now + Duration::days(DEFAULT_SNOOZE_DAYS),
+    }
+}
+
+/// Finds the date the sender says they are back. Understands ISO dates,
+/// `March 3`, `3rd of March` (optionally with a year), weekday names and
+/// `tomorrow`. Dates right after a word like "back" or "until" win,
+/// otherwise the latest date mentioned is used.
+pub fn return_date(text: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let text = text.to_lowercase();
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_alphanumeric() && c != '-')
+        .map(|w| w.trim_matches('-'))
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    let mut dates: Vec<(usize, NaiveDate)> = Vec::new();
+    for (i, word) in words.iter().enumerate() {
+        if let Some(date) = date_at(&words, i, today) {
+            dates.push((i, date));
+        } else if let Ok(date) = NaiveDate::parse_from_str(word, "%Y-%m-%d") {
+            dates.push((i, date));
+        }
+    }
+
+    dates.retain(|(_, date)| *date > today && *date <= today + Duration::days(MAX_SNOOZE_DAYS));
+
+    let announced = dates.iter().find(|(i, _)| {
+        words[i.saturating_sub(4)..*i]
+            .iter()
+            .any(|w| RETURN_WORDS.contains(w))
+    });
+
+    announced
+        .or_else(|| dates.iter().max_by_key(|(_, date)| *date))
+        .map(|(_, date)| *date)
+}
+
+/// The date starting at `words[i]` when it is a month or weekday name.
+fn date_at(words: &[&str], i: usize, today: NaiveDate) -> Option<NaiveDate> {
+    let word = words[i];
+
+    if word == "tomorrow" {
+        return today.succ_opt();
+    }
+
+    if let Some((_, weekday)) = WEEKDAYS.iter().find(|(name, _)| *name == word) {
+        let ahead = (7 + weekday.num_days_from_monday() as i64
+            - today.weekday().num_days_from_monday() as i64
+            - 1)
+            % 7
+            + 1;
+        return Some(today + Duration::days(ahead));
+    }
+
+    let (_, month) = MONTHS.iter().find(|(name, _)| *name == word)?;
+    let next = words.get(i + 1).copied();
+
+    // "march 3", "march 3rd 2027"
+    if let Some(day) = next.and_then(day_number) {
+        return dated(*month, day, words.get(i + 2).copied(), today);
+    }
+
+    // "3 march", "3rd of march 2027"
+    let before = match i.checked_sub(1).map(|j| words[j]) {
+        Some("of") => i.checked_sub(2).map(|j| words[j]),
+        other => other,
+    };
+    let day = before.and_then(day_number)?;
+    dated(*month, day, next, today)
+}
+
+/// Builds the date, taking the year from `year` or else the first one that
+/// puts the date after today.
+fn dated(month: u32, day: u32, year: Option<&str>, today: NaiveDate) -> Option<NaiveDate> {
+    if let Some(year) = year.filter(|y| y.len() == 4).and_then(|y| y.parse().ok()) {
+        return NaiveDate::from_ymd_opt(year, month, day);
+    }
+
+    NaiveDate::from_ymd_opt(today.year(), month, day)
+        .filter(|date| *date > today)
+        .or_else(|| NaiveDate::from_ymd_opt(today.year() + 1, month, day))
+}
+
+/// `3`, `03` or `3rd`.
+fn day_number(word: &str) -> Option<u32> {
+    let digits = word.trim_end_matches(['s', 't', 'n', 'd', 'r', 'h']);
+    if digits.is_empty() || digits.len() > 2 {
+        return None;
+    }
+    digits.parse().ok().filter(|day| (1..=31).contains(day))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_only_unambiguous_notices() {
+        assert!(looks_like_out_of_office("Automatic reply: Out of Office"));
+        assert!(looks_like_out_of_office("I'm on vacation without email."));
+        assert!(!looks_like_out_of_office("I'll be back to you Monday."));
+        assert!(!looks_like_out_of_office("I will be back in touch soon."));
+        assert!(!looks_like_out_of_office(
+            "Going on leave next month, let's talk before."
+        ));
+        assert!(!looks_like_out_of_office(
+            "We're away until the 5th, call then?"
+        ));
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn return_dates() {
+        // A Wednesday.
+        let today = date(2026, 3, 4);
+
+        let cases = [
+            ("I'm back on Monday.", Some(date(2026, 3, 9))),
+            ("Back on Wednesday", Some(date(2026, 3, 11))),
+            ("Out of office until March 10th.", Some(date(2026, 3, 10))),
+            ("Returning on the 2nd of April", Some(date(2026, 4, 2))),
+            ("On vacation until 2026-03-20", Some(date(2026, 3, 20))),
+            ("Out today, back tomorrow", Some(date(2026, 3, 5))),
+            (
+                "Out from Mar 5 to Mar 20, back on Mar 12",
+                Some(date(2026, 3, 12)),
+            ),
+            ("Travelling March 6 and March 9", Some(date(2026, 3, 9))),
+            ("Until January 5 2027", None),
+            ("Back on March 1 2026", None),
+            ("Out of office, limited access to email", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(return_date(text, today), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn snoozes_until_the_day_after_the_return() {
+        let now = date(2026, 3, 4).and_hms_opt(15, 30, 0).unwrap().and_utc();
+
+        assert_eq!(
+            snooze_until("Back on Monday", now),
+            date(2026, 3, 10).and_hms_opt(0, 0, 0).unwrap().and_utc()
+        );
+        assert_eq!(
+            snooze_until("Out of office", now),
+            now + Duration::days(DEFAULT_SNOOZE_DAYS)
+        );
+    }
+}
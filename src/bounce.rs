@@ -0,0 +1,395 @@
+use chrono::Utc;
+use mail_parser::{MimeHeaders, PartType};
+use sqlx::SqlitePool;
+use tracing::{info, warn};
+
+use crate::handlers::log_outreach;
+use crate::models::{BounceType, Message, MessageStatus};
+
+/// A delivery failure reported by a DSN (RFC 3464).
+#[derive(Debug, Clone)]
+pub struct Bounce {
+    pub bounce_type: BounceType,
+    pub recipient: Option<String>,
+    pub reason: String,
+    /// Message-IDs of the original email, taken from the returned headers.
+    pub original_message_ids: Vec<String>,
+}
+
+/// Reads a `multipart/report; report-type=delivery-status` email. Returns
+/// `None` for anything else, including DSNs that only report success.
+pub fn parse_dsn(parsed: &mail_parser::Message<'_>) -> Option<Bounce> {
+    let content_type = parsed.content_type()?;
+    let is_report = content_type.ctype().eq_ignore_ascii_case("multipart")
+        && content_type
+            .subtype()
+            .is_some_and(|s| s.eq_ignore_ascii_case("report"));
+    let is_delivery_status = content_type
+        .attribute("report-type")
+        .is_some_and(|t| t.eq_ignore_ascii_case("delivery-status"));
+    if !is_report || !is_delivery_status {
+        return None;
+    }
+
+    let mut status_fields = None;
+    let mut original_message_ids = Vec::new();
+
+    for part in parsed.parts.iter().skip(1) {
+        let Some(part_type) = part.content_type() else {
+            continue;
+        };
+        let subtype = part_type.subtype().unwrap_or_default().to_ascii_lowercase();
+
+        match (
+            part_type.ctype().to_ascii_lowercase().as_str(),
+            subtype.as_str(),
+        ) {
+            ("message", "delivery-status") => {
+                status_fields = part.text_contents().map(parse_status_fields);
+            }
+            ("message", "rfc822") | ("text", "rfc822-headers") => {
+                let headers = match &part.body {
+                    PartType::Message(message) => message.raw_message(),
+                    _ => part.contents(),
+                };
+                original_message_ids.extend(message_id_headers(&String::from_utf8_lossy(headers)));
+            }
+            _ => {}
+        }
+    }
+
+    let fields = status_fields?;
+    let action = fields.action.as_deref().unwrap_or_default();
+    let status = fields.status.as_deref().unwrap_or_default();
+
+    let bounce_type = if action.eq_ignore_ascii_case("failed") || status.starts_with('5') {
+        BounceType::Hard
+    } else if action.eq_ignore_ascii_case("delayed") || status.starts_with('4') {
+        BounceType::Soft
+    } else {
+        return None;
+    };
+
+    let reason = match (&fields.status, &fields.diagnostic) {
+        (Some(status), Some(diagnostic)) => format!("{} {}", status, diagnostic),
+        (Some(status), None) => status.clone(),
+        (None, Some(diagnostic)) => diagnostic.clone(),
+        (None, None) => action.to_string(),
+    };
+
+    Some(Bounce {
+        bounce_type,
+        recipient: fields.recipient,
+        reason,
+        original_message_ids,
+    })
+}
+
+#[derive(Debug, Default)]
+struct StatusFields {
+    recipient: Option<String>,
+    action: Option<String>,
+    status: Option<String>,
+    diagnostic: Option<String>,
+}
+
+/// Takes the first per-recipient block of a `message/delivery-status` body.
+fn parse_status_fields(text: &str) -> StatusFields {
+    let mut fields = StatusFields::default();
+
+    for line in text.lines() {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        // Address fields look like "rfc822; someone@example.com".
+        let value = value
+            .split_once(';')
+            .map_or(value, |(_, v)| v)
+            .trim()
+            .to_string();
+
+        match name.trim().to_ascii_lowercase().as_str() {
+            "final-recipient" | "original-recipient" if fields.recipient.is_none() => {
+                fields.recipient = Some(value.to_lowercase());
+            }
+            "action" if fields.action.is_none() => fields.action = Some(value),
+            "status" if fields.status.is_none() => fields.status = Some(value),
+            "diagnostic-code" if fields.diagnostic.is_none() => fields.diagnostic = Some(value),
+            _ => {}
+        }
+    }
+
+    fields
+}
+
+fn message_id_headers(headers: &str) -> Vec<String> {
+    headers
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(name, _)| name.trim().eq_ignore_ascii_case("message-id"))
+        .map(|(_, value)| format!("<{}>", value.trim().trim_matches(['<', '>'])))
+        .collect()
+}
+
+/// Finds the message an email event refers to: by Message-ID first, then by
+/// the most recent email sent to the given address.
+pub async fn find_message_for_address(
+    pool: &SqlitePool,
+    email_message_ids: &[String],
+    address: Option<&str>,
+) -> Result<Option<i64>, sqlx::Error> {
+    for email_message_id in email_message_ids {
+        let found = sqlx::query_scalar::<_, i64>(
+            "SELECT message_id FROM sent_emails WHERE email_message_id = ? ORDER BY id DESC LIMIT 1",
+        )
+        .bind(email_message_id)
+        .fetch_optional(pool)
+        .await?;
+
+        if found.is_some() {
+            return Ok(found);
+        }
+    }
+
+    let Some(address) = address else {
+        return Ok(None);
+    };
+
+    sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT s.message_id
+        FROM sent_emails s
+        JOIN messages m ON m.id = s.message_id
+        JOIN leads l ON l.id = m.leads_id
+        WHERE LOWER(l.email) = LOWER(?)
+        ORDER BY s.id DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(address)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Hard bounces mark the message as bounced and flag the lead's email as
+/// undeliverable so nothing else is sent to it. Soft bounces only record
+/// their type and reason: the message keeps its status and its follow-ups.
+pub async fn record_bounce(
+    pool: &SqlitePool,
+    message_id: i64,
+    bounce: &Bounce,
+) -> Result<Option<Message>, sqlx::Error> {
+    if bounce.bounce_type == BounceType::Soft {
+        return record_soft_bounce(pool, message_id, bounce).await;
+    }
+
+    let now = Utc::now().to_rfc3339();
+
+    let message = sqlx::query_as::<_, Message>(
+        r#"
+        UPDATE messages
+        SET status = ?, bounced_at = ?, bounce_type = ?, bounce_reason = ?
+        WHERE id = ?
+        RETURNING id, leads_id, message_sent, sent_at, reply_received, reply_received_at, ai_reply, ai_reply_sent, created_at, status, follow_up_at, closed_at, sender_id, scheduled_at, email_message_id, follow_up_body, bounced_at, bounce_type, bounce_reason, channel, contact_id, body_format, message_html, tracking, open_count, click_count, subject, variant_id, campaign_id, reply_intent, reply_intent_source, snoozed_until, prompt_template_id
+        "#,
+    )
+    .bind(MessageStatus::Bounced.as_str())
+    .bind(&now)
+    .bind(bounce.bounce_type.as_str())
+    .bind(&bounce.reason)
+    .bind(message_id)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(message) = message else {
+        return Ok(None);
+    };
+
+    log_outreach(pool, message.id, MessageStatus::Bounced).await;
+    warn!(
+        "Message {} bounced ({}): {}",
+        message.id,
+        bounce.bounce_type.as_str(),
+        bounce.reason
+    );
+
+    flag_undeliverable(
+        pool,
+        message.leads_id,
+        &format!("hard bounce: {}", bounce.reason),
+    )
+    .await?;
+
+    Ok(Some(message))
+}
+
+async fn record_soft_bounce(
+    pool: &SqlitePool,
+    message_id: i64,
+    bounce: &Bounce,
+) -> Result<Option<Message>, sqlx::Error> {
+    // A hard bounce recorded earlier keeps its details.
+    let message = sqlx::query_as::<_, Message>(
+        r#"
+        UPDATE messages
+        SET bounce_type = CASE WHEN bounced_at IS NULL THEN ? ELSE bounce_type END,
+            bounce_reason = CASE WHEN bounced_at IS NULL THEN ? ELSE bounce_reason END
+        WHERE id = ?
+        RETURNING id, leads_id, message_sent, sent_at, reply_received, reply_received_at, ai_reply, ai_reply_sent, created_at, status, follow_up_at, closed_at, sender_id, scheduled_at, email_message_id, follow_up_body, bounced_at, bounce_type, bounce_reason, channel, contact_id, body_format, message_html, tracking, open_count, click_count, subject, variant_id, campaign_id, reply_intent, reply_intent_source, snoozed_until, prompt_template_id
+        "#,
+    )
+    .bind(bounce.bounce_type.as_str())
+    .bind(&bounce.reason)
+    .bind(message_id)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(message) = message else {
+        return Ok(None);
+    };
+
+    log_outreach(pool, message.id, MessageStatus::SoftBounced).await;
+    info!(
+        "Message {} soft bounced, still deliverable: {}",
+        message.id, bounce.reason
+    );
+
+    Ok(Some(message))
+}
+
+/// Marks a message as complained about and stops all email to the lead.
+pub async fn record_complaint(
+    pool: &SqlitePool,
+    message_id: i64,
+    feedback_type: Option<&str>,
+) -> Result<Option<Message>, sqlx::Error> {
+    let message = sqlx::query_as::<_, Message>(
+        r#"
+        UPDATE messages
+        SET status = ?
+        WHERE id = ?
+        RETURNING id, leads_id, message_sent, sent_at, reply_received, reply_received_at, ai_reply, ai_reply_sent, created_at, status, follow_up_at, closed_at, sender_id, scheduled_at, email_message_id, follow_up_body, bounced_at, bounce_type, bounce_reason, channel, contact_id, body_format, message_html, tracking, open_count, click_count, subject, variant_id, campaign_id, reply_intent, reply_intent_source, snoozed_until, prompt_template_id
+        "#,
+    )
+    .bind(MessageStatus::Complained.as_str())
+    .bind(message_id)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(message) = message else {
+        return Ok(None);
+    };
+
+    log_outreach(pool, message.id, MessageStatus::Complained).await;
+    warn!("Complaint received for message {}", message.id);
+
+    let reason = format!("complaint: {}", feedback_type.unwrap_or("abuse"));
+    flag_undeliverable(pool, message.leads_id, &reason).await?;
+
+    Ok(Some(message))
+}
+
+async fn flag_undeliverable(
+    pool: &SqlitePool,
+    lead_id: i64,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE leads
+        SET email_undeliverable_at = ?, email_undeliverable_reason = ?
+        WHERE id = ? AND email_undeliverable_at IS NULL
+        "#,
+    )
+    .bind(Utc::now().to_rfc3339())
+    .bind(reason)
+    .bind(lead_id)
+    .execute(pool)
+    .await?;
+
+    info!(
+        "Lead {} email flagged as undeliverable ({})",
+        lead_id, reason
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use mail_parser::MessageParser;
+
+    use super::*;
+
+    fn dsn(action: &str, status: &str, returned_type: &str) -> String {
+        format!(
+            "From: MAILER-DAEMON@mx.example.net\r\n\
+             To: sales@example.com\r\n\
+             Subject: Delivery Status Notification\r\n\
+             MIME-Version: 1.0\r\n\
+             Content-Type: multipart/report; report-type=delivery-status; boundary=\"b\"\r\n\
+             \r\n\
+             --b\r\n\
+             Content-Type: text/plain\r\n\
+             \r\n\
+             Your message could not be delivered.\r\n\
+             --b\r\n\
+             Content-Type: message/delivery-status\r\n\
+             \r\n\
+             Reporting-MTA: dns; mx.example.net\r\n\
+             \r\n\
+             Final-Recipient: rfc822; Lead@Example.org\r\n\
+             Action: {action}\r\n\
+             Status: {status}\r\n\
+             Diagnostic-Code: smtp; 550 mailbox unavailable\r\n\
+             \r\n\
+             --b\r\n\
+             Content-Type: {returned_type}\r\n\
+             \r\n\
+             From: sales@example.com\r\n\
+             To: lead@example.org\r\n\
+             Message-ID: <abc@example.com>\r\n\
+             Subject: Hello\r\n\
+             \r\n\
+             --b--\r\n"
+        )
+    }
+
+    fn parse(raw: &str) -> Option<Bounce> {
+        parse_dsn(&MessageParser::default().parse(raw.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn hard_bounces() {
+        let bounce = parse(&dsn("failed", "5.1.1", "message/rfc822")).unwrap();
+
+        assert_eq!(bounce.bounce_type, BounceType::Hard);
+        assert_eq!(bounce.recipient.as_deref(), Some("lead@example.org"));
+        assert_eq!(bounce.reason, "5.1.1 550 mailbox unavailable");
+        assert_eq!(bounce.original_message_ids, vec!["<abc@example.com>"]);
+    }
+
+    #[test]
+    fn soft_bounces() {
+        let bounce = parse(&dsn("delayed", "4.2.2", "text/rfc822-headers")).unwrap();
+
+        assert_eq!(bounce.bounce_type, BounceType::Soft);
+        assert_eq!(bounce.original_message_ids, vec!["<abc@example.com>"]);
+    }
+
+    #[test]
+    fn ignores_successes_and_other_emails() {
+        assert!(parse(&dsn("delivered", "2.0.0", "message/rfc822")).is_none());
+        assert!(
+            parse(
+                "From: lead@example.org\r\n\
+                 Subject: Re: Hello\r\n\
+                 Content-Type: text/plain\r\n\
+                 \r\n\
+                 Action: failed\r\n\
+                 Status: 5.1.1\r\n"
+            )
+            .is_none()
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+
+use chrono::Utc;
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::models::{Campaign, CampaignLead, CampaignStatus};
+
+const CAMPAIGN_COLUMNS: &str = "id, name, workspace_id, status, sender_pool_id, sequence, created_at, started_at, paused_at, archived_at";
+
+pub enum Transition {
+    Done(Box<Campaign>),
+    NotFound,
+    /// The campaign's current status does not allow the change.
+    Invalid(String),
+}
+
+pub async fn fetch(pool: &SqlitePool, campaign_id: i64) -> Result<Option<Campaign>, sqlx::Error> {
+    sqlx::query_as::<_, Campaign>(&format!(
+        "SELECT {} FROM campaigns WHERE id = ?",
+        CAMPAIGN_COLUMNS
+    ))
+    .bind(campaign_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn list(pool: &SqlitePool) -> Result<Vec<Campaign>, sqlx::Error> {
+    sqlx::query_as::<_, Campaign>(&format!(
+        "SELECT {} FROM campaigns ORDER BY id ASC",
+        CAMPAIGN_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn leads(pool: &SqlitePool, campaign_id: i64) -> Result<Vec<CampaignLead>, sqlx::Error> {
+    sqlx::query_as::<_, CampaignLead>(
+        r#"
+        SELECT campaign_id, lead_id, message_id, skipped_reason, added_at
+        FROM campaign_leads
+        WHERE campaign_id = ?
+        ORDER BY added_at ASC, lead_id ASC
+        "#,
+    )
+    .bind(campaign_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Leads on the list that have neither a message nor a skip reason yet.
+pub async fn pending_leads(pool: &SqlitePool, campaign_id: i64) -> Result<Vec<i64>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT lead_id FROM campaign_leads
+        WHERE campaign_id = ? AND message_id IS NULL AND skipped_reason IS NULL
+        ORDER BY added_at ASC, lead_id ASC
+        "#,
+    )
+    .bind(campaign_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Puts the leads on the campaign's list, on the caller's transaction so the
+/// list is added whole or not at all.
+pub async fn add_leads(
+    conn: &mut SqliteConnection,
+    campaign_id: i64,
+    lead_ids: &[i64],
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now().to_rfc3339();
+    for lead_id in lead_ids {
+        sqlx::query(
+            "INSERT OR IGNORE INTO campaign_leads (campaign_id, lead_id, added_at) VALUES (?, ?, ?)",
+        )
+        .bind(campaign_id)
+        .bind(lead_id)
+        .bind(&now)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Records the outcome of enqueueing a lead's message.
+pub async fn record_enqueued(
+    pool: &SqlitePool,
+    campaign_id: i64,
+    lead_id: i64,
+    outcome: Result<i64, &str>,
+) -> Result<(), sqlx::Error> {
+    let (message_id, skipped_reason) = match outcome {
+        Ok(message_id) => (Some(message_id), None),
+        Err(reason) => (None, Some(reason)),
+    };
+
+    sqlx::query(
+        "UPDATE campaign_leads SET message_id = ?, skipped_reason = ? WHERE campaign_id = ? AND lead_id = ?",
+    )
+    .bind(message_id)
+    .bind(skipped_reason)
+    .bind(campaign_id)
+    .bind(lead_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Number of the campaign's messages in each status.
+pub async fn message_counts(
+    pool: &SqlitePool,
+    campaign_id: i64,
+) -> Result<BTreeMap<String, i64>, sqlx::Error> {
+    let counts: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT status, COUNT(*) FROM messages WHERE campaign_id = ? GROUP BY status",
+    )
+    .bind(campaign_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(counts.into_iter().collect())
+}
+
+/// Moves a campaign to `to` if its current status allows it: draft and
+/// paused campaigns can be made active, active ones paused, and anything
+/// not yet archived archived.
+pub async fn transition(
+    pool: &SqlitePool,
+    campaign_id: i64,
+    to: CampaignStatus,
+) -> Result<Transition, sqlx::Error> {
+    let Some(campaign) = fetch(pool, campaign_id).await? else {
+        return Ok(Transition::NotFound);
+    };
+    let Ok(from) = campaign.status.parse::<CampaignStatus>() else {
+        return Ok(Transition::Invalid(campaign.status));
+    };
+
+    let now = Utc::now().to_rfc3339();
+    let (column, value) = match (from, to) {
+        (CampaignStatus::Draft, CampaignStatus::Active) => ("started_at", Some(now)),
+        (CampaignStatus::Paused, CampaignStatus::Active) => ("paused_at", None),
+        (CampaignStatus::Active, CampaignStatus::Paused) => ("paused_at", Some(now)),
+        (
+            CampaignStatus::Draft | CampaignStatus::Active | CampaignStatus::Paused,
+            CampaignStatus::Archived,
+        ) => ("archived_at", Some(now)),
+        _ => return Ok(Transition::Invalid(campaign.status)),
+    };
+
+    let updated = sqlx::query_as::<_, Campaign>(&format!(
+        "UPDATE campaigns SET status = ?, {} = ? WHERE id = ? AND status = ? RETURNING {}",
+        column, CAMPAIGN_COLUMNS
+    ))
+    .bind(to.as_str())
+    .bind(value)
+    .bind(campaign_id)
+    .bind(from.as_str())
+    .fetch_optional(pool)
+    .await?;
+
+    // Another request changed the status in between.
+    Ok(match updated {
+        Some(campaign) => Transition::Done(Box::new(campaign)),
+        None => Transition::Invalid(campaign.status),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    const STATUSES: [CampaignStatus; 4] = [
+        CampaignStatus::Draft,
+        CampaignStatus::Active,
+        CampaignStatus::Paused,
+        CampaignStatus::Archived,
+    ];
+
+    async fn pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        pool
+    }
+
+    async fn campaign(pool: &SqlitePool, status: CampaignStatus) -> i64 {
+        sqlx::query_scalar(
+            "INSERT INTO campaigns (name, status, sequence, created_at, paused_at) VALUES ('Spring', ?, '{}', ?, ?) RETURNING id",
+        )
+        .bind(status.as_str())
+        .bind(Utc::now().to_rfc3339())
+        .bind((status == CampaignStatus::Paused).then(|| Utc::now().to_rfc3339()))
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn follows_the_status_table() {
+        use CampaignStatus::*;
+        let allowed = [
+            (Draft, Active),
+            (Draft, Archived),
+            (Active, Paused),
+            (Active, Archived),
+            (Paused, Active),
+            (Paused, Archived),
+        ];
+
+        let pool = pool().await;
+        for from in STATUSES {
+            for to in STATUSES {
+                let id = campaign(&pool, from).await;
+                let outcome = transition(&pool, id, to).await.unwrap();
+                match outcome {
+                    Transition::Done(campaign) => {
+                        assert!(allowed.contains(&(from, to)), "{:?} -> {:?}", from, to);
+                        assert_eq!(campaign.status, to.as_str());
+                    }
+                    Transition::Invalid(status) => {
+                        assert!(!allowed.contains(&(from, to)), "{:?} -> {:?}", from, to);
+                        assert_eq!(status, from.as_str());
+                        let campaign = fetch(&pool, id).await.unwrap().unwrap();
+                        assert_eq!(campaign.status, from.as_str());
+                    }
+                    Transition::NotFound => panic!("campaign {} not found", id),
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn stamps_the_transition_time() {
+        let pool = pool().await;
+        let id = campaign(&pool, CampaignStatus::Draft).await;
+
+        let Transition::Done(started) =
+            transition(&pool, id, CampaignStatus::Active).await.unwrap()
+        else {
+            panic!("draft campaign did not start");
+        };
+        assert!(started.started_at.is_some());
+
+        let Transition::Done(paused) = transition(&pool, id, CampaignStatus::Paused).await.unwrap()
+        else {
+            panic!("active campaign did not pause");
+        };
+        assert!(paused.paused_at.is_some());
+
+        let Transition::Done(resumed) =
+            transition(&pool, id, CampaignStatus::Active).await.unwrap()
+        else {
+            panic!("paused campaign did not resume");
+        };
+        assert_eq!(resumed.paused_at, None);
+        assert_eq!(resumed.started_at, started.started_at);
+
+        let Transition::Done(archived) = transition(&pool, id, CampaignStatus::Archived)
+            .await
+            .unwrap()
+        else {
+            panic!("active campaign was not archived");
+        };
+        assert!(archived.archived_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn unknown_campaign_is_not_found() {
+        let pool = pool().await;
+        assert!(matches!(
+            transition(&pool, 42, CampaignStatus::Active).await.unwrap(),
+            Transition::NotFound
+        ));
+    }
+}
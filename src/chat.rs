@@ -0,0 +1,214 @@
+use std::error::Error;
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tracing::info;
+
+use crate::models::{ChatChannel, EmailKind, LeadContact};
+use crate::signing::body_signature;
+
+pub type ChatResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Header carrying the hex HMAC-SHA256 of a webhook body, signed with the
+/// channel's secret. Set on outbound webhooks and required on inbound ones
+/// when the channel has a secret.
+pub const SIGNATURE_HEADER: &str = "X-Sales-App-Signature";
+
+/// How long a channel's webhook may take to accept a message.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Body posted to a chat channel's webhook for every outbound message.
+#[derive(Debug, Serialize)]
+struct OutboundChat<'a> {
+    channel: &'a str,
+    to: &'a str,
+    text: &'a str,
+    message_id: i64,
+    kind: &'a str,
+}
+
+/// What the webhook may answer with; an `id` is kept as the external id.
+#[derive(Debug, Default, Deserialize)]
+struct OutboundChatResponse {
+    id: Option<String>,
+}
+
+/// Posts a message to the contact's chat channel webhook and records it in
+/// `chat_messages`. Returns the id the webhook gave the message, if any.
+pub async fn send(
+    pool: &SqlitePool,
+    message_id: i64,
+    kind: EmailKind,
+    contact_id: i64,
+    body: &str,
+) -> ChatResult<Option<String>> {
+    let contact = fetch_contact(pool, contact_id)
+        .await?
+        .ok_or("contact not found")?;
+    let channel = fetch_channel(pool, contact.chat_channel_id)
+        .await?
+        .ok_or("chat channel not found")?;
+
+    let payload = serde_json::to_vec(&OutboundChat {
+        channel: &channel.name,
+        to: &contact.handle,
+        text: body,
+        message_id,
+        kind: kind.as_str(),
+    })?;
+
+    let mut request = reqwest::Client::new()
+        .post(&channel.webhook_url)
+        .header("Content-Type", "application/json")
+        .timeout(WEBHOOK_TIMEOUT);
+    if let Some(secret) = channel.secret.as_deref() {
+        request = request.header(SIGNATURE_HEADER, body_signature(secret, &payload));
+    }
+
+    let response = request.body(payload).send().await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        return Err(format!("{} webhook returned {}: {}", channel.name, status, text).into());
+    }
+
+    let external_id = response
+        .json::<OutboundChatResponse>()
+        .await
+        .unwrap_or_default()
+        .id;
+
+    info!(
+        "Delivered message {} to {} on {}",
+        message_id, contact.handle, channel.name
+    );
+
+    sqlx::query(
+        r#"
+        INSERT INTO chat_messages (message_id, contact_id, direction, kind, body, external_id, created_at)
+        VALUES (?, ?, 'outbound', ?, ?, ?, ?)
+        "#,
+    )
+    .bind(message_id)
+    .bind(contact.id)
+    .bind(kind.as_str())
+    .bind(body)
+    .bind(&external_id)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+
+    Ok(external_id)
+}
+
+/// Records an inbound chat message and returns the conversation it belongs
+/// to: the message last sent to the contact, else the lead's latest chat
+/// message on that contact.
+pub async fn record_inbound(
+    pool: &SqlitePool,
+    contact: &LeadContact,
+    text: &str,
+    external_id: Option<&str>,
+) -> Result<Option<i64>, sqlx::Error> {
+    let mut message_id: Option<i64> = sqlx::query_scalar(
+        r#"
+        SELECT message_id FROM chat_messages
+        WHERE contact_id = ? AND direction = 'outbound' AND message_id IS NOT NULL
+        ORDER BY id DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(contact.id)
+    .fetch_optional(pool)
+    .await?;
+
+    if message_id.is_none() {
+        message_id = sqlx::query_scalar(
+            "SELECT id FROM messages WHERE contact_id = ? ORDER BY id DESC LIMIT 1",
+        )
+        .bind(contact.id)
+        .fetch_optional(pool)
+        .await?;
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO chat_messages (message_id, contact_id, direction, body, external_id, created_at)
+        VALUES (?, ?, 'inbound', ?, ?, ?)
+        "#,
+    )
+    .bind(message_id)
+    .bind(contact.id)
+    .bind(text)
+    .bind(external_id)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+
+    Ok(message_id)
+}
+
+pub async fn fetch_channel(
+    pool: &SqlitePool,
+    channel_id: i64,
+) -> Result<Option<ChatChannel>, sqlx::Error> {
+    sqlx::query_as::<_, ChatChannel>(
+        "SELECT id, name, webhook_url, secret, created_at FROM chat_channels WHERE id = ?",
+    )
+    .bind(channel_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn fetch_channel_by_name(
+    pool: &SqlitePool,
+    name: &str,
+) -> Result<Option<ChatChannel>, sqlx::Error> {
+    sqlx::query_as::<_, ChatChannel>(
+        "SELECT id, name, webhook_url, secret, created_at FROM chat_channels WHERE name = ?",
+    )
+    .bind(name)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn fetch_contact(
+    pool: &SqlitePool,
+    contact_id: i64,
+) -> Result<Option<LeadContact>, sqlx::Error> {
+    sqlx::query_as::<_, LeadContact>(
+        "SELECT id, lead_id, chat_channel_id, handle, created_at FROM lead_contacts WHERE id = ?",
+    )
+    .bind(contact_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn find_contact(
+    pool: &SqlitePool,
+    chat_channel_id: i64,
+    handle: &str,
+) -> Result<Option<LeadContact>, sqlx::Error> {
+    sqlx::query_as::<_, LeadContact>(
+        "SELECT id, lead_id, chat_channel_id, handle, created_at FROM lead_contacts WHERE chat_channel_id = ? AND handle = ?",
+    )
+    .bind(chat_channel_id)
+    .bind(handle.trim())
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn lead_contacts(
+    pool: &SqlitePool,
+    lead_id: i64,
+) -> Result<Vec<LeadContact>, sqlx::Error> {
+    sqlx::query_as::<_, LeadContact>(
+        "SELECT id, lead_id, chat_channel_id, handle, created_at FROM lead_contacts WHERE lead_id = ? ORDER BY id ASC",
+    )
+    .bind(lead_id)
+    .fetch_all(pool)
+    .await
+}
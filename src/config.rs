@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use axum::extract::FromRef;
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use tracing::warn;
+
+use crate::ai::{self, AiProvider, MockFixtures};
+
+const DEV_SIGNING_SECRET: &str = "sales-app-dev-secret";
+const DEFAULT_MAX_ATTACHMENT_BYTES: usize = 10 * 1024 * 1024;
+
+/// Prices of OpenAI's chat models in USD, and of the free mock model, used
+/// unless `AI_PRICES` overrides them.
+const DEFAULT_AI_PRICES: &[(&str, ModelPrice)] = &[
+    (
+        "mock",
+        ModelPrice {
+            prompt: 0.0,
+            completion: 0.0,
+        },
+    ),
+    (
+        "gpt-4o-mini",
+        ModelPrice {
+            prompt: 0.15,
+            completion: 0.60,
+        },
+    ),
+    (
+        "gpt-4o",
+        ModelPrice {
+            prompt: 2.50,
+            completion: 10.00,
+        },
+    ),
+    (
+        "gpt-4.1-mini",
+        ModelPrice {
+            prompt: 0.40,
+            completion: 1.60,
+        },
+    ),
+    (
+        "gpt-4.1",
+        ModelPrice {
+            prompt: 2.00,
+            completion: 8.00,
+        },
+    ),
+];
+
+/// Settings read from the environment at startup.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Base URL the app is reachable at from a lead's inbox, used for links.
+    pub public_url: String,
+    /// Secret used to sign the tokens in public links.
+    pub signing_secret: String,
+    pub inbound_maildir: Option<PathBuf>,
+    /// Directory uploaded attachments are stored in.
+    pub attachments_dir: PathBuf,
+    pub max_attachment_bytes: usize,
+    pub sms: SmsSettings,
+    /// Number SMS are sent from.
+    pub sms_from: Option<String>,
+    pub ai: AiSettings,
+    /// Provider built from `ai`, shared by every request and job.
+    pub ai_provider: Option<Arc<dyn AiProvider>>,
+    /// Prices AI calls are costed with, by model.
+    pub ai_prices: BTreeMap<String, ModelPrice>,
+}
+
+/// Which SMS provider texts are handed to.
+#[derive(Debug, Clone)]
+pub enum SmsSettings {
+    /// Logs texts instead of sending them.
+    Log,
+    /// Twilio's Messages API, or anything speaking the same protocol.
+    Twilio {
+        api_url: String,
+        account_sid: String,
+        auth_token: String,
+    },
+}
+
+impl SmsSettings {
+    fn from_env() -> Self {
+        if std::env::var("SMS_PROVIDER").as_deref() != Ok("twilio") {
+            return SmsSettings::Log;
+        }
+
+        match (
+            std::env::var("TWILIO_ACCOUNT_SID"),
+            std::env::var("TWILIO_AUTH_TOKEN"),
+        ) {
+            (Ok(account_sid), Ok(auth_token)) => SmsSettings::Twilio {
+                api_url: std::env::var("TWILIO_API_URL")
+                    .unwrap_or_else(|_| "https://api.twilio.com".to_string())
+                    .trim_end_matches('/')
+                    .to_string(),
+                account_sid,
+                auth_token,
+            },
+            _ => {
+                warn!("TWILIO_ACCOUNT_SID or TWILIO_AUTH_TOKEN is not set, logging SMS instead");
+                SmsSettings::Log
+            }
+        }
+    }
+}
+
+/// Which AI provider replies are classified and answered with.
+#[derive(Debug, Clone)]
+pub enum AiSettings {
+    /// No provider; replies are classified with rules only.
+    Disabled,
+    /// OpenAI's chat completions API, or any compatible endpoint.
+    OpenAi {
+        api_url: String,
+        api_key: String,
+        model: String,
+    },
+    /// Answers from fixture rules, for tests and demos.
+    Mock {
+        model: String,
+        fixtures: MockFixtures,
+        latency_ms: u64,
+        /// Fails every nth call.
+        fail_every: Option<u64>,
+    },
+}
+
+impl AiSettings {
+    fn from_env() -> Self {
+        match std::env::var("AI_PROVIDER").as_deref() {
+            Ok("openai") => AiSettings::openai_from_env(),
+            Ok("mock") => AiSettings::mock_from_env(),
+            _ => AiSettings::Disabled,
+        }
+    }
+
+    fn openai_from_env() -> Self {
+        match std::env::var("OPENAI_API_KEY") {
+            Ok(api_key) => AiSettings::OpenAi {
+                api_url: std::env::var("OPENAI_API_URL")
+                    .unwrap_or_else(|_| "https://api.openai.com/v1".to_string())
+                    .trim_end_matches('/')
+                    .to_string(),
+                api_key,
+                model: std::env::var("AI_MODEL").unwrap_or_else(|_| "gpt-4o-mini".to_string()),
+            },
+            Err(_) => {
+                warn!("OPENAI_API_KEY is not set, classifying replies with rules only");
+                AiSettings::Disabled
+            }
+        }
+    }
+
+    fn mock_from_env() -> Self {
+        let fixtures = match std::env::var("AI_MOCK_FIXTURES") {
+            Ok(path) => MockFixtures::load(Path::new(&path)).unwrap_or_else(|e| {
+                warn!("Failed to load AI_MOCK_FIXTURES {}, using no rules: {}", path, e);
+                MockFixtures::default()
+            }),
+            Err(_) => MockFixtures::default(),
+        };
+
+        AiSettings::Mock {
+            model: std::env::var("AI_MODEL").unwrap_or_else(|_| "mock".to_string()),
+            fixtures,
+            latency_ms: std::env::var("AI_MOCK_LATENCY_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0),
+            fail_every: std::env::var("AI_MOCK_FAIL_EVERY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|every| *every > 0),
+        }
+    }
+}
+
+/// Price of a model per million tokens.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ModelPrice {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+/// The default price table, with the models of the `AI_PRICES` JSON object
+/// (`{"model": {"prompt": 0.15, "completion": 0.6}}`) added or replaced.
+fn ai_prices_from_env() -> BTreeMap<String, ModelPrice> {
+    let mut prices: BTreeMap<String, ModelPrice> = DEFAULT_AI_PRICES
+        .iter()
+        .map(|(model, price)| (model.to_string(), *price))
+        .collect();
+
+    if let Ok(json) = std::env::var("AI_PRICES") {
+        match serde_json::from_str::<BTreeMap<String, ModelPrice>>(&json) {
+            Ok(overrides) => prices.extend(overrides),
+            Err(e) => warn!("AI_PRICES is not a valid price table, ignoring it: {}", e),
+        }
+    }
+
+    prices
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let public_url = std::env::var("PUBLIC_URL")
+            .unwrap_or_else(|_| "http://localhost:3010".to_string())
+            .trim_end_matches('/')
+            .to_string();
+
+        let signing_secret = std::env::var("SIGNING_SECRET").unwrap_or_else(|_| {
+            warn!("SIGNING_SECRET is not set, using an insecure development secret");
+            DEV_SIGNING_SECRET.to_string()
+        });
+        let ai = AiSettings::from_env();
+
+        Self {
+            public_url,
+            signing_secret,
+            inbound_maildir: std::env::var("INBOUND_MAILDIR").ok().map(PathBuf::from),
+            attachments_dir: std::env::var("ATTACHMENTS_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| PathBuf::from("attachments")),
+            max_attachment_bytes: std::env::var("MAX_ATTACHMENT_BYTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MAX_ATTACHMENT_BYTES),
+            sms: SmsSettings::from_env(),
+            sms_from: std::env::var("SMS_FROM_NUMBER").ok(),
+            ai_provider: ai::provider(&ai),
+            ai,
+            ai_prices: ai_prices_from_env(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AppState {
+    pub pool: SqlitePool,
+    pub config: Arc<Config>,
+}
+
+impl FromRef<AppState> for SqlitePool {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Config> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
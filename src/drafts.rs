@@ -0,0 +1,136 @@
+use chrono::Utc;
+use sqlx::SqlitePool;
+
+use crate::ai::AiProvider;
+use crate::config::Config;
+use crate::fields;
+use crate::models::{AiDraft, Channel, DraftBrief, Lead};
+use crate::usage::{self, AiCall, AiCallError};
+
+const DRAFT_COLUMNS: &str =
+    "id, lead_id, channel, subject, body, system_prompt, prompt, model, message_id, created_at";
+
+const DEFAULT_TONE: &str = "concise and friendly";
+
+/// Instructions for writing a first message on `channel`.
+pub fn system_prompt(channel: Channel) -> String {
+    let format = match channel {
+        Channel::Email => {
+            "Start with a line `Subject: <subject>`, then a blank line, then the body. \
+             Keep the body under 120 words."
+        }
+        Channel::Sms | Channel::Chat => {
+            "Write a single text message under 300 characters, without a subject."
+        }
+    };
+
+    format!(
+        "You write personalized first-touch sales outreach messages. \
+         Address the lead by first name, mention why the product is relevant to them \
+         and end with one clear call to action. Do not invent facts about the lead \
+         and do not use placeholders or a signature. {}",
+        format
+    )
+}
+
+/// The lead's details and the brief, as the prompt the model drafts from.
+pub fn prompt(lead: &Lead, brief: &DraftBrief) -> String {
+    let mut prompt = format!("Lead name: {}\n", lead.name);
+    if let Some(domain) = lead
+        .email
+        .as_deref()
+        .and_then(|email| email.split_once('@'))
+        .map(|(_, domain)| domain)
+    {
+        prompt.push_str(&format!("Lead email domain: {}\n", domain));
+    }
+    for (key, value) in lead.fields.iter() {
+        prompt.push_str(&format!(
+            "Lead {}: {}\n",
+            key.replace('_', " "),
+            fields::value_text(value)
+        ));
+    }
+
+    prompt.push_str(&format!(
+        "Product: {}\nGoal: {}\nTone: {}\n",
+        brief.product.trim(),
+        brief.goal.trim(),
+        brief.tone.as_deref().map_or(DEFAULT_TONE, str::trim)
+    ));
+    prompt
+}
+
+/// Splits the model's answer into a subject and a body. Only emails get a
+/// subject; a missing `Subject:` line leaves the whole answer as the body.
+pub fn parse_answer(answer: &str, channel: Channel) -> (Option<String>, String) {
+    let answer = answer.trim();
+
+    if channel == Channel::Email
+        && let Some((first, rest)) = answer.split_once('\n')
+        && let Some(subject) = first.trim().strip_prefix("Subject:")
+    {
+        return (Some(subject.trim().to_string()), rest.trim().to_string());
+    }
+
+    (None, answer.to_string())
+}
+
+/// Asks the provider for a first message to `lead` and stores it with the
+/// prompt and model for audit.
+pub async fn generate(
+    pool: &SqlitePool,
+    config: &Config,
+    provider: &dyn AiProvider,
+    lead: &Lead,
+    brief: &DraftBrief,
+    channel: Channel,
+) -> Result<AiDraft, AiCallError> {
+    let system_prompt = system_prompt(channel);
+    let prompt = prompt(lead, brief);
+
+    let call = AiCall {
+        workspace_id: lead.workspace_id,
+        purpose: "draft",
+        message_id: None,
+    };
+    let answer = usage::complete(pool, config, provider, &call, &system_prompt, &prompt).await?;
+    let (subject, body) = parse_answer(&answer.text, channel);
+
+    let draft = sqlx::query_as::<_, AiDraft>(&format!(
+        r#"
+        INSERT INTO ai_drafts (lead_id, channel, subject, body, system_prompt, prompt, model, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        RETURNING {}
+        "#,
+        DRAFT_COLUMNS
+    ))
+    .bind(lead.id)
+    .bind(channel.as_str())
+    .bind(subject)
+    .bind(body)
+    .bind(&system_prompt)
+    .bind(&prompt)
+    .bind(provider.model())
+    .bind(Utc::now().to_rfc3339())
+    .fetch_one(pool)
+    .await?;
+
+    Ok(draft)
+}
+
+/// Links the draft to the message it was enqueued as.
+pub async fn set_message(
+    pool: &SqlitePool,
+    draft_id: i64,
+    message_id: i64,
+) -> Result<AiDraft, sqlx::Error> {
+    sqlx::query_as::<_, AiDraft>(&format!(
+        "UPDATE ai_drafts SET message_id = ? WHERE id = ? RETURNING {}",
+        DRAFT_COLUMNS
+    ))
+    .bind(message_id)
+    .bind(draft_id)
+    .fetch_one(pool)
+    .await
+}
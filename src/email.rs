@@ -0,0 +1,682 @@
+use std::error::Error;
+use std::path::Path;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::Utc;
+use tracing::info;
+
+use crate::config::Config;
+use crate::inbound::tag_address;
+use crate::models::{EmailKind, Lead, SenderIdentity, TransportSettings, Workspace};
+use crate::richtext::{escape, text_to_html};
+use crate::tracking;
+
+pub type TransportResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+/// A fully composed email, ready to be rendered and handed to a transport.
+#[derive(Debug, Clone)]
+pub struct OutgoingEmail {
+    pub message_id: String,
+    pub from: Option<String>,
+    pub reply_to: Option<String>,
+    pub in_reply_to: Option<String>,
+    pub references: Vec<String>,
+    /// One-click unsubscribe URL, sent as `List-Unsubscribe`.
+    pub list_unsubscribe: Option<String>,
+    pub to: String,
+    pub subject: Option<String>,
+    pub body: String,
+    /// HTML alternative of `body`; the email is sent as multipart/alternative
+    /// when set.
+    pub html: Option<String>,
+    pub attachments: Vec<EmailAttachment>,
+}
+
+/// A file sent along with an email.
+#[derive(Debug, Clone)]
+pub struct EmailAttachment {
+    pub filename: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+impl OutgoingEmail {
+    /// Builds the email for a lead, appending the sender signature to the body
+    /// and tagging the reply-to address so replies can be matched back.
+    /// Returns `None` when the lead has no email address.
+    pub fn compose(
+        message_id: i64,
+        body: &str,
+        html: Option<&str>,
+        lead: &Lead,
+        sender: Option<&SenderIdentity>,
+    ) -> Option<Self> {
+        let to = lead.email.as_deref()?;
+
+        let mut body = body.to_string();
+        let mut html = html.map(str::to_string);
+        if let Some(signature) = sender.and_then(|s| s.signature.as_deref())
+            && !signature.trim().is_empty()
+        {
+            body.push_str("\n\n-- \n");
+            body.push_str(signature.trim_end());
+            if let Some(html) = &mut html {
+                html.push_str(&format!(
+                    "\n<p>-- <br>\n{}</p>",
+                    text_to_html(signature.trim_end())
+                ));
+            }
+        }
+
+        let domain = sender
+            .and_then(|s| s.address.split_once('@'))
+            .map_or("localhost", |(_, domain)| domain);
+
+        Some(Self {
+            message_id: generate_message_id(message_id, domain),
+            from: sender.map(|s| format_address(&s.name, &s.address)),
+            reply_to: sender
+                .and_then(|s| s.reply_to.as_deref())
+                .map(|reply_to| tag_address(reply_to, message_id)),
+            in_reply_to: None,
+            references: Vec::new(),
+            list_unsubscribe: None,
+            to: format_address(&lead.name, to),
+            subject: None,
+            body,
+            html,
+            attachments: Vec::new(),
+        })
+    }
+
+    /// An internal email to a sender identity's own mailbox, e.g. to tell
+    /// it that a lead is interested.
+    pub fn notification(sender: &SenderIdentity, subject: &str, body: &str) -> Self {
+        let address = format_address(&sender.name, &sender.address);
+        let domain = sender
+            .address
+            .split_once('@')
+            .map_or("localhost", |(_, domain)| domain);
+
+        Self {
+            message_id: generate_message_id(0, domain),
+            from: Some(address.clone()),
+            reply_to: None,
+            in_reply_to: None,
+            references: Vec::new(),
+            list_unsubscribe: None,
+            to: address,
+            subject: Some(subject.replace(['\r', '\n'], " ")),
+            body: body.to_string(),
+            html: None,
+            attachments: Vec::new(),
+        }
+    }
+
+    /// Threads the email under the Message-IDs already sent for the same
+    /// message, oldest first, so it lands in the same conversation.
+    pub fn in_thread(mut self, thread: &[String]) -> Self {
+        self.in_reply_to = thread.last().cloned();
+        self.references = thread.to_vec();
+        self
+    }
+
+    /// Sets the subject. Follow-ups and AI replies answer the initial email,
+    /// so their subject is prefixed with `Re:`.
+    pub fn with_subject(mut self, subject: Option<&str>, kind: EmailKind) -> Self {
+        self.subject = subject.map(|subject| match kind {
+            EmailKind::Initial => subject.to_string(),
+            _ => format!("Re: {}", subject),
+        });
+        self
+    }
+
+    /// Appends the workspace's compliance footer (custom text, physical
+    /// address and unsubscribe link) and sets the `List-Unsubscribe` header.
+    pub fn with_compliance_footer(
+        mut self,
+        workspace: Option<&Workspace>,
+        unsubscribe_url: &str,
+    ) -> Self {
+        let mut footer: Vec<&str> = Vec::new();
+        if let Some(text) = workspace.and_then(|w| w.footer.as_deref()) {
+            footer.push(text.trim());
+        }
+        if let Some(address) = workspace.and_then(|w| w.physical_address.as_deref()) {
+            footer.push(address.trim());
+        }
+
+        let unsubscribe = format!("Unsubscribe: {}", unsubscribe_url);
+        footer.push(&unsubscribe);
+
+        self.body.push_str("\n\n");
+        self.body.push_str(&footer.join("\n"));
+
+        if let Some(html) = &mut self.html {
+            let mut lines: Vec<String> = footer[..footer.len() - 1]
+                .iter()
+                .map(|line| text_to_html(line))
+                .collect();
+            lines.push(format!(
+                "<a href=\"{}\">Unsubscribe</a>",
+                escape(unsubscribe_url)
+            ));
+            html.push_str(&format!(
+                "\n<p style=\"color:#666666;font-size:12px\">{}</p>",
+                lines.join("<br>\n")
+            ));
+        }
+
+        self.list_unsubscribe = Some(unsubscribe_url.to_string());
+        self
+    }
+
+    /// Routes links through the click-tracking redirect and, for HTML
+    /// emails, adds the open-tracking pixel. Plain-text emails only track
+    /// clicks.
+    pub fn with_tracking(mut self, config: &Config, message_id: i64) -> Self {
+        let rewrite = |url: &str| tracking::click_url(config, message_id, url);
+
+        self.body = tracking::rewrite_text(&self.body, rewrite);
+        if let Some(html) = &mut self.html {
+            *html = tracking::rewrite_html(html, rewrite);
+            html.push_str(&format!(
+                "\n<img src=\"{}\" width=\"1\" height=\"1\" alt=\"\" style=\"display:none\">",
+                escape(&tracking::open_url(config, message_id))
+            ));
+        }
+        self
+    }
+
+    pub fn with_attachments(mut self, attachments: Vec<EmailAttachment>) -> Self {
+        self.attachments = attachments;
+        self
+    }
+
+    /// Renders the email as an RFC 822 message.
+    pub fn to_rfc822(&self) -> String {
+        let mut headers = vec![format!("Message-ID: {}", self.message_id)];
+
+        if let Some(from) = &self.from {
+            headers.push(format!("From: {}", single_line(from)));
+        }
+        if let Some(reply_to) = &self.reply_to {
+            headers.push(format!("Reply-To: {}", single_line(reply_to)));
+        }
+        headers.push(format!("To: {}", single_line(&self.to)));
+        if let Some(in_reply_to) = &self.in_reply_to {
+            headers.push(format!("In-Reply-To: {}", single_line(in_reply_to)));
+        }
+        if !self.references.is_empty() {
+            headers.push(format!(
+                "References: {}",
+                single_line(&self.references.join(" "))
+            ));
+        }
+        if let Some(url) = &self.list_unsubscribe {
+            headers.push(format!("List-Unsubscribe: <{}>", url));
+            headers.push("List-Unsubscribe-Post: List-Unsubscribe=One-Click".to_string());
+        }
+        if let Some(subject) = &self.subject {
+            headers.push(format!("Subject: {}", encode_header(subject)));
+        }
+        headers.push(format!("Date: {}", Utc::now().to_rfc2822()));
+        headers.push("MIME-Version: 1.0".to_string());
+
+        let (content_headers, body) = self.body_part();
+
+        if self.attachments.is_empty() {
+            headers.push(content_headers);
+            return format!("{}\r\n\r\n{}\r\n", headers.join("\r\n"), body);
+        }
+
+        let boundary = self.boundary("mixed");
+        headers.push(format!(
+            "Content-Type: multipart/mixed; boundary=\"{}\"",
+            boundary
+        ));
+
+        let mut raw = headers.join("\r\n");
+        raw.push_str("\r\n\r\n");
+        raw.push_str(&format!(
+            "--{}\r\n{}\r\n\r\n{}\r\n",
+            boundary, content_headers, body
+        ));
+        for attachment in &self.attachments {
+            let (name, filename) = filename_params(&attachment.filename);
+            raw.push_str(&format!(
+                "--{}\r\nContent-Type: {};\r\n {}\r\nContent-Disposition: attachment;\r\n {}\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+                boundary,
+                single_line(&attachment.content_type),
+                name,
+                filename,
+                base64_lines(&attachment.data)
+            ));
+        }
+        raw.push_str(&format!("--{}--\r\n", boundary));
+        raw
+    }
+
+    /// Content headers and body of the message text: plain text, or
+    /// multipart/alternative when there is an HTML version. Text is
+    /// quoted-printable so it passes transports that are not 8-bit clean.
+    fn body_part(&self) -> (String, String) {
+        let Some(html) = &self.html else {
+            return (text_headers("text/plain"), quoted_printable(&self.body));
+        };
+
+        let boundary = self.boundary("alt");
+        let parts = [
+            ("text/plain", quoted_printable(&self.body)),
+            (
+                "text/html",
+                quoted_printable(&format!("<html><body>\n{}\n</body></html>", html)),
+            ),
+        ];
+
+        let mut body = String::new();
+        for (content_type, content) in parts {
+            body.push_str(&format!(
+                "--{}\r\n{}\r\n\r\n{}\r\n",
+                boundary,
+                text_headers(content_type),
+                content
+            ));
+        }
+        body.push_str(&format!("--{}--", boundary));
+
+        (
+            format!(
+                "Content-Type: multipart/alternative; boundary=\"{}\"",
+                boundary
+            ),
+            body,
+        )
+    }
+
+    fn boundary(&self, kind: &str) -> String {
+        format!(
+            "=_{}_{}",
+            kind,
+            self.message_id.trim_matches(['<', '>']).replace('@', "_")
+        )
+    }
+}
+
+/// Hands a rendered email to the transport configured for the sender.
+pub async fn deliver(settings: &TransportSettings, email: &OutgoingEmail) -> TransportResult {
+    let raw = email.to_rfc822();
+
+    match settings {
+        TransportSettings::Log => {
+            info!(
+                "Delivering {} to {} via log transport:\n{}",
+                email.message_id, email.to, raw
+            );
+        }
+        TransportSettings::Outbox { dir } => {
+            tokio::fs::create_dir_all(dir).await?;
+            let name = email
+                .message_id
+                .trim_matches(['<', '>'])
+                .replace(['/', '\\'], "_");
+            let path = Path::new(dir).join(format!("{}.eml", name));
+            tokio::fs::write(&path, raw).await?;
+            info!(
+                "Delivered {} to outbox at {}",
+                email.message_id,
+                path.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Base64 with lines wrapped at 76 characters, as MIME requires.
+fn base64_lines(data: &[u8]) -> String {
+    let encoded = STANDARD.encode(data);
+    encoded
+        .as_bytes()
+        .chunks(76)
+        .map(|line| String::from_utf8_lossy(line))
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+fn text_headers(content_type: &str) -> String {
+    format!(
+        "Content-Type: {}; charset=utf-8\r\nContent-Transfer-Encoding: quoted-printable",
+        content_type
+    )
+}
+
+/// Quoted-printable text (RFC 2045): line breaks are kept, bytes outside
+/// printable ASCII, `=` and spaces ending a line are escaped, and lines are
+/// soft-wrapped so none is longer than 76 characters.
+fn quoted_printable(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+
+    for (i, line) in crlf(text).split("\r\n").enumerate() {
+        if i > 0 {
+            out.push_str("\r\n");
+        }
+        let bytes = line.as_bytes();
+        let mut width = 0;
+        for (j, &byte) in bytes.iter().enumerate() {
+            let escape = match byte {
+                b' ' | b'\t' => j + 1 == bytes.len(),
+                b'=' => true,
+                _ => !byte.is_ascii_graphic(),
+            };
+            let encoded = if escape {
+                format!("={:02X}", byte)
+            } else {
+                (byte as char).to_string()
+            };
+            // Keep a column for the `=` of the soft line break.
+            if width + encoded.len() > 75 {
+                out.push_str("=\r\n");
+                width = 0;
+            }
+            width += encoded.len();
+            out.push_str(&encoded);
+        }
+    }
+
+    out
+}
+
+/// The `name` and `filename` parameters of an attachment. Non-ASCII names
+/// are given as RFC 2231 `filename*`, split into continuations when long,
+/// and as the RFC 2047 encoded `name` older clients read.
+fn filename_params(filename: &str) -> (String, String) {
+    let filename = quoted_text(filename);
+    if filename.is_ascii() {
+        return (
+            format!("name=\"{}\"", filename),
+            format!("filename=\"{}\"", filename),
+        );
+    }
+
+    let mut chunks = vec![String::new()];
+    for byte in filename.bytes() {
+        let encoded = if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            (byte as char).to_string()
+        } else {
+            format!("%{:02X}", byte)
+        };
+        if chunks
+            .last()
+            .is_some_and(|chunk| chunk.len() + encoded.len() > 50)
+        {
+            chunks.push(String::new());
+        }
+        if let Some(chunk) = chunks.last_mut() {
+            chunk.push_str(&encoded);
+        }
+    }
+
+    let extended = if chunks.len() == 1 {
+        format!("filename*=UTF-8''{}", chunks[0])
+    } else {
+        chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| match i {
+                0 => format!("filename*0*=UTF-8''{}", chunk),
+                _ => format!("filename*{}*={}", i, chunk),
+            })
+            .collect::<Vec<_>>()
+            .join(";\r\n ")
+    };
+
+    (format!("name=\"{}\"", encode_header(&filename)), extended)
+}
+
+fn crlf(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\n', "\r\n")
+}
+
+fn generate_message_id(message_id: i64, domain: &str) -> String {
+    let nanos = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+    format!("<sales-app.m{}.{:x}@{}>", message_id, nanos, domain)
+}
+
+/// Whether `address` is a plain `local@domain` email address that can be
+/// written into a header as is.
+pub fn is_valid_address(address: &str) -> bool {
+    let Some((local, domain)) = address.rsplit_once('@') else {
+        return false;
+    };
+    let allowed = |c: char| {
+        c.is_ascii_graphic()
+            && !matches!(
+                c,
+                '<' | '>' | '(' | ')' | '[' | ']' | ',' | ';' | ':' | '"' | '\\' | '@'
+            )
+    };
+
+    address.len() <= 254
+        && !local.is_empty()
+        && local.len() <= 64
+        && local.chars().all(allowed)
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.'))
+}
+
+fn format_address(name: &str, address: &str) -> String {
+    let name = quoted_text(name);
+    let name = name.trim();
+    let address = single_line(address);
+    if name.is_empty() {
+        address
+    } else if name.is_ascii() {
+        format!("\"{}\" <{}>", name, address)
+    } else {
+        format!("{} <{}>", encode_header(name), address)
+    }
+}
+
+/// Header text with line breaks replaced, so it cannot start new headers.
+fn single_line(text: &str) -> String {
+    text.replace(['\r', '\n'], " ")
+}
+
+/// Text for a quoted header value, without quotes and backslashes.
+fn quoted_text(text: &str) -> String {
+    single_line(text).replace(['"', '\\'], "")
+}
+
+/// Writes non-ASCII header text as RFC 2047 encoded words, folded so no
+/// line gets longer than 76 characters.
+fn encode_header(text: &str) -> String {
+    let text = single_line(text);
+    if text.is_ascii() {
+        return text;
+    }
+
+    // 39 bytes encode to 52 base64 characters, 64 with the `=?UTF-8?B?` and
+    // `?=` around them, leaving room for the header name.
+    let mut words = Vec::new();
+    let mut chunk = String::new();
+    for c in text.chars() {
+        if chunk.len() + c.len_utf8() > 39 {
+            words.push(encoded_word(&chunk));
+            chunk.clear();
+        }
+        chunk.push(c);
+    }
+    words.push(encoded_word(&chunk));
+    words.join("\r\n ")
+}
+
+fn encoded_word(text: &str) -> String {
+    format!("=?UTF-8?B?{}?=", STANDARD.encode(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use mail_parser::{MessageParser, MimeHeaders};
+
+    use super::*;
+
+    fn email(to: &str, subject: &str) -> OutgoingEmail {
+        OutgoingEmail {
+            message_id: "<m1@example.com>".to_string(),
+            from: Some(format_address("Jane Sales", "jane@example.com")),
+            reply_to: None,
+            in_reply_to: None,
+            references: Vec::new(),
+            list_unsubscribe: None,
+            to: to.to_string(),
+            subject: Some(subject.to_string()),
+            body: "Hello".to_string(),
+            html: None,
+            attachments: Vec::new(),
+        }
+    }
+
+    fn headers(raw: &str) -> Vec<&str> {
+        raw.split("\r\n\r\n")
+            .next()
+            .unwrap()
+            .split("\r\n")
+            .collect()
+    }
+
+    #[test]
+    fn validates_addresses() {
+        assert!(is_valid_address("jane.doe+sales@mail.example.com"));
+        assert!(!is_valid_address(
+            "jane@example.com\r\nBcc: all@example.com"
+        ));
+        assert!(!is_valid_address("jane@example.com\nBcc: x@y.com"));
+        assert!(!is_valid_address("Jane <jane@example.com>"));
+        assert!(!is_valid_address("jane example@example.com"));
+        assert!(!is_valid_address("jane@localhost"));
+        assert!(!is_valid_address("@example.com"));
+        assert!(!is_valid_address("jane"));
+    }
+
+    #[test]
+    fn line_breaks_cannot_add_headers() {
+        let to = format_address("Evil\r\nBcc: x@y.com", "a@b.com\r\nBcc: all@example.com");
+        let raw = email(&to, "Hi\r\nBcc: z@y.com").to_rfc822();
+        let headers = headers(&raw);
+
+        assert!(headers.iter().all(|header| !header.starts_with("Bcc")));
+        assert!(headers.contains(&"Subject: Hi  Bcc: z@y.com"));
+    }
+
+    #[test]
+    fn encodes_non_ascii_headers() {
+        assert_eq!(
+            format_address("José Núñez", "jose@example.com"),
+            "=?UTF-8?B?Sm9zw6kgTsO6w7Fleg==?= <jose@example.com>"
+        );
+        assert_eq!(
+            format_address("Jane \"JD\" Doe", "jane@example.com"),
+            "\"Jane JD Doe\" <jane@example.com>"
+        );
+        assert_eq!(encode_header("Quick question"), "Quick question");
+
+        let subject = "Ünïcödé ".repeat(10);
+        let encoded = encode_header(&subject);
+        assert!(encoded.split("\r\n ").count() > 1);
+        assert!(
+            encoded
+                .lines()
+                .all(|line| line.len() <= 76 - "Subject: ".len())
+        );
+
+        let decoded: String = encoded
+            .split("\r\n ")
+            .map(|word| {
+                let base64 = word
+                    .strip_prefix("=?UTF-8?B?")
+                    .unwrap()
+                    .strip_suffix("?=")
+                    .unwrap();
+                String::from_utf8(STANDARD.decode(base64).unwrap()).unwrap()
+            })
+            .collect();
+        assert_eq!(decoded, subject);
+    }
+
+    #[test]
+    fn text_is_quoted_printable() {
+        assert_eq!(
+            quoted_printable("Grüße = 3\nok "),
+            "Gr=C3=BC=C3=9Fe =3D 3\r\nok=20"
+        );
+        assert_eq!(quoted_printable("tab\t\r\nend"), "tab=09\r\nend");
+
+        let long = "word ".repeat(40);
+        let encoded = quoted_printable(&long);
+        assert!(encoded.split("\r\n").all(|line| line.len() <= 76));
+        assert_eq!(encoded.replace("=\r\n", "").replace("=20", " "), long);
+    }
+
+    #[test]
+    fn parts_decode_to_the_original_text() {
+        let mut email = email("ada@example.org", "Hi");
+        email.body = format!("Hallo Jürgen,\n{}\nbis bald", "ä".repeat(100));
+        email.html = Some("<p>Hallo Jürgen</p>".to_string());
+        email.attachments = vec![EmailAttachment {
+            filename: "Angebot für Jürgen – März.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            data: b"%PDF-1.4".to_vec(),
+        }];
+
+        let raw = email.to_rfc822();
+        assert!(raw.lines().all(|line| line.is_ascii() && line.len() <= 76));
+        assert!(!raw.contains("8bit"));
+
+        let parsed = MessageParser::default().parse(raw.as_bytes()).unwrap();
+        assert_eq!(
+            parsed.body_text(0).unwrap(),
+            email.body.replace('\n', "\r\n")
+        );
+        assert!(parsed.body_html(0).unwrap().contains("<p>Hallo Jürgen</p>"));
+        let attachment = parsed.attachment(0).unwrap();
+        assert_eq!(
+            attachment.attachment_name(),
+            Some("Angebot für Jürgen – März.pdf")
+        );
+        assert_eq!(attachment.contents(), b"%PDF-1.4");
+    }
+
+    #[test]
+    fn encodes_non_ascii_filenames() {
+        assert_eq!(
+            filename_params("report \"Q1\".pdf"),
+            (
+                "name=\"report Q1.pdf\"".to_string(),
+                "filename=\"report Q1.pdf\"".to_string()
+            )
+        );
+        assert_eq!(
+            filename_params("Grüße.txt"),
+            (
+                "name=\"=?UTF-8?B?R3LDvMOfZS50eHQ=?=\"".to_string(),
+                "filename*=UTF-8''Gr%C3%BC%C3%9Fe.txt".to_string()
+            )
+        );
+
+        let (_, filename) = filename_params(&"ü".repeat(10));
+        assert_eq!(
+            filename,
+            format!(
+                "filename*0*=UTF-8''{};\r\n filename*1*={}",
+                "%C3%BC".repeat(8),
+                "%C3%BC".repeat(2)
+            )
+        );
+    }
+}
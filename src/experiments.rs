@@ -0,0 +1,394 @@
+use std::cmp::Ordering;
+
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use tracing::info;
+
+use crate::models::{Experiment, ExperimentVariant, VariantStats};
+
+/// Days a send is given for replies before it counts towards auto-promotion.
+pub const DEFAULT_SETTLE_DAYS: i64 = 3;
+
+pub async fn fetch(
+    pool: &SqlitePool,
+    experiment_id: i64,
+) -> Result<Option<Experiment>, sqlx::Error> {
+    sqlx::query_as::<_, Experiment>(
+        "SELECT id, name, auto_promote_after, settle_days, winner_variant_id, promoted_at, created_at FROM experiments WHERE id = ?",
+    )
+    .bind(experiment_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn variants(
+    pool: &SqlitePool,
+    experiment_id: i64,
+) -> Result<Vec<ExperimentVariant>, sqlx::Error> {
+    sqlx::query_as::<_, ExperimentVariant>(
+        r#"
+        SELECT id, experiment_id, name, subject, message, follow_up, attachment_ids, weight, created_at
+        FROM experiment_variants
+        WHERE experiment_id = ?
+        ORDER BY id ASC
+        "#,
+    )
+    .bind(experiment_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Picks the variant a lead gets. Once a winner is promoted every lead gets
+/// it; before that the lead is hashed into the variants by weight, so the
+/// same lead always lands on the same variant.
+pub async fn assign(
+    pool: &SqlitePool,
+    experiment: &Experiment,
+    lead_id: i64,
+) -> Result<Option<ExperimentVariant>, sqlx::Error> {
+    let variants = variants(pool, experiment.id).await?;
+
+    let winner_id = match experiment.winner_variant_id {
+        Some(winner_id) => Some(winner_id),
+        None => promote_if_decided(pool, experiment).await?,
+    };
+    if let Some(winner_id) = winner_id {
+        return Ok(variants.into_iter().find(|v| v.id == winner_id));
+    }
+
+    let total: i64 = variants.iter().map(|v| v.weight).sum();
+    if total <= 0 {
+        return Ok(None);
+    }
+
+    let digest = Sha256::digest(format!("{}:{}", experiment.id, lead_id));
+    let mut bucket =
+        (u64::from_be_bytes(digest[..8].try_into().unwrap_or_default()) % total as u64) as i64;
+
+    Ok(variants.into_iter().find(|v| {
+        if bucket < v.weight {
+            return true;
+        }
+        bucket -= v.weight;
+        false
+    }))
+}
+
+/// Sent, replied, opened and clicked counts of each variant.
+pub async fn variant_stats(
+    pool: &SqlitePool,
+    experiment_id: i64,
+) -> Result<Vec<VariantStats>, sqlx::Error> {
+    stats_sent_before(pool, experiment_id, None).await
+}
+
+/// The counts of each variant over the messages sent before `cutoff`, or
+/// over all of them without one.
+async fn stats_sent_before(
+    pool: &SqlitePool,
+    experiment_id: i64,
+    cutoff: Option<&str>,
+) -> Result<Vec<VariantStats>, sqlx::Error> {
+    let counts: Vec<(i64, i64, i64, i64, i64, i64)> = sqlx::query_as(
+        r#"
+        SELECT variant_id,
+            COUNT(sent_at),
+            COUNT(CASE WHEN sent_at IS NOT NULL AND reply_received_at IS NOT NULL THEN 1 END),
+            COUNT(CASE WHEN sent_at IS NOT NULL AND tracking = 1 THEN 1 END),
+            COUNT(CASE WHEN sent_at IS NOT NULL AND tracking = 1 AND open_count > 0 THEN 1 END),
+            COUNT(CASE WHEN sent_at IS NOT NULL AND tracking = 1 AND click_count > 0 THEN 1 END)
+        FROM messages
+        WHERE variant_id IN (SELECT id FROM experiment_variants WHERE experiment_id = ?)
+          AND (? IS NULL OR sent_at < ?)
+        GROUP BY variant_id
+        "#,
+    )
+    .bind(experiment_id)
+    .bind(cutoff)
+    .bind(cutoff)
+    .fetch_all(pool)
+    .await?;
+
+    let stats = variants(pool, experiment_id)
+        .await?
+        .into_iter()
+        .map(|variant| {
+            let (_, sent, replied, tracked, opened, clicked) = counts
+                .iter()
+                .find(|c| c.0 == variant.id)
+                .copied()
+                .unwrap_or_default();
+            VariantStats {
+                variant,
+                sent,
+                replied,
+                tracked,
+                opened,
+                clicked,
+                reply_rate: rate(replied, sent),
+                open_rate: rate(opened, tracked),
+                click_rate: rate(clicked, tracked),
+            }
+        })
+        .collect();
+
+    Ok(stats)
+}
+
+pub async fn promote(
+    pool: &SqlitePool,
+    experiment_id: i64,
+    variant_id: i64,
+) -> Result<Option<Experiment>, sqlx::Error> {
+    sqlx::query_as::<_, Experiment>(
+        r#"
+        UPDATE experiments SET winner_variant_id = ?, promoted_at = ?
+        WHERE id = ?
+        RETURNING id, name, auto_promote_after, settle_days, winner_variant_id, promoted_at, created_at
+        "#,
+    )
+    .bind(variant_id)
+    .bind(Utc::now().to_rfc3339())
+    .bind(experiment_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Promotes the variant with the best reply rate (open rate breaks ties)
+/// once every variant has reached the experiment's sample threshold. Only
+/// sends older than the experiment's `settle_days` count, so leads have had
+/// time to reply.
+async fn promote_if_decided(
+    pool: &SqlitePool,
+    experiment: &Experiment,
+) -> Result<Option<i64>, sqlx::Error> {
+    let Some(threshold) = experiment.auto_promote_after else {
+        return Ok(None);
+    };
+
+    let cutoff = (Utc::now() - Duration::days(experiment.settle_days)).to_rfc3339();
+    let stats = stats_sent_before(pool, experiment.id, Some(&cutoff)).await?;
+    if stats.is_empty() || stats.iter().any(|s| s.sent < threshold) {
+        return Ok(None);
+    }
+
+    let Some(winner) = stats.iter().max_by(|a, b| {
+        a.reply_rate
+            .partial_cmp(&b.reply_rate)
+            .unwrap_or(Ordering::Equal)
+            .then(
+                a.open_rate
+                    .partial_cmp(&b.open_rate)
+                    .unwrap_or(Ordering::Equal),
+            )
+            // Prefer the earlier variant on a full tie.
+            .then(b.variant.id.cmp(&a.variant.id))
+    }) else {
+        return Ok(None);
+    };
+
+    promote(pool, experiment.id, winner.variant.id).await?;
+    info!(
+        "Experiment {}: promoted variant {} ({:.1}% replies over {} settled sends)",
+        experiment.id,
+        winner.variant.name,
+        winner.reply_rate * 100.0,
+        winner.sent
+    );
+
+    Ok(Some(winner.variant.id))
+}
+
+fn rate(count: i64, total: i64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    async fn pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        sqlx::query("INSERT INTO leads (id, name) VALUES (1, 'Ada')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool
+    }
+
+    /// An experiment with a variant per weight, in that order.
+    async fn experiment(
+        pool: &SqlitePool,
+        auto_promote_after: Option<i64>,
+        weights: &[i64],
+    ) -> (Experiment, Vec<i64>) {
+        let now = Utc::now().to_rfc3339();
+        let experiment_id: i64 = sqlx::query_scalar(
+            "INSERT INTO experiments (name, auto_promote_after, created_at) VALUES ('Subject lines', ?, ?) RETURNING id",
+        )
+        .bind(auto_promote_after)
+        .bind(&now)
+        .fetch_one(pool)
+        .await
+        .unwrap();
+
+        let mut variant_ids = Vec::new();
+        for (i, weight) in weights.iter().enumerate() {
+            let variant_id = sqlx::query_scalar(
+                "INSERT INTO experiment_variants (experiment_id, name, weight, created_at) VALUES (?, ?, ?, ?) RETURNING id",
+            )
+            .bind(experiment_id)
+            .bind(format!("Variant {}", i + 1))
+            .bind(weight)
+            .bind(&now)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+            variant_ids.push(variant_id);
+        }
+
+        let experiment = fetch(pool, experiment_id).await.unwrap().unwrap();
+        (experiment, variant_ids)
+    }
+
+    /// Records a tracked send of the variant `days_ago`.
+    async fn send(pool: &SqlitePool, variant_id: i64, days_ago: i64, replied: bool, opened: bool) {
+        let sent_at = (Utc::now() - Duration::days(days_ago)).to_rfc3339();
+        sqlx::query(
+            "INSERT INTO messages (leads_id, status, sent_at, reply_received_at, tracking, open_count, variant_id) VALUES (1, 'sent', ?, ?, 1, ?, ?)",
+        )
+        .bind(&sent_at)
+        .bind(replied.then_some(&sent_at))
+        .bind(opened as i64)
+        .bind(variant_id)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    async fn assigned(pool: &SqlitePool, experiment_id: i64, lead_id: i64) -> Option<i64> {
+        let experiment = fetch(pool, experiment_id).await.unwrap().unwrap();
+        assign(pool, &experiment, lead_id)
+            .await
+            .unwrap()
+            .map(|variant| variant.id)
+    }
+
+    #[tokio::test]
+    async fn splits_leads_by_weight() {
+        let pool = pool().await;
+        let (experiment, variants) = experiment(&pool, None, &[1, 3]).await;
+
+        let mut heavy = 0;
+        for lead_id in 0..2000 {
+            if assigned(&pool, experiment.id, lead_id).await == Some(variants[1]) {
+                heavy += 1;
+            }
+        }
+        assert!((1400..1600).contains(&heavy), "{} of 2000 leads", heavy);
+    }
+
+    #[tokio::test]
+    async fn same_lead_keeps_its_variant_within_an_experiment() {
+        let pool = pool().await;
+        let (first, first_variants) = experiment(&pool, None, &[1, 1]).await;
+        let (second, second_variants) = experiment(&pool, None, &[1, 1]).await;
+        let position =
+            |variants: &[i64], id: Option<i64>| variants.iter().position(|v| Some(*v) == id);
+
+        let mut differs = false;
+        for lead_id in 0..50 {
+            let variant = assigned(&pool, first.id, lead_id).await;
+            assert!(variant.is_some());
+            assert_eq!(assigned(&pool, first.id, lead_id).await, variant);
+
+            let other = assigned(&pool, second.id, lead_id).await;
+            differs |= position(&first_variants, variant) != position(&second_variants, other);
+        }
+        // The hash includes the experiment, so leads are not always split
+        // the same way.
+        assert!(differs);
+    }
+
+    #[tokio::test]
+    async fn zero_weights_assign_nothing() {
+        let pool = pool().await;
+        let (none, _) = experiment(&pool, None, &[0, 0]).await;
+        let (one, variants) = experiment(&pool, None, &[0, 1]).await;
+
+        for lead_id in 0..20 {
+            assert_eq!(assigned(&pool, none.id, lead_id).await, None);
+            assert_eq!(assigned(&pool, one.id, lead_id).await, Some(variants[1]));
+        }
+    }
+
+    #[tokio::test]
+    async fn promotes_once_every_variant_has_enough_settled_sends() {
+        let pool = pool().await;
+        let (experiment, variants) = experiment(&pool, Some(2), &[1, 1]).await;
+        send(&pool, variants[0], 5, true, true).await;
+        send(&pool, variants[0], 5, false, true).await;
+        send(&pool, variants[1], 5, true, true).await;
+        // Too recent to count, with the default three settle days.
+        send(&pool, variants[1], 1, true, true).await;
+
+        assigned(&pool, experiment.id, 1).await;
+        let undecided = fetch(&pool, experiment.id).await.unwrap().unwrap();
+        assert_eq!(undecided.winner_variant_id, None);
+
+        send(&pool, variants[1], 4, true, true).await;
+        for lead_id in 0..10 {
+            assert_eq!(
+                assigned(&pool, experiment.id, lead_id).await,
+                Some(variants[1])
+            );
+        }
+        let decided = fetch(&pool, experiment.id).await.unwrap().unwrap();
+        assert_eq!(decided.winner_variant_id, Some(variants[1]));
+        assert!(decided.promoted_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn breaks_reply_rate_ties_on_open_rate() {
+        let pool = pool().await;
+        let (experiment, variants) = experiment(&pool, Some(1), &[1, 1]).await;
+        send(&pool, variants[0], 5, true, false).await;
+        send(&pool, variants[1], 5, true, true).await;
+
+        assert_eq!(assigned(&pool, experiment.id, 1).await, Some(variants[1]));
+    }
+
+    #[tokio::test]
+    async fn breaks_full_ties_on_the_earlier_variant() {
+        let pool = pool().await;
+        let (experiment, variants) = experiment(&pool, Some(1), &[1, 1]).await;
+        send(&pool, variants[1], 5, false, true).await;
+        send(&pool, variants[0], 5, false, true).await;
+
+        assert_eq!(assigned(&pool, experiment.id, 1).await, Some(variants[0]));
+    }
+
+    #[tokio::test]
+    async fn never_promotes_without_a_threshold() {
+        let pool = pool().await;
+        let (experiment, variants) = experiment(&pool, None, &[1, 1]).await;
+        send(&pool, variants[0], 5, true, true).await;
+        send(&pool, variants[1], 5, false, false).await;
+
+        assigned(&pool, experiment.id, 1).await;
+        let experiment = fetch(&pool, experiment.id).await.unwrap().unwrap();
+        assert_eq!(experiment.winner_variant_id, None);
+    }
+}
@@ -0,0 +1,458 @@
+use std::collections::BTreeMap;
+
+use chrono::{NaiveDate, Utc};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use sqlx::types::Json;
+
+use crate::models::{CustomField, CustomFieldRequest, CustomFieldType, Lead};
+use crate::richtext;
+
+const FIELD_COLUMNS: &str =
+    "id, workspace_id, key, label, field_type, required, options, created_at";
+
+/// Template variables every lead has, which custom fields cannot shadow.
+const BUILT_IN_VARIABLES: &[&str] = &["name", "first_name", "email", "phone"];
+
+/// Checks the definition of a new field.
+pub fn validate_definition(request: &CustomFieldRequest) -> Result<(), String> {
+    if !is_key(&request.key) {
+        return Err(
+            "Key must start with a lowercase letter and contain only lowercase letters, \
+             digits and underscores"
+                .to_string(),
+        );
+    }
+    if BUILT_IN_VARIABLES.contains(&request.key.as_str()) {
+        return Err(format!("{} is a built-in lead field", request.key));
+    }
+
+    match request.field_type {
+        CustomFieldType::Enum if request.options.is_empty() => {
+            Err("Enum fields need options".to_string())
+        }
+        CustomFieldType::Enum if request.options.iter().any(|o| o.trim().is_empty()) => {
+            Err("Enum options cannot be empty".to_string())
+        }
+        CustomFieldType::Enum => Ok(()),
+        _ if !request.options.is_empty() => Err("Only enum fields have options".to_string()),
+        _ => Ok(()),
+    }
+}
+
+pub async fn create(
+    pool: &SqlitePool,
+    workspace_id: i64,
+    request: &CustomFieldRequest,
+) -> Result<CustomField, sqlx::Error> {
+    let options: Vec<String> = request
+        .options
+        .iter()
+        .map(|o| o.trim().to_string())
+        .collect();
+    let label = request
+        .label
+        .as_deref()
+        .map(str::trim)
+        .filter(|label| !label.is_empty())
+        .unwrap_or(&request.key);
+
+    sqlx::query_as::<_, CustomField>(&format!(
+        r#"
+        INSERT INTO custom_fields (workspace_id, key, label, field_type, required, options, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        RETURNING {}
+        "#,
+        FIELD_COLUMNS
+    ))
+    .bind(workspace_id)
+    .bind(&request.key)
+    .bind(label)
+    .bind(request.field_type.as_str())
+    .bind(request.required)
+    .bind(Json(options))
+    .bind(Utc::now().to_rfc3339())
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn list(pool: &SqlitePool, workspace_id: i64) -> Result<Vec<CustomField>, sqlx::Error> {
+    sqlx::query_as::<_, CustomField>(&format!(
+        "SELECT {} FROM custom_fields WHERE workspace_id = ? ORDER BY id ASC",
+        FIELD_COLUMNS
+    ))
+    .bind(workspace_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Checks lead values against the workspace's fields and normalizes them.
+/// Empty values are dropped.
+pub fn validate_values(
+    definitions: &[CustomField],
+    values: &BTreeMap<String, Value>,
+) -> Result<BTreeMap<String, Value>, String> {
+    let mut valid = BTreeMap::new();
+
+    for (key, value) in values {
+        let Some(field) = definitions.iter().find(|field| &field.key == key) else {
+            return Err(format!("Unknown custom field {}", key));
+        };
+        if value.is_null() || value.as_str().is_some_and(|s| s.trim().is_empty()) {
+            continue;
+        }
+
+        let Some(value) = normalize(field, value) else {
+            return Err(if field.options.is_empty() {
+                format!("{} is not a valid {}", field.label, field.field_type)
+            } else {
+                format!(
+                    "{} must be one of {}",
+                    field.label,
+                    field.options.join(", ")
+                )
+            });
+        };
+        valid.insert(key.clone(), value);
+    }
+
+    if let Some(field) = definitions
+        .iter()
+        .find(|field| field.required && !valid.contains_key(&field.key))
+    {
+        return Err(format!("{} is required", field.label));
+    }
+
+    Ok(valid)
+}
+
+fn normalize(field: &CustomField, value: &Value) -> Option<Value> {
+    let field_type = field.field_type.parse().ok()?;
+    let text = value.as_str().map(str::trim);
+
+    match field_type {
+        CustomFieldType::Text => text.map(Value::from),
+        CustomFieldType::Number => match value {
+            Value::Number(_) => Some(value.clone()),
+            // Whole numbers stay integers so they compare as typed in filters.
+            _ => match text?.parse::<i64>() {
+                Ok(number) => Some(Value::from(number)),
+                Err(_) => text?
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|n| n.is_finite())
+                    .map(Value::from),
+            },
+        },
+        CustomFieldType::Boolean => match value {
+            Value::Bool(_) => Some(value.clone()),
+            _ => text?.parse::<bool>().ok().map(Value::from),
+        },
+        CustomFieldType::Date => {
+            let text = text?;
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .map(|_| Value::from(text))
+        }
+        CustomFieldType::Url => {
+            let text = text?;
+            reqwest::Url::parse(text)
+                .ok()
+                .filter(|url| matches!(url.scheme(), "http" | "https"))
+                .map(|_| Value::from(text))
+        }
+        CustomFieldType::Enum => {
+            let text = text?;
+            field
+                .options
+                .iter()
+                .find(|option| option.eq_ignore_ascii_case(text))
+                .map(|option| Value::from(option.as_str()))
+        }
+    }
+}
+
+/// Parses `key:value` filters separated by commas.
+pub fn parse_filters(filters: &str) -> Result<Vec<(String, String)>, String> {
+    filters
+        .split(',')
+        .filter(|filter| !filter.trim().is_empty())
+        .map(|filter| match filter.split_once(':') {
+            Some((key, value)) if is_key(key.trim()) => {
+                Ok((key.trim().to_string(), value.trim().to_string()))
+            }
+            _ => Err(format!(
+                "Invalid field filter {:?}, expected key:value",
+                filter
+            )),
+        })
+        .collect()
+}
+
+/// JSON path of a field in `leads.fields`.
+pub fn json_path(key: &str) -> String {
+    format!("$.\"{}\"", key)
+}
+
+/// The value of a template variable for the lead: `name`, `first_name`,
+/// `email`, `phone` or a custom field key.
+pub fn variable(lead: &Lead, name: &str) -> Option<String> {
+    let value = match name {
+        "name" => Some(lead.name.clone()),
+        "first_name" => lead.name.split_whitespace().next().map(str::to_string),
+        "email" => lead.email.clone(),
+        "phone" => lead.phone.clone(),
+        _ => lead.fields.get(name).map(value_text),
+    };
+    value.filter(|value| !value.trim().is_empty())
+}
+
+pub fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Replaces the `{{variable}}` and `{{variable|fallback}}` placeholders in
+/// `template` with the lead's values, escaped for HTML bodies. Variables the
+/// lead has no value for use the fallback, or are left out.
+pub fn render(template: &str, lead: &Lead, html: bool) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}").map(|end| start + end) else {
+            break;
+        };
+        let (name, fallback) = match rest[start + 2..end].split_once('|') {
+            Some((name, fallback)) => (name.trim(), Some(fallback.trim())),
+            None => (rest[start + 2..end].trim(), None),
+        };
+
+        out.push_str(&rest[..start]);
+        if is_key(name) {
+            let value = variable(lead, name).or_else(|| fallback.map(str::to_string));
+            let value = value.unwrap_or_default();
+            out.push_str(&if html {
+                richtext::escape(&value)
+            } else {
+                value
+            });
+        } else {
+            out.push_str(&rest[start..end + 2]);
+        }
+        rest = &rest[end + 2..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+pub fn is_key(key: &str) -> bool {
+    key.len() <= 64
+        && key.starts_with(|c: char| c.is_ascii_lowercase())
+        && key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn field(key: &str, field_type: &str, required: bool, options: &[&str]) -> CustomField {
+        CustomField {
+            id: 1,
+            workspace_id: 1,
+            key: key.to_string(),
+            label: key.replace('_', " "),
+            field_type: field_type.to_string(),
+            required,
+            options: Json(options.iter().map(|o| o.to_string()).collect()),
+            created_at: "2026-03-01T09:00:00Z".to_string(),
+        }
+    }
+
+    fn definitions() -> Vec<CustomField> {
+        vec![
+            field("company", "text", true, &[]),
+            field("employees", "number", false, &[]),
+            field("customer", "boolean", false, &[]),
+            field("founded", "date", false, &[]),
+            field("website", "url", false, &[]),
+            field("plan", "enum", false, &["Starter", "Business"]),
+        ]
+    }
+
+    fn values(values: Value) -> BTreeMap<String, Value> {
+        serde_json::from_value(values).unwrap()
+    }
+
+    fn lead(fields: Value) -> Lead {
+        Lead {
+            id: 1,
+            name: "Ada Lovelace".to_string(),
+            email: Some("ada@example.org".to_string()),
+            phone: None,
+            email_undeliverable_at: None,
+            email_undeliverable_reason: None,
+            workspace_id: 1,
+            consent_status: "unknown".to_string(),
+            consent_source: None,
+            consent_at: None,
+            score: 0,
+            score_updated_at: None,
+            fields: Json(values(fields)),
+        }
+    }
+
+    #[test]
+    fn normalizes_values_to_their_type() {
+        let valid = validate_values(
+            &definitions(),
+            &values(json!({
+                "company": "  Acme  ",
+                "employees": "250",
+                "customer": "true",
+                "founded": "2019-04-01",
+                "website": "https://acme.example",
+                "plan": "business",
+            })),
+        )
+        .unwrap();
+
+        assert_eq!(
+            Value::from(serde_json::Map::from_iter(valid)),
+            json!({
+                "company": "Acme",
+                "employees": 250,
+                "customer": true,
+                "founded": "2019-04-01",
+                "website": "https://acme.example",
+                "plan": "Business",
+            })
+        );
+    }
+
+    #[test]
+    fn keeps_decimals_and_typed_values() {
+        let valid = validate_values(
+            &definitions(),
+            &values(json!({"company": "Acme", "employees": "2.5", "customer": false})),
+        )
+        .unwrap();
+
+        assert_eq!(valid["employees"], json!(2.5));
+        assert_eq!(valid["customer"], json!(false));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let invalid = |key: &str, value: Value| {
+            let mut values = values(json!({"company": "Acme"}));
+            values.insert(key.to_string(), value);
+            validate_values(&definitions(), &values).unwrap_err()
+        };
+
+        assert_eq!(
+            invalid("employees", json!("many")),
+            "employees is not a valid number"
+        );
+        assert_eq!(
+            invalid("employees", json!("NaN")),
+            "employees is not a valid number"
+        );
+        assert_eq!(
+            invalid("customer", json!("yes")),
+            "customer is not a valid boolean"
+        );
+        assert_eq!(
+            invalid("founded", json!("01/04/2019")),
+            "founded is not a valid date"
+        );
+        assert_eq!(
+            invalid("website", json!("ftp://acme.example")),
+            "website is not a valid url"
+        );
+        assert_eq!(
+            invalid("plan", json!("Free")),
+            "plan must be one of Starter, Business"
+        );
+        assert_eq!(
+            invalid("industry", json!("Retail")),
+            "Unknown custom field industry"
+        );
+    }
+
+    #[test]
+    fn requires_required_fields() {
+        let definitions = definitions();
+        assert_eq!(
+            validate_values(&definitions, &values(json!({"plan": "Starter"}))).unwrap_err(),
+            "company is required"
+        );
+        assert_eq!(
+            validate_values(&definitions, &values(json!({"company": "  "}))).unwrap_err(),
+            "company is required"
+        );
+        assert_eq!(
+            validate_values(
+                &definitions,
+                &values(json!({"company": "Acme", "plan": null}))
+            )
+            .unwrap()
+            .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn renders_variables_and_fallbacks() {
+        let lead = lead(json!({"company": "Acme"}));
+
+        assert_eq!(
+            render("Hi {{first_name}} at {{ company }}", &lead, false),
+            "Hi Ada at Acme"
+        );
+        assert_eq!(
+            render(
+                "Hi {{phone|there}}, {{plan | the free plan}}{{city}}.",
+                &lead,
+                false
+            ),
+            "Hi there, the free plan."
+        );
+    }
+
+    #[test]
+    fn escapes_values_in_html() {
+        let lead = lead(json!({"company": "Smith & <Sons>"}));
+
+        assert_eq!(
+            render("<p>{{company}}</p>", &lead, true),
+            "<p>Smith &amp; &lt;Sons&gt;</p>"
+        );
+        assert_eq!(render("{{company}}", &lead, false), "Smith & <Sons>");
+        assert_eq!(
+            render("<p>{{city|\"you\"}}</p>", &lead, true),
+            "<p>&quot;you&quot;</p>"
+        );
+    }
+
+    #[test]
+    fn leaves_other_braces_as_written() {
+        let lead = lead(json!({}));
+
+        assert_eq!(render("Hi {{name", &lead, false), "Hi {{name");
+        assert_eq!(render("{{name}} {{", &lead, false), "Ada Lovelace {{");
+        assert_eq!(render("{{ Not A Key }}", &lead, false), "{{ Not A Key }}");
+        assert_eq!(
+            render("{{a-b}} {{name}}", &lead, false),
+            "{{a-b}} Ada Lovelace"
+        );
+        assert_eq!(render("{{}}", &lead, false), "{{}}");
+    }
+}
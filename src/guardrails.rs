@@ -0,0 +1,321 @@
+use std::sync::LazyLock;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::models::{AiPolicy, Lead};
+
+/// Appended to the system prompt so the model rates its own reply.
+pub const CONFIDENCE_INSTRUCTION: &str = "\nAfter the reply, add a last line \
+`Confidence: <number between 0 and 1>` saying how sure you are that the reply is accurate \
+and appropriate to send without review.";
+
+static EMAIL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").unwrap());
+static PHONE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\+?\d[\d ().-]{7,}\d").unwrap());
+static CARD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b(?:\d[ -]?){12,18}\d\b").unwrap());
+static SSN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b\d{3}-\d{2}-\d{4}\b").unwrap());
+
+/// Checks a policy can be applied, i.e. its patterns compile.
+pub fn validate(policy: &AiPolicy) -> Result<(), String> {
+    for pattern in &policy.banned_patterns {
+        if let Err(e) = banned_pattern(pattern) {
+            return Err(format!("Invalid banned pattern {:?}: {}", pattern, e));
+        }
+    }
+    if policy
+        .min_confidence
+        .is_some_and(|min| !(0.0..=1.0).contains(&min))
+    {
+        return Err("Min confidence must be between 0 and 1".to_string());
+    }
+    Ok(())
+}
+
+/// Splits the model's `Confidence:` line off its answer.
+pub fn split_confidence(answer: &str) -> (String, Option<f64>) {
+    let answer = answer.trim();
+    let (rest, last) = answer.rsplit_once('\n').unwrap_or(("", answer));
+
+    let confidence = last
+        .trim()
+        .trim_matches('*')
+        .split_once(':')
+        .filter(|(label, _)| label.trim().eq_ignore_ascii_case("confidence"))
+        .and_then(|(_, value)| value.trim().trim_end_matches('.').parse::<f64>().ok());
+
+    match confidence {
+        Some(confidence) => (
+            rest.trim_end().to_string(),
+            Some(confidence.clamp(0.0, 1.0)),
+        ),
+        None => (answer.to_string(), None),
+    }
+}
+
+/// The rules of `policy` the reply breaks. `max_length` is the prompt
+/// template's limit, enforced along with the policy's own.
+pub fn check(
+    policy: &AiPolicy,
+    max_length: Option<usize>,
+    reply: &str,
+    lead: &Lead,
+    confidence: Option<f64>,
+) -> Vec<String> {
+    let mut violations = Vec::new();
+    let lowercase = reply.to_lowercase();
+
+    for phrase in &policy.banned_phrases {
+        if lowercase.contains(&phrase.to_lowercase()) {
+            violations.push(format!("contains banned phrase {:?}", phrase));
+        }
+    }
+
+    for pattern in &policy.banned_patterns {
+        if banned_pattern(pattern).is_ok_and(|regex| regex.is_match(reply)) {
+            violations.push(format!("matches banned pattern {:?}", pattern));
+        }
+    }
+
+    let length = reply.chars().count();
+    if let Some(max) = [policy.max_length, max_length].into_iter().flatten().min()
+        && length > max
+    {
+        violations.push(format!(
+            "is {} characters long, over the {} limit",
+            length, max
+        ));
+    }
+
+    if let Some(signature) = policy.required_signature.as_deref()
+        && !reply.contains(signature.trim())
+    {
+        violations.push("is missing the required signature".to_string());
+    }
+
+    if policy.block_pii {
+        let allowed = policy.required_signature.as_deref().unwrap_or_default();
+        violations.extend(pii(reply, lead, allowed));
+    }
+
+    if let Some(min) = policy.min_confidence {
+        match confidence {
+            Some(confidence) if confidence >= min => {}
+            Some(confidence) => violations.push(format!(
+                "has confidence {:.2}, below the {:.2} threshold",
+                confidence, min
+            )),
+            None => violations.push("has no confidence rating".to_string()),
+        }
+    }
+
+    violations
+}
+
+/// Personal data in the reply that is neither the lead's own contact details
+/// nor part of the `allowed` signature.
+fn pii(reply: &str, lead: &Lead, allowed: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let digits = |text: &str| -> String { text.chars().filter(char::is_ascii_digit).collect() };
+    let lead_phone = lead.phone.as_deref().map(digits).unwrap_or_default();
+    let allowed_digits = digits(allowed);
+
+    if EMAIL.find_iter(reply).any(|m| {
+        let email = m.as_str();
+        !lead
+            .email
+            .as_deref()
+            .is_some_and(|own| own.eq_ignore_ascii_case(email))
+            && !allowed.contains(email)
+    }) {
+        found.push("contains an email address".to_string());
+    }
+
+    let is_card = |number: &str| {
+        let number = digits(number);
+        (13..=19).contains(&number.len()) && luhn(&number)
+    };
+    if CARD.find_iter(reply).any(|m| is_card(m.as_str())) {
+        found.push("contains a card number".to_string());
+    }
+
+    if SSN.is_match(reply) {
+        found.push("contains a social security number".to_string());
+    }
+
+    if PHONE.find_iter(reply).any(|m| {
+        let number = digits(m.as_str());
+        number.len() >= 9
+            && !is_card(m.as_str())
+            && !SSN.is_match(m.as_str())
+            && (lead_phone.is_empty() || !lead_phone.ends_with(&number))
+            && !allowed_digits.contains(&number)
+    }) {
+        found.push("contains a phone number".to_string());
+    }
+
+    found
+}
+
+fn banned_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).case_insensitive(true).build()
+}
+
+fn luhn(number: &str) -> bool {
+    let sum: u32 = number
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, d)| match (i % 2 == 1, d * 2) {
+            (true, doubled) if doubled > 9 => doubled - 9,
+            (true, doubled) => doubled,
+            (false, _) => d,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::types::Json;
+
+    use super::*;
+
+    fn lead() -> Lead {
+        Lead {
+            id: 1,
+            name: "Ada Lovelace".to_string(),
+            email: Some("ada@example.org".to_string()),
+            phone: Some("+1 415 555 0100".to_string()),
+            email_undeliverable_at: None,
+            email_undeliverable_reason: None,
+            workspace_id: 1,
+            consent_status: "unknown".to_string(),
+            consent_source: None,
+            consent_at: None,
+            score: 0,
+            score_updated_at: None,
+            fields: Json(Default::default()),
+        }
+    }
+
+    #[test]
+    fn splits_confidence() {
+        assert_eq!(
+            split_confidence("Hi Ada,\nsure.\n\nConfidence: 0.8"),
+            ("Hi Ada,\nsure.".to_string(), Some(0.8))
+        );
+        assert_eq!(
+            split_confidence("Hi Ada\n**Confidence: 0.9**"),
+            ("Hi Ada".to_string(), Some(0.9))
+        );
+        assert_eq!(
+            split_confidence("Hi Ada\nconfidence: 1.5."),
+            ("Hi Ada".to_string(), Some(1.0))
+        );
+        assert_eq!(
+            split_confidence("Hi Ada\nConfidence: high"),
+            ("Hi Ada\nConfidence: high".to_string(), None)
+        );
+        assert_eq!(
+            split_confidence("Hi Ada, my confidence: total"),
+            ("Hi Ada, my confidence: total".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn passes_clean_replies() {
+        let reply = "Hi Ada, happy to walk you through it. Write back to ada@example.org?";
+        assert!(check(&AiPolicy::default(), None, reply, &lead(), None).is_empty());
+    }
+
+    #[test]
+    fn flags_content_rules() {
+        let policy = AiPolicy {
+            banned_phrases: vec!["Guarantee".to_string()],
+            banned_patterns: vec![r"\bfree\b".to_string()],
+            max_length: Some(100),
+            required_signature: Some("-- Sam".to_string()),
+            ..AiPolicy::default()
+        };
+        let reply = "We GUARANTEE results, and the trial is free.";
+
+        assert_eq!(
+            check(&policy, None, reply, &lead(), None),
+            vec![
+                "contains banned phrase \"Guarantee\"",
+                "matches banned pattern \"\\\\bfree\\\\b\"",
+                "is missing the required signature",
+            ]
+        );
+        // The template's limit applies when it is the lower one.
+        assert_eq!(
+            check(&policy, Some(20), reply, &lead(), None)[2],
+            "is 44 characters long, over the 20 limit"
+        );
+    }
+
+    #[test]
+    fn flags_personal_data() {
+        let policy = AiPolicy {
+            required_signature: Some("Sam, +1 212 555 0199".to_string()),
+            ..AiPolicy::default()
+        };
+        let check = |reply: &str| check(&policy, None, reply, &lead(), None);
+
+        assert!(check("Call you on +1 415 555 0100? Sam, +1 212 555 0199").is_empty());
+        assert_eq!(
+            check("Mail bob@example.com. Sam, +1 212 555 0199"),
+            vec!["contains an email address"]
+        );
+        assert_eq!(
+            check("Card 4111 1111 1111 1111. Sam, +1 212 555 0199"),
+            vec!["contains a card number"]
+        );
+        assert_eq!(
+            check("SSN 123-45-6789. Sam, +1 212 555 0199"),
+            vec!["contains a social security number"]
+        );
+        assert_eq!(
+            check("Call +44 20 7946 0958. Sam, +1 212 555 0199"),
+            vec!["contains a phone number"]
+        );
+    }
+
+    #[test]
+    fn flags_low_confidence() {
+        let policy = AiPolicy {
+            min_confidence: Some(0.7),
+            ..AiPolicy::default()
+        };
+
+        assert!(check(&policy, None, "Hi", &lead(), Some(0.7)).is_empty());
+        assert_eq!(
+            check(&policy, None, "Hi", &lead(), Some(0.5)),
+            vec!["has confidence 0.50, below the 0.70 threshold"]
+        );
+        assert_eq!(
+            check(&policy, None, "Hi", &lead(), None),
+            vec!["has no confidence rating"]
+        );
+    }
+
+    #[test]
+    fn validates_policies() {
+        assert!(validate(&AiPolicy::default()).is_ok());
+        assert!(
+            validate(&AiPolicy {
+                banned_patterns: vec!["(unclosed".to_string()],
+                ..AiPolicy::default()
+            })
+            .is_err()
+        );
+        assert!(
+            validate(&AiPolicy {
+                min_confidence: Some(1.5),
+                ..AiPolicy::default()
+            })
+            .is_err()
+        );
+    }
+}
@@ -1,16 +1,63 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, Uri, header},
+    response::{Html, IntoResponse, Redirect, Response},
     Json,
 };
-use chrono::Utc;
+use chrono::{DateTime, NaiveDate, Utc};
+use mail_parser::mailbox::mbox::MessageIterator as MboxIterator;
 use sqlx::SqlitePool;
-use tracing::{error, info};
+use sqlx::types::Json as SqlJson;
+use tracing::{error, info, warn};
 
+use crate::ai::AiProvider;
+use crate::analytics;
+use crate::attachments::{self, AttachmentError};
+use crate::autoreply;
+use crate::bounce::{find_message_for_address, record_complaint};
+use crate::campaigns::{self, Transition};
+use crate::chat;
+use crate::config::{Config, SmsSettings};
+use crate::drafts;
+use crate::email;
+use crate::experiments;
+use crate::fields;
+use crate::guardrails;
+use crate::inbound::{self, IngestError};
+use crate::intent;
 use crate::models::{
-    AiReplyRequest, ApiError, CreateLeadRequest, Lead, LeadWithDetails, Message, MessageStatus,
-    OutreachLog, ReplyRequest, SendMessageRequest,
+    AddCampaignLeadsRequest, AiDraftRequest, AiDraftResult, AiReplyRequest, AiReview, AiReviewQuery, AiUsageQuery, AiUsageReport, ApiError, ApproveReviewRequest, Attachment, BodyFormat, Campaign, CampaignStatus,
+    CampaignWithDetails, Channel, ChatChannel, ComplaintRequest, ConsentStatus,
+    CreateCampaignRequest, CreateChatChannelRequest, CreateContactRequest, CreateExperimentRequest, CreateLeadRequest, CreateSenderPoolRequest,
+    CreateSenderRequest, CreateSuppressionRequest, CustomField, CustomFieldQuery, CustomFieldRequest, EmailKind, Experiment, ExperimentWithStats, FunnelQuery, FunnelReport, InboundChatRequest, InboundResult,
+    InboundSmsRequest, InboundSmsResult, Lead, LeadContact, LeadQuery, LeadSummary, LeadWithDetails, MboxImportSummary, Message, MessageStatus, OutreachLog, PromoteVariantRequest, PromptTemplate, PromptTemplateQuery, PromptTemplateRequest, ReplyIntent, ReplyRequest, Segment, SegmentQuery, SegmentRequest, SendMessageRequest,
+    SenderIdentity, SenderPool, SenderPoolWithMembers, SenderUsage, SmsKeyword, Suppression,
+    SuppressionKind, TagLeadsResult, TagsRequest, TrackingEvent, UpdateConsentRequest, UpdateLeadFieldsRequest, UploadAttachmentQuery, Workspace, WorkspaceRequest,
+};
+use crate::prompts::{self, NewTemplate};
+use crate::reviews;
+use crate::richtext;
+use crate::scoring;
+use crate::segments::{self, Expr, Op};
+use crate::signing::{verify_body, verify_twilio};
+use crate::sms;
+use crate::suppression::{
+    is_opt_out, lead_suppression, normalize, suppress, suppress_lead, unsubscribe_lead_id,
 };
+use crate::tags;
+use crate::throttle::Throttle;
+use crate::tracking;
+use crate::usage::{self, AiCall, AiCallError};
+
+/// Workspace created by the migrations, used when a request does not name one.
+pub const DEFAULT_WORKSPACE_ID: i64 = 1;
+
+/// AI reply used when no AI provider is configured.
+const CANNED_AI_REPLY: &str = "Thank you for your interest! Our team will follow up shortly.";
 
 type ApiResult<T> = Result<(StatusCode, Json<T>), (StatusCode, Json<ApiError>)>;
 
@@ -40,22 +87,67 @@ pub async fn create_lead(
         ));
     }
 
+    if payload
+        .email
+        .as_deref()
+        .is_some_and(|email| !email::is_valid_address(email))
+    {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Invalid email address"));
+    }
+
+    let workspace_id = payload.workspace_id.unwrap_or(DEFAULT_WORKSPACE_ID);
+    match fetch_workspace(&pool, workspace_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Workspace not found")),
+        Err(e) => {
+            error!("Failed to fetch workspace: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    }
+
+    let lead_fields = validate_lead_fields(&pool, workspace_id, &payload.fields).await?;
+
+    let consent_status = payload.consent_status.unwrap_or(ConsentStatus::Unknown);
+    let consent_at = match consent_status {
+        ConsentStatus::Unknown => payload.consent_at.clone(),
+        _ => payload
+            .consent_at
+            .clone()
+            .or_else(|| Some(Utc::now().to_rfc3339())),
+    };
+
     let result = sqlx::query_as::<_, Lead>(
         r#"
-        INSERT INTO leads (name, email, phone)
-        VALUES (?, ?, ?)
-        RETURNING id, name, email, phone
+        INSERT INTO leads (name, email, phone, workspace_id, consent_status, consent_source, consent_at, fields)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        RETURNING id, name, email, phone, email_undeliverable_at, email_undeliverable_reason, workspace_id, consent_status, consent_source, consent_at, score, score_updated_at, fields
         "#,
     )
     .bind(&payload.name)
     .bind(&payload.email)
     .bind(&payload.phone)
+    .bind(workspace_id)
+    .bind(consent_status.as_str())
+    .bind(&payload.consent_source)
+    .bind(&consent_at)
+    .bind(SqlJson(&lead_fields))
     .fetch_one(&pool)
     .await;
 
     match result {
         Ok(lead) => {
             info!("Lead created with id: {}", lead.id);
+            let lead = match scoring::rescore_lead(&pool, lead.id).await {
+                Ok(Some(scored)) => scored,
+                Ok(None) => lead,
+                Err(e) => {
+                    error!("Failed to score lead {}: {}", lead.id, e);
+                    lead
+                }
+            };
             Ok((StatusCode::CREATED, Json(lead)))
         }
         Err(e) => {
@@ -68,241 +160,3393 @@ pub async fn create_lead(
     }
 }
 
-pub async fn send_message(
+pub async fn update_lead_consent(
     State(pool): State<SqlitePool>,
-    Json(payload): Json<SendMessageRequest>,
-) -> ApiResult<Message> {
-    info!("Enqueueing message for lead_id: {}", payload.lead_id);
+    Path(lead_id): Path<i64>,
+    Json(payload): Json<UpdateConsentRequest>,
+) -> ApiResult<Lead> {
+    info!("Updating consent for lead_id: {}: {:?}", lead_id, payload);
 
-    let lead_exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM leads WHERE id = ?")
-        .bind(payload.lead_id)
-        .fetch_one(&pool)
-        .await;
+    // consent_at records when consent was granted, so it only moves when the
+    // status becomes granted.
+    let granted_at = (payload.consent_status == ConsentStatus::Granted)
+        .then(|| Utc::now().to_rfc3339());
+    let result = sqlx::query_as::<_, Lead>(
+        r#"
+        UPDATE leads
+        SET consent_status = ?1, consent_source = ?2,
+            consent_at = CASE WHEN ?3 IS NOT NULL AND consent_status != ?1 THEN ?3 ELSE consent_at END
+        WHERE id = ?4
+        RETURNING id, name, email, phone, email_undeliverable_at, email_undeliverable_reason, workspace_id, consent_status, consent_source, consent_at, score, score_updated_at, fields
+        "#,
+    )
+    .bind(payload.consent_status.as_str())
+    .bind(&payload.consent_source)
+    .bind(&granted_at)
+    .bind(lead_id)
+    .fetch_optional(&pool)
+    .await;
 
-    match lead_exists {
-        Ok(count) if count == 0 => {
-            return Err(api_error(StatusCode::NOT_FOUND, "Lead not found"));
+    match result {
+        Ok(Some(lead)) => Ok((StatusCode::OK, Json(lead))),
+        Ok(None) => Err(api_error(StatusCode::NOT_FOUND, "Lead not found")),
+        Err(e) => {
+            error!("Failed to update consent: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to update consent",
+            ))
         }
+    }
+}
+
+/// Replaces the values of a lead's custom fields.
+pub async fn update_lead_fields(
+    State(pool): State<SqlitePool>,
+    Path(lead_id): Path<i64>,
+    Json(payload): Json<UpdateLeadFieldsRequest>,
+) -> ApiResult<Lead> {
+    info!("Updating fields for lead_id: {}: {:?}", lead_id, payload);
+
+    let lead = match fetch_lead(&pool, lead_id).await {
+        Ok(Some(lead)) => lead,
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Lead not found")),
         Err(e) => {
-            error!("Failed to check lead existence: {}", e);
+            error!("Failed to fetch lead: {}", e);
             return Err(api_error(
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Database error",
             ));
         }
-        _ => {}
+    };
+    let lead_fields = validate_lead_fields(&pool, lead.workspace_id, &payload.fields).await?;
+
+    let result = sqlx::query("UPDATE leads SET fields = ? WHERE id = ?")
+        .bind(SqlJson(&lead_fields))
+        .bind(lead.id)
+        .execute(&pool)
+        .await;
+    if let Err(e) = result {
+        error!("Failed to update lead fields: {}", e);
+        return Err(api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to update lead fields",
+        ));
     }
 
-    let now = Utc::now().to_rfc3339();
-    let status = MessageStatus::Enqueued.as_str();
+    // Profile scoring rules can use custom fields.
+    match scoring::rescore_lead(&pool, lead.id).await {
+        Ok(Some(lead)) => Ok((StatusCode::OK, Json(lead))),
+        Ok(None) => Err(api_error(StatusCode::NOT_FOUND, "Lead not found")),
+        Err(e) => {
+            error!("Failed to score lead {}: {}", lead.id, e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ))
+        }
+    }
+}
 
-    let result = sqlx::query_as::<_, Message>(
-        r#"
-        INSERT INTO messages (leads_id, message_sent, created_at, status)
-        VALUES (?, ?, ?, ?)
-        RETURNING id, leads_id, message_sent, sent_at, reply_received, reply_received_at, ai_reply, ai_reply_sent, created_at, status, follow_up_at, closed_at
-        "#,
-    )
-    .bind(payload.lead_id)
-    .bind(&payload.message)
-    .bind(&now)
-    .bind(status)
-    .fetch_one(&pool)
+/// Checks custom field values against the fields the workspace defines.
+async fn validate_lead_fields(
+    pool: &SqlitePool,
+    workspace_id: i64,
+    values: &BTreeMap<String, serde_json::Value>,
+) -> Result<BTreeMap<String, serde_json::Value>, (StatusCode, Json<ApiError>)> {
+    let definitions = fields::list(pool, workspace_id).await.map_err(|e| {
+        error!("Failed to fetch custom fields: {}", e);
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+    })?;
+
+    fields::validate_values(&definitions, values)
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))
+}
+
+pub async fn add_lead_tags(
+    State(pool): State<SqlitePool>,
+    Path(lead_id): Path<i64>,
+    Json(payload): Json<TagsRequest>,
+) -> ApiResult<Vec<String>> {
+    info!("Tagging lead_id: {}: {:?}", lead_id, payload);
+
+    let tags = match tags::normalize_all(&payload.tags) {
+        Ok(tags) => tags,
+        Err(e) => return Err(api_error(StatusCode::BAD_REQUEST, &e)),
+    };
+    match fetch_lead(&pool, lead_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Lead not found")),
+        Err(e) => {
+            error!("Failed to fetch lead: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    }
+
+    let result = async {
+        tags::add(&pool, &[lead_id], &tags).await?;
+        tags::lead_tags(&pool, lead_id).await
+    }
     .await;
 
     match result {
-        Ok(message) => {
-            log_outreach(&pool, message.id, MessageStatus::Enqueued).await;
-            info!("Message enqueued with id: {}", message.id);
-            Ok((StatusCode::CREATED, Json(message)))
-        }
+        Ok(tags) => Ok((StatusCode::OK, Json(tags))),
         Err(e) => {
-            error!("Failed to create message: {}", e);
+            error!("Failed to tag lead {}: {}", lead_id, e);
             Err(api_error(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to create message",
+                "Failed to tag lead",
             ))
         }
     }
 }
 
-pub async fn reply_to_message(
+pub async fn remove_lead_tag(
     State(pool): State<SqlitePool>,
-    Json(payload): Json<ReplyRequest>,
-) -> ApiResult<Message> {
-    info!(
-        "Recording reply for message_id: {}, reply: {}",
-        payload.message_id, payload.reply
-    );
-
-    let now = Utc::now().to_rfc3339();
-    let status = MessageStatus::Replied.as_str();
+    Path((lead_id, tag)): Path<(i64, String)>,
+) -> ApiResult<Vec<String>> {
+    info!("Removing tag {} from lead_id: {}", tag, lead_id);
 
-    let result = sqlx::query_as::<_, Message>(
-        r#"
-        UPDATE messages
-        SET reply_received = ?, reply_received_at = ?, status = ?
-        WHERE id = ?
-        RETURNING id, leads_id, message_sent, sent_at, reply_received, reply_received_at, ai_reply, ai_reply_sent, created_at, status, follow_up_at, closed_at
-        "#,
-    )
-    .bind(&payload.reply)
-    .bind(&now)
-    .bind(status)
-    .bind(payload.message_id)
-    .fetch_optional(&pool)
+    let result = async {
+        let removed = tags::remove(&pool, lead_id, &tag.trim().to_lowercase()).await?;
+        let tags = tags::lead_tags(&pool, lead_id).await?;
+        Ok::<_, sqlx::Error>((removed, tags))
+    }
     .await;
 
     match result {
-        Ok(Some(message)) => {
-            log_outreach(&pool, message.id, MessageStatus::Replied).await;
-            info!("Reply recorded for message_id: {}", message.id);
-            Ok((StatusCode::OK, Json(message)))
-        }
-        Ok(None) => Err(api_error(StatusCode::NOT_FOUND, "Message not found")),
+        Ok((true, tags)) => Ok((StatusCode::OK, Json(tags))),
+        Ok((false, _)) => Err(api_error(StatusCode::NOT_FOUND, "Tag not found")),
         Err(e) => {
-            error!("Failed to update message: {}", e);
+            error!("Failed to remove tag from lead {}: {}", lead_id, e);
             Err(api_error(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to update message",
+                "Failed to remove tag",
             ))
         }
     }
 }
 
-pub async fn ai_reply(
+pub async fn create_custom_field(
     State(pool): State<SqlitePool>,
-    Json(payload): Json<AiReplyRequest>,
-) -> ApiResult<Message> {
-    info!("Generating AI reply for message_id: {}", payload.message_id);
+    Json(payload): Json<CustomFieldRequest>,
+) -> ApiResult<CustomField> {
+    info!("Creating custom field: {:?}", payload);
 
-    let ai_response = "Thank you for your interest! Our team will follow up shortly.";
-    let status = MessageStatus::AiEnqueued.as_str();
+    if let Err(e) = fields::validate_definition(&payload) {
+        return Err(api_error(StatusCode::BAD_REQUEST, &e));
+    }
 
-    let result = sqlx::query_as::<_, Message>(
-        r#"
-        UPDATE messages
-        SET ai_reply = ?, status = ?
-        WHERE id = ?
-        RETURNING id, leads_id, message_sent, sent_at, reply_received, reply_received_at, ai_reply, ai_reply_sent, created_at, status, follow_up_at, closed_at
-        "#,
-    )
-    .bind(ai_response)
-    .bind(status)
-    .bind(payload.message_id)
-    .fetch_optional(&pool)
-    .await;
+    let workspace_id = payload.workspace_id.unwrap_or(DEFAULT_WORKSPACE_ID);
+    match fetch_workspace(&pool, workspace_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Workspace not found")),
+        Err(e) => {
+            error!("Failed to fetch workspace: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    }
 
-    match result {
-        Ok(Some(message)) => {
-            log_outreach(&pool, message.id, MessageStatus::AiEnqueued).await;
-            info!("AI reply enqueued for message_id: {}", message.id);
-            Ok((StatusCode::OK, Json(message)))
+    match fields::create(&pool, workspace_id, &payload).await {
+        Ok(field) => {
+            info!("Custom field {} created with id: {}", field.key, field.id);
+            Ok((StatusCode::CREATED, Json(field)))
         }
-        Ok(None) => Err(api_error(StatusCode::NOT_FOUND, "Message not found")),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Err(api_error(
+            StatusCode::CONFLICT,
+            "The workspace already has a field with this key",
+        )),
         Err(e) => {
-            error!("Failed to update message: {}", e);
+            error!("Failed to create custom field: {}", e);
             Err(api_error(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to update message",
+                "Failed to create custom field",
             ))
         }
     }
 }
 
-pub async fn get_lead(
+pub async fn list_custom_fields(
     State(pool): State<SqlitePool>,
-    Path(lead_id): Path<i64>,
-) -> ApiResult<LeadWithDetails> {
-    info!("Fetching lead with id: {}", lead_id);
+    Query(query): Query<CustomFieldQuery>,
+) -> ApiResult<Vec<CustomField>> {
+    let workspace_id = query.workspace_id.unwrap_or(DEFAULT_WORKSPACE_ID);
 
-    let lead = sqlx::query_as::<_, Lead>("SELECT id, name, email, phone FROM leads WHERE id = ?")
-        .bind(lead_id)
-        .fetch_optional(&pool)
-        .await;
+    match fields::list(&pool, workspace_id).await {
+        Ok(fields) => Ok((StatusCode::OK, Json(fields))),
+        Err(e) => {
+            error!("Failed to list custom fields: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to list custom fields",
+            ))
+        }
+    }
+}
 
-    let lead = match lead {
-        Ok(Some(lead)) => lead,
-        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Lead not found")),
+pub async fn create_segment(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<SegmentRequest>,
+) -> ApiResult<Segment> {
+    info!("Creating segment: {:?}", payload);
+
+    if payload.name.trim().is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Name is required"));
+    }
+    if let Err(e) = segments::parse(&payload.filter) {
+        return Err(api_error(StatusCode::BAD_REQUEST, &e));
+    }
+
+    let workspace_id = payload.workspace_id.unwrap_or(DEFAULT_WORKSPACE_ID);
+    match fetch_workspace(&pool, workspace_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Workspace not found")),
         Err(e) => {
-            error!("Failed to fetch lead: {}", e);
+            error!("Failed to fetch workspace: {}", e);
             return Err(api_error(
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Database error",
             ));
         }
-    };
-
-    let messages = sqlx::query_as::<_, Message>(
-        r#"
-        SELECT id, leads_id, message_sent, sent_at, reply_received, reply_received_at, ai_reply, ai_reply_sent, created_at, status, follow_up_at, closed_at
-        FROM messages
-        WHERE leads_id = ?
-        ORDER BY created_at DESC
-        "#,
-    )
-    .bind(lead_id)
-    .fetch_all(&pool)
-    .await
-    .unwrap_or_default();
+    }
 
-    let message_ids: Vec<i64> = messages.iter().map(|m| m.id).collect();
+    match segments::create(&pool, workspace_id, &payload).await {
+        Ok(segment) => {
+            info!("Segment {} created with id: {}", segment.name, segment.id);
+            Ok((StatusCode::CREATED, Json(segment)))
+        }
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Err(api_error(
+            StatusCode::CONFLICT,
+            "The workspace already has a segment with this name",
+        )),
+        Err(e) => {
+            error!("Failed to create segment: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create segment",
+            ))
+        }
+    }
+}
 
-    let outreach_logs = if !message_ids.is_empty() {
-        let placeholders = message_ids
-            .iter()
-            .map(|_| "?")
-            .collect::<Vec<_>>()
-            .join(",");
-        let query = format!(
-            "SELECT id, message_id, log_at, step FROM outreach_log WHERE message_id IN ({}) ORDER BY log_at DESC",
-            placeholders
-        );
+pub async fn list_segments(
+    State(pool): State<SqlitePool>,
+    Query(query): Query<SegmentQuery>,
+) -> ApiResult<Vec<Segment>> {
+    let workspace_id = query.workspace_id.unwrap_or(DEFAULT_WORKSPACE_ID);
 
-        let mut query_builder = sqlx::query_as::<_, OutreachLog>(&query);
-        for id in &message_ids {
-            query_builder = query_builder.bind(id);
+    match segments::list(&pool, workspace_id).await {
+        Ok(segments) => Ok((StatusCode::OK, Json(segments))),
+        Err(e) => {
+            error!("Failed to list segments: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to list segments",
+            ))
         }
+    }
+}
 
-        query_builder.fetch_all(&pool).await.unwrap_or_default()
-    } else {
-        vec![]
-    };
-
-    info!(
-        "Found {} messages and {} outreach logs for lead_id: {}",
-        messages.len(),
-        outreach_logs.len(),
-        lead_id
-    );
+pub async fn delete_segment(
+    State(pool): State<SqlitePool>,
+    Path(segment_id): Path<i64>,
+) -> ApiResult<Segment> {
+    info!("Deleting segment with id: {}", segment_id);
 
-    Ok((
-        StatusCode::OK,
-        Json(LeadWithDetails {
-            lead,
-            messages,
-            outreach_logs,
-        }),
-    ))
+    match segments::delete(&pool, segment_id).await {
+        Ok(Some(segment)) => Ok((StatusCode::OK, Json(segment))),
+        Ok(None) => Err(api_error(StatusCode::NOT_FOUND, "Segment not found")),
+        Err(e) => {
+            error!("Failed to delete segment: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ))
+        }
+    }
 }
 
-pub async fn log_outreach(pool: &SqlitePool, message_id: i64, status: MessageStatus) {
-    let now = Utc::now().to_rfc3339();
-    let step = status.as_str();
+/// Tags every lead the segment matches now. Leads matching it later are
+/// not tagged.
+pub async fn tag_segment_leads(
+    State(pool): State<SqlitePool>,
+    Path(segment_id): Path<i64>,
+    Json(payload): Json<TagsRequest>,
+) -> ApiResult<TagLeadsResult> {
+    info!("Tagging the leads of segment {}: {:?}", segment_id, payload);
 
-    let result = sqlx::query(
-        "INSERT INTO outreach_log (message_id, log_at, step) VALUES (?, ?, ?)",
-    )
-    .bind(message_id)
-    .bind(&now)
-    .bind(step)
-    .execute(pool)
+    let tags = match tags::normalize_all(&payload.tags) {
+        Ok(tags) => tags,
+        Err(e) => return Err(api_error(StatusCode::BAD_REQUEST, &e)),
+    };
+    let (segment, filter) = segment_filter(&pool, segment_id).await?;
+
+    let result = async {
+        let lead_ids = segments::lead_ids(&pool, segment.workspace_id, &filter).await?;
+        let tagged = tags::add(&pool, &lead_ids, &tags).await?;
+        Ok::<_, sqlx::Error>(TagLeadsResult {
+            leads: lead_ids.len(),
+            tagged,
+        })
+    }
     .await;
 
-    if let Err(e) = result {
+    match result {
+        Ok(result) => {
+            info!(
+                "Tagged {} leads of segment {}, {} new tags",
+                result.leads, segment_id, result.tagged
+            );
+            Ok((StatusCode::OK, Json(result)))
+        }
+        Err(e) => {
+            error!("Failed to tag the leads of segment {}: {}", segment_id, e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to tag leads",
+            ))
+        }
+    }
+}
+
+/// The segment and its parsed filter.
+async fn segment_filter(
+    pool: &SqlitePool,
+    segment_id: i64,
+) -> Result<(Segment, Expr), (StatusCode, Json<ApiError>)> {
+    let segment = match segments::fetch(pool, segment_id).await {
+        Ok(Some(segment)) => segment,
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Segment not found")),
+        Err(e) => {
+            error!("Failed to fetch segment: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    };
+
+    match segments::parse(&segment.filter) {
+        Ok(filter) => Ok((segment, filter)),
+        Err(e) => {
+            error!("Segment {} has an invalid filter: {}", segment_id, e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Segment has an invalid filter",
+            ))
+        }
+    }
+}
+
+pub async fn send_message(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<SendMessageRequest>,
+) -> ApiResult<Message> {
+    enqueue_message(&pool, &payload, None)
+        .await
+        .map(|message| (StatusCode::CREATED, Json(message)))
+}
+
+/// Validates and enqueues a message for a lead. Shared by `/send` and
+/// campaigns, which pass their id so the scheduler can hold the message
+/// while the campaign is not active.
+async fn enqueue_message(
+    pool: &SqlitePool,
+    payload: &SendMessageRequest,
+    campaign_id: Option<i64>,
+) -> Result<Message, (StatusCode, Json<ApiError>)> {
+    info!("Enqueueing message for lead_id: {}", payload.lead_id);
+
+    let lead = match fetch_lead(pool, payload.lead_id).await {
+        Ok(Some(lead)) => lead,
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Lead not found")),
+        Err(e) => {
+            error!("Failed to check lead existence: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    };
+
+    let variant = match payload.experiment_id {
+        Some(experiment_id) => {
+            let experiment = match experiments::fetch(pool, experiment_id).await {
+                Ok(Some(experiment)) => experiment,
+                Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Experiment not found")),
+                Err(e) => {
+                    error!("Failed to fetch experiment: {}", e);
+                    return Err(api_error(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Database error",
+                    ));
+                }
+            };
+            match experiments::assign(pool, &experiment, lead.id).await {
+                Ok(Some(variant)) => Some(variant),
+                Ok(None) => {
+                    return Err(api_error(
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        "Experiment has no variant to send",
+                    ));
+                }
+                Err(e) => {
+                    error!("Failed to assign experiment variant: {}", e);
+                    return Err(api_error(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Database error",
+                    ));
+                }
+            }
+        }
+        None => None,
+    };
+
+    let channel = payload.channel.unwrap_or_default();
+    let format = payload.format.unwrap_or_default();
+
+    // Template variables are filled in with the lead's values.
+    let message = variant
+        .as_ref()
+        .and_then(|v| v.message.as_deref())
+        .unwrap_or(&payload.message);
+    let message = fields::render(message, &lead, format == BodyFormat::Html);
+    let follow_up = variant
+        .as_ref()
+        .and_then(|v| v.follow_up.clone())
+        .or_else(|| payload.follow_up.clone())
+        .map(|follow_up| fields::render(&follow_up, &lead, false));
+    let subject = variant
+        .as_ref()
+        .and_then(|v| v.subject.as_deref())
+        .or(payload.subject.as_deref())
+        .map(|subject| clean_subject(&fields::render(subject, &lead, false)))
+        .filter(|subject| !subject.is_empty());
+
+    let body = richtext::render(&message, format);
+
+    if body.text.trim().is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Message is empty"));
+    }
+
+    let contact_id = match channel {
+        Channel::Email => {
+            if lead.email_undeliverable_at.is_some() {
+                return Err(api_error(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "Lead email is undeliverable",
+                ));
+            }
+            None
+        }
+        Channel::Sms => {
+            if lead.phone.is_none() {
+                return Err(api_error(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "Lead has no phone number",
+                ));
+            }
+
+            let texts = [
+                Some(sms::compose(&body.text, EmailKind::Initial)),
+                follow_up.clone(),
+            ];
+            for text in texts.iter().flatten() {
+                if let Err(reason) = sms::validate(text) {
+                    return Err(api_error(StatusCode::UNPROCESSABLE_ENTITY, &reason));
+                }
+            }
+            None
+        }
+        Channel::Chat => {
+            let contacts = match chat::lead_contacts(pool, lead.id).await {
+                Ok(contacts) => contacts,
+                Err(e) => {
+                    error!("Failed to fetch lead contacts: {}", e);
+                    return Err(api_error(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Database error",
+                    ));
+                }
+            };
+
+            let contact = match payload.contact_id {
+                Some(contact_id) => contacts.iter().find(|c| c.id == contact_id),
+                None => contacts.first(),
+            };
+
+            match contact {
+                Some(contact) => Some(contact.id),
+                None => {
+                    return Err(api_error(
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        "Lead has no matching chat contact",
+                    ));
+                }
+            }
+        }
+    };
+
+    let consent_status = lead
+        .consent_status
+        .parse::<ConsentStatus>()
+        .unwrap_or(ConsentStatus::Unknown);
+
+    if consent_status == ConsentStatus::Withdrawn {
+        return Err(api_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Lead has withdrawn consent",
+        ));
+    }
+
+    let strict_consent = match fetch_workspace(pool, lead.workspace_id).await {
+        Ok(workspace) => workspace.is_some_and(|w| w.strict_consent),
+        Err(e) => {
+            error!("Failed to fetch workspace: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    };
+
+    if strict_consent && !consent_status.is_lawful_basis() {
+        info!(
+            "Refusing to enqueue for lead {}: no lawful basis in strict consent mode",
+            lead.id
+        );
+        return Err(api_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Lead has no lawful basis for contact",
+        ));
+    }
+
+    match lead_suppression(pool, &lead).await {
+        Ok(None) => {}
+        Ok(Some(suppression)) => {
+            info!(
+                "Refusing to enqueue for lead {}: {} {} is suppressed",
+                lead.id, suppression.kind, suppression.value
+            );
+            return Err(api_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Lead is on the suppression list",
+            ));
+        }
+        Err(e) => {
+            error!("Failed to check suppression list: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    }
+
+    let attachment_ids = variant
+        .as_ref()
+        .and_then(|v| v.attachment_ids.as_ref().map(|ids| ids.0.clone()))
+        .or_else(|| payload.attachment_ids.clone())
+        .unwrap_or_default();
+
+    if !attachment_ids.is_empty() && channel != Channel::Email {
+        return Err(api_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Attachments can only be sent by email",
+        ));
+    }
+
+    if payload.track && channel != Channel::Email {
+        return Err(api_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Open and click tracking is only available for email",
+        ));
+    }
+
+    check_attachments(pool, &attachment_ids).await?;
+
+    let sender_id = match (payload.sender_id, payload.sender_pool_id) {
+        _ if channel != Channel::Email => None,
+        (Some(sender_id), _) => match fetch_sender(pool, sender_id).await {
+            Ok(Some(sender)) if sender.workspace_id == lead.workspace_id => Some(sender.id),
+            Ok(Some(_)) => {
+                return Err(api_error(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "Sender belongs to another workspace",
+                ));
+            }
+            Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Sender not found")),
+            Err(e) => {
+                error!("Failed to fetch sender: {}", e);
+                return Err(api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Database error",
+                ));
+            }
+        },
+        (None, Some(pool_id)) => match next_pool_sender(pool, pool_id, lead.workspace_id).await {
+            Ok(Some(sender_id)) => Some(sender_id),
+            Ok(None) => {
+                return Err(api_error(
+                    StatusCode::NOT_FOUND,
+                    "Sender pool not found or has no sender in the lead's workspace",
+                ));
+            }
+            Err(e) => {
+                error!("Failed to pick sender from pool {}: {}", pool_id, e);
+                return Err(api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Database error",
+                ));
+            }
+        },
+        (None, None) => match sqlx::query_scalar::<_, i64>(
+            "SELECT id FROM sender_identities WHERE workspace_id = ? ORDER BY id ASC LIMIT 1",
+        )
+        .bind(lead.workspace_id)
+        .fetch_optional(pool)
+        .await
+        {
+            Ok(sender_id) => sender_id,
+            Err(e) => {
+                error!("Failed to fetch default sender: {}", e);
+                return Err(api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Database error",
+                ));
+            }
+        },
+    };
+
+    let now = Utc::now().to_rfc3339();
+    let status = MessageStatus::Enqueued.as_str();
+
+    let result = async {
+        let mut tx = pool.begin().await?;
+
+        let message = sqlx::query_as::<_, Message>(
+            r#"
+        INSERT INTO messages (leads_id, message_sent, created_at, status, sender_id, follow_up_body, channel, contact_id, body_format, message_html, tracking, subject, variant_id, campaign_id)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        RETURNING id, leads_id, message_sent, sent_at, reply_received, reply_received_at, ai_reply, ai_reply_sent, created_at, status, follow_up_at, closed_at, sender_id, scheduled_at, email_message_id, follow_up_body, bounced_at, bounce_type, bounce_reason, channel, contact_id, body_format, message_html, tracking, open_count, click_count, subject, variant_id, campaign_id, reply_intent, reply_intent_source, snoozed_until, prompt_template_id
+            "#,
+        )
+        .bind(payload.lead_id)
+        .bind(&body.text)
+        .bind(&now)
+        .bind(status)
+        .bind(sender_id)
+        .bind(&follow_up)
+        .bind(channel.as_str())
+        .bind(contact_id)
+        .bind(format.as_str())
+        .bind(&body.html)
+        .bind(payload.track)
+        .bind(&subject)
+        .bind(variant.as_ref().map(|v| v.id))
+        .bind(campaign_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        attachments::attach(&mut tx, message.id, &attachment_ids).await?;
+
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(message)
+    }
+    .await;
+
+    match result {
+        Ok(message) => {
+            log_outreach(pool, message.id, MessageStatus::Enqueued).await;
+            info!("Message enqueued with id: {}", message.id);
+            Ok(message)
+        }
+        Err(e) => {
+            error!("Failed to create message: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create message",
+            ))
+        }
+    }
+}
+
+/// Checks every attachment has been uploaded.
+async fn check_attachments(
+    pool: &SqlitePool,
+    attachment_ids: &[i64],
+) -> Result<(), (StatusCode, Json<ApiError>)> {
+    for attachment_id in attachment_ids {
+        match attachments::fetch(pool, *attachment_id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Attachment not found")),
+            Err(e) => {
+                error!("Failed to fetch attachment: {}", e);
+                return Err(api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Database error",
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Subjects go in a header, so they are kept to a single line.
+fn clean_subject(subject: &str) -> String {
+    subject.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+pub async fn reply_to_message(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Json(payload): Json<ReplyRequest>,
+) -> ApiResult<Message> {
+    info!(
+        "Recording reply for message_id: {}, reply: {}",
+        payload.message_id, payload.reply
+    );
+
+    match record_reply(&pool, &config, payload.message_id, &payload.reply).await {
+        Ok(Some(message)) => Ok((StatusCode::OK, Json(message))),
+        Ok(None) => Err(api_error(StatusCode::NOT_FOUND, "Message not found")),
+        Err(e) => {
+            error!("Failed to update message: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to update message",
+            ))
+        }
+    }
+}
+
+/// Classifies a lead's reply, stores it on the message and acts on the
+/// intent: unsubscribes are suppressed, interested leads are reported to
+/// the message's owner, and out-of-office replies whose text reads as an
+/// auto-reply are handed to `record_auto_reply`. Other out-of-office
+/// replies are stored like any reply.
+pub async fn record_reply(
+    pool: &SqlitePool,
+    config: &Config,
+    message_id: i64,
+    reply: &str,
+) -> Result<Option<Message>, sqlx::Error> {
+    let classification = intent::classify(pool, config, message_id, reply).await;
+    let reply_intent = classification.as_ref().map(|c| c.intent);
+    let intent_source = classification.as_ref().map(|c| c.source);
+
+    if reply_intent == Some(ReplyIntent::OutOfOffice) && intent::is_auto_reply_text(reply) {
+        return record_auto_reply(pool, message_id, reply, intent_source.unwrap_or("rules")).await;
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let status = MessageStatus::Replied.as_str();
+
+    let message = sqlx::query_as::<_, Message>(
+        r#"
+        UPDATE messages
+        SET reply_received = ?, reply_received_at = ?, status = ?, reply_intent = ?, reply_intent_source = ?
+        WHERE id = ?
+        RETURNING id, leads_id, message_sent, sent_at, reply_received, reply_received_at, ai_reply, ai_reply_sent, created_at, status, follow_up_at, closed_at, sender_id, scheduled_at, email_message_id, follow_up_body, bounced_at, bounce_type, bounce_reason, channel, contact_id, body_format, message_html, tracking, open_count, click_count, subject, variant_id, campaign_id, reply_intent, reply_intent_source, snoozed_until, prompt_template_id
+        "#,
+    )
+    .bind(reply)
+    .bind(&now)
+    .bind(status)
+    .bind(reply_intent.map(|intent| intent.as_str()))
+    .bind(intent_source)
+    .bind(message_id)
+    .fetch_optional(pool)
+    .await?;
+
+    if let Some(message) = &message {
+        log_outreach(pool, message.id, MessageStatus::Replied).await;
+        info!(
+            "Reply recorded for message_id: {} (intent: {})",
+            message.id,
+            reply_intent.map_or("unknown", |intent| intent.as_str())
+        );
+
+        // Opt-out keywords are honoured even if the provider saw another intent.
+        if (reply_intent == Some(ReplyIntent::Unsubscribe) || is_opt_out(reply))
+            && let Some(lead) = fetch_lead(pool, message.leads_id).await?
+        {
+            info!("Reply on message {} is an opt-out", message.id);
+            suppress_lead(pool, &lead, "opted out in reply", "reply").await?;
+        }
+
+        if reply_intent == Some(ReplyIntent::Interested) {
+            intent::notify_owner(pool, message, reply).await;
+        }
+    }
+
+    Ok(message)
+}
+
+/// Records an auto-reply without counting it as a reply: the message keeps
+/// its status, an `auto_replied` step is logged with the auto-reply's text
+/// and follow-ups are snoozed past the return date found in the text.
+/// `source` is what gave it away: `headers`, `ai` or `rules`.
+pub async fn record_auto_reply(
+    pool: &SqlitePool,
+    message_id: i64,
+    reply: &str,
+    source: &str,
+) -> Result<Option<Message>, sqlx::Error> {
+    let until = autoreply::snooze_until(reply, Utc::now());
+
+    // A real reply recorded earlier keeps its intent.
+    let message = sqlx::query_as::<_, Message>(
+        r#"
+        UPDATE messages
+        SET reply_intent = CASE WHEN reply_received IS NULL THEN ? ELSE reply_intent END,
+            reply_intent_source = CASE WHEN reply_received IS NULL THEN ? ELSE reply_intent_source END,
+            snoozed_until = ?
+        WHERE id = ?
+        RETURNING id, leads_id, message_sent, sent_at, reply_received, reply_received_at, ai_reply, ai_reply_sent, created_at, status, follow_up_at, closed_at, sender_id, scheduled_at, email_message_id, follow_up_body, bounced_at, bounce_type, bounce_reason, channel, contact_id, body_format, message_html, tracking, open_count, click_count, subject, variant_id, campaign_id, reply_intent, reply_intent_source, snoozed_until, prompt_template_id
+        "#,
+    )
+    .bind(ReplyIntent::OutOfOffice.as_str())
+    .bind(source)
+    .bind(until.to_rfc3339())
+    .bind(message_id)
+    .fetch_optional(pool)
+    .await?;
+
+    if let Some(message) = &message {
+        log_outreach_body(pool, message.id, MessageStatus::AutoReplied, Some(reply)).await;
+        info!(
+            "Auto-reply on message {} (detected by {}), follow-ups snoozed until {}",
+            message.id, source, until
+        );
+    }
+
+    Ok(message)
+}
+
+pub async fn receive_inbound_email(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    body: Bytes,
+) -> ApiResult<InboundResult> {
+    info!("Receiving inbound email ({} bytes)", body.len());
+
+    match inbound::ingest(&pool, &config, &body).await {
+        Ok(result) => Ok((StatusCode::OK, Json(result))),
+        Err(IngestError::Unparseable) => Err(api_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Could not parse email",
+        )),
+        Err(IngestError::NoMatch) => Err(api_error(
+            StatusCode::NOT_FOUND,
+            "No matching message for email",
+        )),
+        Err(IngestError::Database(e)) => {
+            error!("Failed to record inbound email: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to record inbound email",
+            ))
+        }
+    }
+}
+
+pub async fn receive_inbound_sms(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> ApiResult<InboundSmsResult> {
+    let params: Vec<(String, String)> = match serde_urlencoded::from_bytes(&body) {
+        Ok(params) => params,
+        Err(e) => {
+            return Err(api_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                &format!("Invalid inbound SMS: {}", e),
+            ));
+        }
+    };
+
+    // Twilio signs the URL it posted to, so it is rebuilt from PUBLIC_URL.
+    if let SmsSettings::Twilio { auth_token, .. } = &config.sms {
+        let url = format!(
+            "{}{}",
+            config.public_url.trim_end_matches('/'),
+            uri.path_and_query().map_or(uri.path(), |path| path.as_str())
+        );
+        let signature = headers
+            .get(sms::TWILIO_SIGNATURE_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        if !verify_twilio(auth_token, &url, &params, signature) {
+            warn!("Rejected inbound SMS with an invalid Twilio signature");
+            return Err(api_error(StatusCode::UNAUTHORIZED, "Invalid signature"));
+        }
+    }
+
+    let payload: InboundSmsRequest = match serde_urlencoded::from_bytes(&body) {
+        Ok(payload) => payload,
+        Err(e) => {
+            return Err(api_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                &format!("Invalid inbound SMS: {}", e),
+            ));
+        }
+    };
+    info!("Receiving inbound SMS from {}", payload.from);
+
+    let message_id = match sms::record_inbound(
+        &pool,
+        &payload.from,
+        payload.to.as_deref(),
+        &payload.body,
+        payload.message_sid.as_deref(),
+    )
+    .await
+    {
+        Ok(message_id) => message_id,
+        Err(e) => {
+            error!("Failed to record inbound SMS: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to record inbound SMS",
+            ));
+        }
+    };
+
+    if let Some(keyword) = sms::keyword(&payload.body) {
+        let reply = match keyword {
+            SmsKeyword::Stop => {
+                if let Err(e) = suppress(
+                    &pool,
+                    SuppressionKind::Phone,
+                    &payload.from,
+                    Some("replied STOP"),
+                    "sms",
+                )
+                .await
+                {
+                    error!("Failed to suppress {}: {}", payload.from, e);
+                    return Err(api_error(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Database error",
+                    ));
+                }
+                sms::STOP_REPLY
+            }
+            SmsKeyword::Help => sms::HELP_REPLY,
+        };
+
+        info!("Inbound SMS from {} is a {:?} keyword", payload.from, keyword);
+
+        if let Err(e) = sms::send(&pool, &config, message_id, None, &payload.from, reply).await {
+            error!("Failed to answer {:?} from {}: {}", keyword, payload.from, e);
+        }
+
+        let message = match message_id {
+            Some(message_id) => fetch_message(&pool, message_id).await.unwrap_or_default(),
+            None => None,
+        };
+
+        return Ok((
+            StatusCode::OK,
+            Json(InboundSmsResult {
+                message,
+                keyword: Some(keyword),
+                reply: None,
+            }),
+        ));
+    }
+
+    let Some(message_id) = message_id else {
+        return Err(api_error(
+            StatusCode::NOT_FOUND,
+            "No matching message for SMS",
+        ));
+    };
+
+    match record_reply(&pool, &config, message_id, payload.body.trim()).await {
+        Ok(Some(message)) => Ok((
+            StatusCode::OK,
+            Json(InboundSmsResult {
+                message: Some(message),
+                keyword: None,
+                reply: Some(payload.body.trim().to_string()),
+            }),
+        )),
+        Ok(None) => Err(api_error(StatusCode::NOT_FOUND, "Message not found")),
+        Err(e) => {
+            error!("Failed to record SMS reply: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to record SMS reply",
+            ))
+        }
+    }
+}
+
+pub async fn receive_inbound_chat(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Path(channel_name): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> ApiResult<InboundResult> {
+    info!("Receiving inbound chat message on {}", channel_name);
+
+    let channel = match chat::fetch_channel_by_name(&pool, &channel_name).await {
+        Ok(Some(channel)) => channel,
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Chat channel not found")),
+        Err(e) => {
+            error!("Failed to fetch chat channel: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    };
+
+    if let Some(secret) = channel.secret.as_deref() {
+        let signature = headers
+            .get(chat::SIGNATURE_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        if !verify_body(secret, &body, signature) {
+            return Err(api_error(StatusCode::UNAUTHORIZED, "Invalid signature"));
+        }
+    }
+
+    let payload: InboundChatRequest = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(e) => {
+            return Err(api_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                &format!("Invalid chat message: {}", e),
+            ));
+        }
+    };
+
+    let contact = match chat::find_contact(&pool, channel.id, &payload.from).await {
+        Ok(Some(contact)) => contact,
+        Ok(None) => {
+            return Err(api_error(
+                StatusCode::NOT_FOUND,
+                "No lead with this chat handle",
+            ));
+        }
+        Err(e) => {
+            error!("Failed to find chat contact: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    };
+
+    let message_id = match chat::record_inbound(
+        &pool,
+        &contact,
+        &payload.text,
+        payload.external_id.as_deref(),
+    )
+    .await
+    {
+        Ok(Some(message_id)) => message_id,
+        Ok(None) => {
+            return Err(api_error(
+                StatusCode::NOT_FOUND,
+                "No matching message for chat reply",
+            ));
+        }
+        Err(e) => {
+            error!("Failed to record inbound chat message: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to record inbound chat message",
+            ));
+        }
+    };
+
+    let reply = payload.text.trim().to_string();
+
+    match record_reply(&pool, &config, message_id, &reply).await {
+        Ok(Some(message)) => Ok((
+            StatusCode::OK,
+            Json(InboundResult {
+                auto_reply: message.reply_received_at.is_none(),
+                message,
+                reply: Some(reply),
+                bounce_type: None,
+            }),
+        )),
+        Ok(None) => Err(api_error(StatusCode::NOT_FOUND, "Message not found")),
+        Err(e) => {
+            error!("Failed to record chat reply: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to record chat reply",
+            ))
+        }
+    }
+}
+
+pub async fn receive_complaint(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<ComplaintRequest>,
+) -> ApiResult<Message> {
+    info!("Receiving complaint for email: {}", payload.email);
+
+    let email_message_ids: Vec<String> = payload
+        .email_message_id
+        .iter()
+        .map(|id| format!("<{}>", id.trim().trim_matches(['<', '>'])))
+        .collect();
+
+    let message_id =
+        match find_message_for_address(&pool, &email_message_ids, Some(&payload.email)).await {
+            Ok(Some(message_id)) => message_id,
+            Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Message not found")),
+            Err(e) => {
+                error!("Failed to find message for complaint: {}", e);
+                return Err(api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Database error",
+                ));
+            }
+        };
+
+    match record_complaint(&pool, message_id, payload.feedback_type.as_deref()).await {
+        Ok(Some(message)) => Ok((StatusCode::OK, Json(message))),
+        Ok(None) => Err(api_error(StatusCode::NOT_FOUND, "Message not found")),
+        Err(e) => {
+            error!("Failed to record complaint: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to record complaint",
+            ))
+        }
+    }
+}
+
+pub async fn import_mbox(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    body: Bytes,
+) -> ApiResult<MboxImportSummary> {
+    info!("Importing mbox ({} bytes)", body.len());
+
+    let mut summary = MboxImportSummary::default();
+
+    for entry in MboxIterator::new(&body[..]) {
+        summary.processed += 1;
+
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                error!("Failed to read mbox entry: {}", e);
+                summary.failed += 1;
+                continue;
+            }
+        };
+
+        match inbound::ingest(&pool, &config, entry.contents()).await {
+            Ok(_) => summary.matched += 1,
+            Err(IngestError::NoMatch) => summary.unmatched += 1,
+            Err(IngestError::Unparseable) => summary.failed += 1,
+            Err(IngestError::Database(e)) => {
+                error!("Failed to record inbound email: {}", e);
+                summary.failed += 1;
+            }
+        }
+    }
+
+    info!(
+        "Imported mbox: {} processed, {} matched, {} unmatched, {} failed",
+        summary.processed, summary.matched, summary.unmatched, summary.failed
+    );
+
+    Ok((StatusCode::OK, Json(summary)))
+}
+
+/// Generates a reply to the lead with the AI provider, using the prompt
+/// template selected for the message. Generated replies that break the
+/// workspace's AI policy are held for review instead of being enqueued.
+/// Without a provider a canned reply is used, checked against the same policy.
+pub async fn ai_reply(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Json(payload): Json<AiReplyRequest>,
+) -> ApiResult<Message> {
+    info!("Generating AI reply for message_id: {}", payload.message_id);
+
+    let generated = match config.ai_provider.as_deref() {
+        Some(provider) => generate_ai_reply(&pool, &config, provider, payload.message_id).await?,
+        None => canned_ai_reply(&pool, payload.message_id).await?,
+    };
+    let status = if generated.violations.is_empty() {
+        MessageStatus::AiEnqueued
+    } else {
+        MessageStatus::AiReview
+    };
+
+    let result = sqlx::query_as::<_, Message>(
+        r#"
+        UPDATE messages
+        SET ai_reply = ?, status = ?, prompt_template_id = ?
+        WHERE id = ?
+        RETURNING id, leads_id, message_sent, sent_at, reply_received, reply_received_at, ai_reply, ai_reply_sent, created_at, status, follow_up_at, closed_at, sender_id, scheduled_at, email_message_id, follow_up_body, bounced_at, bounce_type, bounce_reason, channel, contact_id, body_format, message_html, tracking, open_count, click_count, subject, variant_id, campaign_id, reply_intent, reply_intent_source, snoozed_until, prompt_template_id
+        "#,
+    )
+    .bind(&generated.text)
+    .bind(status.as_str())
+    .bind(generated.template_id)
+    .bind(payload.message_id)
+    .fetch_optional(&pool)
+    .await;
+
+    let message = match result {
+        Ok(Some(message)) => message,
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Message not found")),
+        Err(e) => {
+            error!("Failed to update message: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to update message",
+            ));
+        }
+    };
+
+    if status == MessageStatus::AiReview {
+        if let Err(e) = reviews::create(
+            &pool,
+            message.id,
+            &generated.text,
+            &generated.violations,
+            generated.confidence,
+        )
+        .await
+        {
+            error!("Failed to create AI review: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to hold AI reply for review",
+            ));
+        }
+        warn!(
+            "AI reply for message_id {} held for review: it {}",
+            message.id,
+            generated.violations.join(", it ")
+        );
+    } else {
+        info!("AI reply enqueued for message_id: {}", message.id);
+    }
+
+    log_outreach(&pool, message.id, status).await;
+    Ok((StatusCode::OK, Json(message)))
+}
+
+/// An AI reply with the prompt template version it was written with and
+/// the policy rules it breaks.
+struct GeneratedReply {
+    text: String,
+    template_id: Option<i64>,
+    confidence: Option<f64>,
+    violations: Vec<String>,
+}
+
+/// Asks the provider to answer the lead's reply on a message and checks the
+/// answer against the policy of the lead's workspace.
+async fn generate_ai_reply(
+    pool: &SqlitePool,
+    config: &Config,
+    provider: &dyn AiProvider,
+    message_id: i64,
+) -> Result<GeneratedReply, (StatusCode, Json<ApiError>)> {
+    let database_error = |e: sqlx::Error| {
+        error!("Failed to prepare AI reply: {}", e);
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+    };
+
+    let message = fetch_message(pool, message_id)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Message not found"))?;
+    if message.reply_received.is_none() {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Message has no reply to answer",
+        ));
+    }
+
+    let lead = fetch_lead(pool, message.leads_id)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Lead not found"))?;
+    let template = prompts::select(pool, &message, &lead)
+        .await
+        .map_err(database_error)?;
+    let policy = fetch_workspace(pool, lead.workspace_id)
+        .await
+        .map_err(database_error)?
+        .map(|workspace| workspace.ai_policy.0)
+        .unwrap_or_default();
+
+    let system_prompt =
+        prompts::system_prompt(template.as_ref()) + guardrails::CONFIDENCE_INSTRUCTION;
+    let call = AiCall {
+        workspace_id: lead.workspace_id,
+        purpose: "reply",
+        message_id: Some(message.id),
+    };
+    let prompt = prompts::reply_prompt(&message, &lead);
+    let answer = usage::complete(pool, config, provider, &call, &system_prompt, &prompt)
+        .await
+        .map_err(|e| ai_call_error(e, "reply"))?;
+
+    let (text, confidence) = guardrails::split_confidence(&answer.text);
+    let max_length = template
+        .as_ref()
+        .and_then(|t| t.max_length)
+        .map(|max_length| max_length as usize);
+    let violations = guardrails::check(&policy, max_length, &text, &lead, confidence);
+
+    info!(
+        "AI reply generated for message {} with {} (template: {:?}, confidence: {:?})",
+        message.id,
+        provider.model(),
+        template.as_ref().map(|t| t.id),
+        confidence
+    );
+    Ok(GeneratedReply {
+        text,
+        template_id: template.map(|t| t.id),
+        confidence,
+        violations,
+    })
+}
+
+/// The canned reply used without a provider, with the rules of the lead's
+/// workspace policy it breaks. It has no confidence rating.
+async fn canned_ai_reply(
+    pool: &SqlitePool,
+    message_id: i64,
+) -> Result<GeneratedReply, (StatusCode, Json<ApiError>)> {
+    let database_error = |e: sqlx::Error| {
+        error!("Failed to prepare AI reply: {}", e);
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+    };
+
+    let message = fetch_message(pool, message_id)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Message not found"))?;
+    let lead = fetch_lead(pool, message.leads_id)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Lead not found"))?;
+    let policy = fetch_workspace(pool, lead.workspace_id)
+        .await
+        .map_err(database_error)?
+        .map(|workspace| workspace.ai_policy.0)
+        .unwrap_or_default();
+
+    Ok(GeneratedReply {
+        text: CANNED_AI_REPLY.to_string(),
+        template_id: None,
+        confidence: None,
+        violations: guardrails::check(&policy, None, CANNED_AI_REPLY, &lead, None),
+    })
+}
+
+/// Maps a failed AI call for generating a `task` to its API error.
+fn ai_call_error(e: AiCallError, task: &str) -> (StatusCode, Json<ApiError>) {
+    match e {
+        AiCallError::BudgetExceeded => api_error(
+            StatusCode::PAYMENT_REQUIRED,
+            "The workspace's monthly AI budget is exceeded",
+        ),
+        AiCallError::Provider(e) => {
+            error!("Failed to generate AI {}: {}", task, e);
+            api_error(
+                StatusCode::BAD_GATEWAY,
+                &format!("AI provider failed to generate a {}", task),
+            )
+        }
+        AiCallError::Database(e) => {
+            error!("Failed to generate AI {}: {}", task, e);
+            api_error(StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        }
+    }
+}
+
+/// Drafts a personalized first message for a lead with the AI provider,
+/// and enqueues it through the `/send` path when asked to.
+pub async fn ai_draft(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Json(payload): Json<AiDraftRequest>,
+) -> ApiResult<AiDraftResult> {
+    info!("Drafting AI outreach for lead_id: {}", payload.lead_id);
+
+    if payload.brief.product.trim().is_empty() || payload.brief.goal.trim().is_empty() {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "The brief needs a product and a goal",
+        ));
+    }
+
+    let Some(provider) = config.ai_provider.as_deref() else {
+        return Err(api_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "AI provider is not configured",
+        ));
+    };
+
+    let lead = match fetch_lead(&pool, payload.lead_id).await {
+        Ok(Some(lead)) => lead,
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Lead not found")),
+        Err(e) => {
+            error!("Failed to fetch lead: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    };
+
+    let channel = payload.channel.unwrap_or_default();
+    let brief = &payload.brief;
+    let draft = drafts::generate(&pool, &config, provider, &lead, brief, channel)
+        .await
+        .map_err(|e| ai_call_error(e, "draft"))?;
+    info!(
+        "Draft {} generated for lead {} with {}",
+        draft.id, lead.id, draft.model
+    );
+
+    if !payload.enqueue {
+        return Ok((
+            StatusCode::CREATED,
+            Json(AiDraftResult {
+                draft,
+                message: None,
+            }),
+        ));
+    }
+
+    let request = SendMessageRequest {
+        lead_id: lead.id,
+        message: draft.body.clone(),
+        subject: draft.subject.clone(),
+        experiment_id: None,
+        sender_id: payload.sender_id,
+        sender_pool_id: payload.sender_pool_id,
+        follow_up: payload.follow_up.clone(),
+        channel: Some(channel),
+        contact_id: None,
+        format: None,
+        attachment_ids: None,
+        track: payload.track,
+    };
+    let message = enqueue_message(&pool, &request, None).await?;
+
+    match drafts::set_message(&pool, draft.id, message.id).await {
+        Ok(draft) => Ok((
+            StatusCode::CREATED,
+            Json(AiDraftResult {
+                draft,
+                message: Some(message),
+            }),
+        )),
+        Err(e) => {
+            error!("Failed to link draft to message: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to link draft to message",
+            ))
+        }
+    }
+}
+
+/// Leads with their score and an overview of their messages, filtered by
+/// workspace, score range and custom field values.
+pub async fn list_leads(
+    State(pool): State<SqlitePool>,
+    Query(query): Query<LeadQuery>,
+) -> ApiResult<Vec<LeadSummary>> {
+    let mut workspace_id = query.workspace_id;
+    let mut conditions = Vec::new();
+
+    if let Some(min_score) = query.min_score {
+        conditions.push(Expr::term("score", Op::Ge, min_score));
+    }
+    if let Some(max_score) = query.max_score {
+        conditions.push(Expr::term("score", Op::Le, max_score));
+    }
+    match query.fields.as_deref().map(fields::parse_filters) {
+        Some(Ok(filters)) => conditions.extend(
+            filters
+                .iter()
+                .map(|(key, value)| Expr::term(key, Op::Eq, value)),
+        ),
+        Some(Err(e)) => return Err(api_error(StatusCode::BAD_REQUEST, &e)),
+        None => {}
+    }
+    if let Some(filter) = query.filter.as_deref().filter(|f| !f.trim().is_empty()) {
+        match segments::parse(filter) {
+            Ok(filter) => conditions.push(filter),
+            Err(e) => return Err(api_error(StatusCode::BAD_REQUEST, &e)),
+        }
+    }
+    if let Some(segment_id) = query.segment_id {
+        let (segment, filter) = segment_filter(&pool, segment_id).await?;
+        if workspace_id.is_some_and(|id| id != segment.workspace_id) {
+            return Err(api_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Segment belongs to another workspace",
+            ));
+        }
+        workspace_id = Some(segment.workspace_id);
+        conditions.push(filter);
+    }
+
+    let filter = Expr::all(conditions);
+    match segments::lead_summaries(&pool, workspace_id, filter.as_ref(), query.sort).await {
+        Ok(leads) => Ok((StatusCode::OK, Json(leads))),
+        Err(e) => {
+            error!("Failed to list leads: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to list leads",
+            ))
+        }
+    }
+}
+
+pub async fn get_lead(
+    State(pool): State<SqlitePool>,
+    Path(lead_id): Path<i64>,
+) -> ApiResult<LeadWithDetails> {
+    info!("Fetching lead with id: {}", lead_id);
+
+    let lead = match fetch_lead(&pool, lead_id).await {
+        Ok(Some(lead)) => lead,
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Lead not found")),
+        Err(e) => {
+            error!("Failed to fetch lead: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    };
+
+    let messages = sqlx::query_as::<_, Message>(
+        r#"
+        SELECT id, leads_id, message_sent, sent_at, reply_received, reply_received_at, ai_reply, ai_reply_sent, created_at, status, follow_up_at, closed_at, sender_id, scheduled_at, email_message_id, follow_up_body, bounced_at, bounce_type, bounce_reason, channel, contact_id, body_format, message_html, tracking, open_count, click_count, subject, variant_id, campaign_id, reply_intent, reply_intent_source, snoozed_until, prompt_template_id
+        FROM messages
+        WHERE leads_id = ?
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(lead_id)
+    .fetch_all(&pool)
+    .await
+    .unwrap_or_default();
+
+    let contacts = chat::lead_contacts(&pool, lead_id)
+        .await
+        .unwrap_or_default();
+
+    let attachments = attachments::lead_attachments(&pool, lead_id)
+        .await
+        .unwrap_or_default();
+
+    let tags = tags::lead_tags(&pool, lead_id).await.unwrap_or_default();
+
+    let message_ids: Vec<i64> = messages.iter().map(|m| m.id).collect();
+
+    let outreach_logs = if !message_ids.is_empty() {
+        let placeholders = message_ids
+            .iter()
+            .map(|_| "?")
+            .collect::<Vec<_>>()
+            .join(",");
+        let query = format!(
+            "SELECT id, message_id, log_at, step, user_agent, url, body FROM outreach_log WHERE message_id IN ({}) ORDER BY log_at DESC",
+            placeholders
+        );
+
+        let mut query_builder = sqlx::query_as::<_, OutreachLog>(&query);
+        for id in &message_ids {
+            query_builder = query_builder.bind(id);
+        }
+
+        query_builder.fetch_all(&pool).await.unwrap_or_default()
+    } else {
+        vec![]
+    };
+
+    info!(
+        "Found {} messages and {} outreach logs for lead_id: {}",
+        messages.len(),
+        outreach_logs.len(),
+        lead_id
+    );
+
+    Ok((
+        StatusCode::OK,
+        Json(LeadWithDetails {
+            lead,
+            contacts,
+            messages,
+            attachments,
+            outreach_logs,
+            tags,
+        }),
+    ))
+}
+
+pub async fn create_sender(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<CreateSenderRequest>,
+) -> ApiResult<SenderIdentity> {
+    info!("Creating sender: {:?}", payload);
+
+    if payload.name.trim().is_empty() || payload.address.trim().is_empty() {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Name and address are required",
+        ));
+    }
+
+    if !email::is_valid_address(&payload.address) {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Invalid sender address"));
+    }
+    if payload
+        .reply_to
+        .as_deref()
+        .is_some_and(|reply_to| !email::is_valid_address(reply_to))
+    {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Invalid reply-to address"));
+    }
+
+    let negative = [
+        payload.daily_cap,
+        payload.hourly_cap,
+        payload.min_spacing_seconds,
+    ]
+    .iter()
+    .any(|v| v.is_some_and(|v| v < 0));
+    if negative {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Caps and spacing must not be negative",
+        ));
+    }
+
+    let result = sqlx::query_as::<_, SenderIdentity>(
+        r#"
+        INSERT INTO sender_identities (name, address, daily_cap, hourly_cap, min_spacing_seconds, created_at, signature, reply_to, transport, workspace_id)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        RETURNING id, name, address, daily_cap, hourly_cap, min_spacing_seconds, created_at, signature, reply_to, transport, workspace_id
+        "#,
+    )
+    .bind(&payload.name)
+    .bind(&payload.address)
+    .bind(payload.daily_cap)
+    .bind(payload.hourly_cap)
+    .bind(payload.min_spacing_seconds.unwrap_or(0))
+    .bind(Utc::now().to_rfc3339())
+    .bind(&payload.signature)
+    .bind(&payload.reply_to)
+    .bind(SqlJson(payload.transport.clone().unwrap_or_default()))
+    .bind(payload.workspace_id.unwrap_or(DEFAULT_WORKSPACE_ID))
+    .fetch_one(&pool)
+    .await;
+
+    match result {
+        Ok(sender) => {
+            info!("Sender created with id: {}", sender.id);
+            Ok((StatusCode::CREATED, Json(sender)))
+        }
+        Err(e) => {
+            error!("Failed to create sender: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create sender",
+            ))
+        }
+    }
+}
+
+pub async fn list_senders(State(pool): State<SqlitePool>) -> ApiResult<Vec<SenderIdentity>> {
+    let result = sqlx::query_as::<_, SenderIdentity>(
+        r#"
+        SELECT id, name, address, daily_cap, hourly_cap, min_spacing_seconds, created_at, signature, reply_to, transport, workspace_id
+        FROM sender_identities
+        ORDER BY id ASC
+        "#,
+    )
+    .fetch_all(&pool)
+    .await;
+
+    match result {
+        Ok(senders) => Ok((StatusCode::OK, Json(senders))),
+        Err(e) => {
+            error!("Failed to list senders: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ))
+        }
+    }
+}
+
+pub async fn get_sender_usage(
+    State(pool): State<SqlitePool>,
+    Path(sender_id): Path<i64>,
+) -> ApiResult<SenderUsage> {
+    info!("Fetching usage for sender_id: {}", sender_id);
+
+    let sender = match fetch_sender(&pool, sender_id).await {
+        Ok(Some(sender)) => sender,
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Sender not found")),
+        Err(e) => {
+            error!("Failed to fetch sender: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    };
+
+    let now = Utc::now();
+    let usage = match Throttle::load(&pool, sender, now).await {
+        Ok(throttle) => throttle.usage(&pool, now).await,
+        Err(e) => Err(e),
+    };
+
+    match usage {
+        Ok(usage) => Ok((StatusCode::OK, Json(usage))),
+        Err(e) => {
+            error!("Failed to compute usage for sender {}: {}", sender_id, e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ))
+        }
+    }
+}
+
+pub async fn create_sender_pool(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<CreateSenderPoolRequest>,
+) -> ApiResult<SenderPoolWithMembers> {
+    info!("Creating sender pool: {:?}", payload);
+
+    if payload.name.trim().is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Name is required"));
+    }
+
+    if payload.sender_ids.is_empty() {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "At least one sender is required",
+        ));
+    }
+
+    let mut sender_ids = payload.sender_ids.clone();
+    sender_ids.sort_unstable();
+    sender_ids.dedup();
+
+    let mut senders = Vec::with_capacity(sender_ids.len());
+    for sender_id in &sender_ids {
+        match fetch_sender(&pool, *sender_id).await {
+            Ok(Some(sender)) => senders.push(sender),
+            Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Sender not found")),
+            Err(e) => {
+                error!("Failed to fetch sender: {}", e);
+                return Err(api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Database error",
+                ));
+            }
+        }
+    }
+
+    let result = async {
+        let mut tx = pool.begin().await?;
+
+        let sender_pool = sqlx::query_as::<_, SenderPool>(
+            "INSERT INTO sender_pools (name, created_at) VALUES (?, ?) RETURNING id, name, created_at",
+        )
+        .bind(&payload.name)
+        .bind(Utc::now().to_rfc3339())
+        .fetch_one(&mut *tx)
+        .await?;
+
+        for sender in &senders {
+            sqlx::query(
+                "INSERT OR IGNORE INTO sender_pool_members (pool_id, sender_id) VALUES (?, ?)",
+            )
+            .bind(sender_pool.id)
+            .bind(sender.id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(sender_pool)
+    }
+    .await;
+
+    match result {
+        Ok(sender_pool) => {
+            info!("Sender pool created with id: {}", sender_pool.id);
+            Ok((
+                StatusCode::CREATED,
+                Json(SenderPoolWithMembers {
+                    pool: sender_pool,
+                    senders,
+                }),
+            ))
+        }
+        Err(e) => {
+            error!("Failed to create sender pool: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create sender pool",
+            ))
+        }
+    }
+}
+
+/// Picks the pool member that was least recently assigned a message, so
+/// consecutive sends rotate through every mailbox in the pool. Only
+/// members of the workspace are picked.
+pub async fn next_pool_sender(
+    pool: &SqlitePool,
+    pool_id: i64,
+    workspace_id: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT m.sender_id
+        FROM sender_pool_members m
+        JOIN sender_identities s ON s.id = m.sender_id
+        LEFT JOIN (
+            SELECT sender_id, MAX(id) AS last_message_id
+            FROM messages
+            WHERE sender_id IS NOT NULL
+            GROUP BY sender_id
+        ) u ON u.sender_id = m.sender_id
+        WHERE m.pool_id = ? AND s.workspace_id = ?
+        ORDER BY COALESCE(u.last_message_id, 0) ASC, m.sender_id ASC
+        LIMIT 1
+        "#,
+    )
+    .bind(pool_id)
+    .bind(workspace_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn create_suppression(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<CreateSuppressionRequest>,
+) -> ApiResult<Suppression> {
+    info!("Creating suppression: {:?}", payload);
+
+    if normalize(payload.kind, &payload.value).is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Value is required"));
+    }
+
+    match suppress(
+        &pool,
+        payload.kind,
+        &payload.value,
+        payload.reason.as_deref(),
+        "manual",
+    )
+    .await
+    {
+        Ok(suppression) => Ok((StatusCode::CREATED, Json(suppression))),
+        Err(e) => {
+            error!("Failed to create suppression: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create suppression",
+            ))
+        }
+    }
+}
+
+pub async fn list_suppressions(State(pool): State<SqlitePool>) -> ApiResult<Vec<Suppression>> {
+    let result = sqlx::query_as::<_, Suppression>(
+        "SELECT id, kind, value, reason, source, created_at FROM suppressions ORDER BY id DESC",
+    )
+    .fetch_all(&pool)
+    .await;
+
+    match result {
+        Ok(suppressions) => Ok((StatusCode::OK, Json(suppressions))),
+        Err(e) => {
+            error!("Failed to list suppressions: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ))
+        }
+    }
+}
+
+pub async fn delete_suppression(
+    State(pool): State<SqlitePool>,
+    Path(suppression_id): Path<i64>,
+) -> ApiResult<Suppression> {
+    info!("Deleting suppression with id: {}", suppression_id);
+
+    let result = sqlx::query_as::<_, Suppression>(
+        "DELETE FROM suppressions WHERE id = ? RETURNING id, kind, value, reason, source, created_at",
+    )
+    .bind(suppression_id)
+    .fetch_optional(&pool)
+    .await;
+
+    match result {
+        Ok(Some(suppression)) => Ok((StatusCode::OK, Json(suppression))),
+        Ok(None) => Err(api_error(StatusCode::NOT_FOUND, "Suppression not found")),
+        Err(e) => {
+            error!("Failed to delete suppression: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ))
+        }
+    }
+}
+
+/// Confirmation page for the unsubscribe link in the email footer. The
+/// unsubscribe itself happens on POST so link scanners cannot trigger it.
+pub async fn unsubscribe_page(
+    State(config): State<Arc<Config>>,
+    Path(token): Path<String>,
+) -> (StatusCode, Html<String>) {
+    if unsubscribe_lead_id(&config, &token).is_none() {
+        return (
+            StatusCode::NOT_FOUND,
+            Html("<p>This unsubscribe link is not valid.</p>".to_string()),
+        );
+    }
+
+    (
+        StatusCode::OK,
+        Html(format!(
+            r#"<form method="post" action="/unsubscribe/{}"><p>Stop receiving emails from us?</p><button type="submit">Unsubscribe</button></form>"#,
+            token
+        )),
+    )
+}
+
+/// One-click unsubscribe (RFC 8058), also used by the confirmation page.
+pub async fn unsubscribe(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Path(token): Path<String>,
+) -> (StatusCode, Html<String>) {
+    let Some(lead_id) = unsubscribe_lead_id(&config, &token) else {
+        return (
+            StatusCode::NOT_FOUND,
+            Html("<p>This unsubscribe link is not valid.</p>".to_string()),
+        );
+    };
+
+    info!("Unsubscribing lead_id: {}", lead_id);
+
+    let result = match fetch_lead(&pool, lead_id).await {
+        Ok(Some(lead)) => suppress_lead(&pool, &lead, "unsubscribe link", "unsubscribe").await,
+        Ok(None) => Ok(()),
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(()) => (
+            StatusCode::OK,
+            Html("<p>You have been unsubscribed.</p>".to_string()),
+        ),
+        Err(e) => {
+            error!("Failed to unsubscribe lead {}: {}", lead_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Html("<p>Something went wrong, please try again.</p>".to_string()),
+            )
+        }
+    }
+}
+
+pub async fn list_ai_reviews(
+    State(pool): State<SqlitePool>,
+    Query(query): Query<AiReviewQuery>,
+) -> ApiResult<Vec<AiReview>> {
+    let status = query.status.as_deref().unwrap_or(reviews::PENDING);
+
+    match reviews::list(&pool, status).await {
+        Ok(reviews) => Ok((StatusCode::OK, Json(reviews))),
+        Err(e) => {
+            error!("Failed to list AI reviews: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to list AI reviews",
+            ))
+        }
+    }
+}
+
+/// Enqueues a held AI reply, or the reviewer's edited version of it.
+pub async fn approve_ai_review(
+    State(pool): State<SqlitePool>,
+    Path(review_id): Path<i64>,
+    payload: Option<Json<ApproveReviewRequest>>,
+) -> ApiResult<Message> {
+    info!("Approving AI review {}", review_id);
+
+    let edited = payload
+        .and_then(|Json(payload)| payload.ai_reply)
+        .filter(|reply| !reply.trim().is_empty());
+    decide_ai_review(&pool, review_id, reviews::APPROVED, edited).await
+}
+
+/// Drops a held AI reply; the message goes back to `replied`.
+pub async fn reject_ai_review(
+    State(pool): State<SqlitePool>,
+    Path(review_id): Path<i64>,
+) -> ApiResult<Message> {
+    info!("Rejecting AI review {}", review_id);
+
+    decide_ai_review(&pool, review_id, reviews::REJECTED, None).await
+}
+
+async fn decide_ai_review(
+    pool: &SqlitePool,
+    review_id: i64,
+    decision: &str,
+    edited: Option<String>,
+) -> ApiResult<Message> {
+    // The review and its message change together: a review is never marked
+    // decided while its message has moved on without it.
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    };
+
+    let review = match reviews::decide(&mut tx, review_id, decision).await {
+        Ok(Some(review)) => review,
+        Ok(None) => {
+            return Err(api_error(
+                StatusCode::NOT_FOUND,
+                "Pending AI review not found",
+            ));
+        }
+        Err(e) => {
+            error!("Failed to update AI review: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to update AI review",
+            ));
+        }
+    };
+
+    let (ai_reply, status) = if decision == reviews::APPROVED {
+        (
+            Some(edited.unwrap_or(review.ai_reply)),
+            MessageStatus::AiEnqueued,
+        )
+    } else {
+        (None, MessageStatus::Replied)
+    };
+
+    let result = sqlx::query_as::<_, Message>(
+        r#"
+        UPDATE messages
+        SET ai_reply = ?, status = ?
+        WHERE id = ? AND status = ?
+        RETURNING id, leads_id, message_sent, sent_at, reply_received, reply_received_at, ai_reply, ai_reply_sent, created_at, status, follow_up_at, closed_at, sender_id, scheduled_at, email_message_id, follow_up_body, bounced_at, bounce_type, bounce_reason, channel, contact_id, body_format, message_html, tracking, open_count, click_count, subject, variant_id, campaign_id, reply_intent, reply_intent_source, snoozed_until, prompt_template_id
+        "#,
+    )
+    .bind(ai_reply)
+    .bind(status.as_str())
+    .bind(review.message_id)
+    .bind(MessageStatus::AiReview.as_str())
+    .fetch_optional(&mut *tx)
+    .await;
+
+    match result {
+        Ok(Some(message)) => {
+            if let Err(e) = tx.commit().await {
+                error!("Failed to commit AI review {}: {}", review.id, e);
+                return Err(api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to update AI review",
+                ));
+            }
+            if status == MessageStatus::AiEnqueued {
+                log_outreach(pool, message.id, status).await;
+            }
+            info!(
+                "AI review {} {}, message {} is {}",
+                review.id,
+                decision,
+                message.id,
+                status.as_str()
+            );
+            Ok((StatusCode::OK, Json(message)))
+        }
+        Ok(None) => {
+            if let Err(e) = tx.rollback().await {
+                error!("Failed to roll back AI review {}: {}", review.id, e);
+            }
+            Err(api_error(
+                StatusCode::CONFLICT,
+                "Message is no longer awaiting review",
+            ))
+        }
+        Err(e) => {
+            error!("Failed to update message: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to update message",
+            ))
+        }
+    }
+}
+
+/// A workspace's AI calls in a month, per model and purpose, with what is
+/// left of its monthly budget.
+pub async fn get_ai_usage(
+    State(pool): State<SqlitePool>,
+    Query(query): Query<AiUsageQuery>,
+) -> ApiResult<AiUsageReport> {
+    let workspace_id = query.workspace_id.unwrap_or(DEFAULT_WORKSPACE_ID);
+    let month = query
+        .month
+        .unwrap_or_else(|| Utc::now().format("%Y-%m").to_string());
+    let Some((start, end)) = usage::month_range(&month) else {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Month must be YYYY-MM"));
+    };
+
+    let workspace = match fetch_workspace(&pool, workspace_id).await {
+        Ok(Some(workspace)) => workspace,
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Workspace not found")),
+        Err(e) => {
+            error!("Failed to fetch workspace: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    };
+
+    let usage = match usage::report(&pool, workspace_id, start, end).await {
+        Ok(usage) => usage,
+        Err(e) => {
+            error!("Failed to report AI usage: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to report AI usage",
+            ));
+        }
+    };
+    let spent = usage.iter().fold(0.0, |spent, line| spent + line.cost);
+    let budget = workspace.ai_monthly_budget;
+
+    Ok((
+        StatusCode::OK,
+        Json(AiUsageReport {
+            workspace_id,
+            month,
+            budget,
+            spent,
+            remaining: budget.map(|budget| (budget - spent).max(0.0)),
+            usage,
+        }),
+    ))
+}
+
+pub async fn create_prompt_template(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<PromptTemplateRequest>,
+) -> ApiResult<PromptTemplate> {
+    info!("Creating prompt template: {:?}", payload);
+
+    if payload.system_prompt.trim().is_empty() {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "System prompt is required",
+        ));
+    }
+    if payload.max_length.is_some_and(|max_length| max_length <= 0) {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Max length must be positive",
+        ));
+    }
+
+    // A campaign's templates belong to the campaign's workspace.
+    let workspace_id = match payload.campaign_id {
+        Some(campaign_id) => match campaigns::fetch(&pool, campaign_id).await {
+            Ok(Some(campaign)) => campaign.workspace_id,
+            Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Campaign not found")),
+            Err(e) => {
+                error!("Failed to fetch campaign: {}", e);
+                return Err(api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Database error",
+                ));
+            }
+        },
+        None => payload.workspace_id.unwrap_or(DEFAULT_WORKSPACE_ID),
+    };
+    match fetch_workspace(&pool, workspace_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Workspace not found")),
+        Err(e) => {
+            error!("Failed to fetch workspace: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    }
+
+    let template = NewTemplate {
+        workspace_id,
+        campaign_id: payload.campaign_id,
+        system_prompt: payload.system_prompt.trim(),
+        persona: payload.persona.as_deref(),
+        tone: payload.tone.as_deref(),
+        forbidden_topics: &payload.forbidden_topics,
+        max_length: payload.max_length,
+    };
+
+    match prompts::create(&pool, &template).await {
+        Ok(template) => {
+            info!(
+                "Prompt template {} created (version {})",
+                template.id, template.version
+            );
+            Ok((StatusCode::CREATED, Json(template)))
+        }
+        Err(e) => {
+            error!("Failed to create prompt template: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create prompt template",
+            ))
+        }
+    }
+}
+
+pub async fn list_prompt_templates(
+    State(pool): State<SqlitePool>,
+    Query(filter): Query<PromptTemplateQuery>,
+) -> ApiResult<Vec<PromptTemplate>> {
+    match prompts::list(&pool, &filter).await {
+        Ok(templates) => Ok((StatusCode::OK, Json(templates))),
+        Err(e) => {
+            error!("Failed to list prompt templates: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to list prompt templates",
+            ))
+        }
+    }
+}
+
+/// Counts and conversion rates through the outreach funnel.
+pub async fn get_funnel(
+    State(pool): State<SqlitePool>,
+    Query(query): Query<FunnelQuery>,
+) -> ApiResult<FunnelReport> {
+    info!("Computing funnel: {:?}", query);
+
+    for date in [&query.from, &query.to].into_iter().flatten() {
+        let valid = DateTime::parse_from_rfc3339(date).is_ok()
+            || NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok();
+        if !valid {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                "Dates must be YYYY-MM-DD or RFC 3339 timestamps",
+            ));
+        }
+    }
+
+    match analytics::funnel(&pool, &query).await {
+        Ok(report) => Ok((StatusCode::OK, Json(report))),
+        Err(e) => {
+            error!("Failed to compute funnel: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to compute funnel",
+            ))
+        }
+    }
+}
+
+pub async fn create_campaign(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<CreateCampaignRequest>,
+) -> ApiResult<CampaignWithDetails> {
+    info!("Creating campaign: {:?}", payload);
+
+    if payload.name.trim().is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Name is required"));
+    }
+
+    let sequence = &payload.sequence;
+    if sequence.experiment_id.is_none() && sequence.message.trim().is_empty() {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "The sequence needs a message or an experiment",
+        ));
+    }
+
+    if let Some(experiment_id) = sequence.experiment_id {
+        match experiments::fetch(&pool, experiment_id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Experiment not found")),
+            Err(e) => {
+                error!("Failed to fetch experiment: {}", e);
+                return Err(api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Database error",
+                ));
+            }
+        }
+    }
+
+    check_attachments(&pool, sequence.attachment_ids.as_deref().unwrap_or_default()).await?;
+
+    if let Some(pool_id) = payload.sender_pool_id {
+        let exists = sqlx::query_scalar::<_, i64>("SELECT id FROM sender_pools WHERE id = ?")
+            .bind(pool_id)
+            .fetch_optional(&pool)
+            .await;
+        match exists {
+            Ok(Some(_)) => {}
+            Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Sender pool not found")),
+            Err(e) => {
+                error!("Failed to fetch sender pool: {}", e);
+                return Err(api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Database error",
+                ));
+            }
+        }
+    }
+
+    let workspace_id = payload.workspace_id.unwrap_or(DEFAULT_WORKSPACE_ID);
+    match fetch_workspace(&pool, workspace_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Workspace not found")),
+        Err(e) => {
+            error!("Failed to fetch workspace: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    }
+
+    let lead_ids = payload.lead_ids.clone().unwrap_or_default();
+    check_campaign_leads(&pool, workspace_id, &lead_ids).await?;
+
+    let result = async {
+        let mut tx = pool.begin().await?;
+
+        let campaign = sqlx::query_as::<_, Campaign>(
+            r#"
+            INSERT INTO campaigns (name, workspace_id, status, sender_pool_id, sequence, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            RETURNING id, name, workspace_id, status, sender_pool_id, sequence, created_at, started_at, paused_at, archived_at
+            "#,
+        )
+        .bind(&payload.name)
+        .bind(workspace_id)
+        .bind(CampaignStatus::Draft.as_str())
+        .bind(payload.sender_pool_id)
+        .bind(SqlJson(sequence))
+        .bind(Utc::now().to_rfc3339())
+        .fetch_one(&mut *tx)
+        .await?;
+
+        campaigns::add_leads(&mut tx, campaign.id, &lead_ids).await?;
+        tx.commit().await?;
+
+        campaign_details(&pool, campaign).await
+    }
+    .await;
+
+    match result {
+        Ok(details) => {
+            info!("Campaign created with id: {}", details.campaign.id);
+            Ok((StatusCode::CREATED, Json(details)))
+        }
+        Err(e) => {
+            error!("Failed to create campaign: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create campaign",
+            ))
+        }
+    }
+}
+
+pub async fn list_campaigns(State(pool): State<SqlitePool>) -> ApiResult<Vec<Campaign>> {
+    match campaigns::list(&pool).await {
+        Ok(campaigns) => Ok((StatusCode::OK, Json(campaigns))),
+        Err(e) => {
+            error!("Failed to list campaigns: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to list campaigns",
+            ))
+        }
+    }
+}
+
+pub async fn get_campaign(
+    State(pool): State<SqlitePool>,
+    Path(campaign_id): Path<i64>,
+) -> ApiResult<CampaignWithDetails> {
+    let result = match campaigns::fetch(&pool, campaign_id).await {
+        Ok(Some(campaign)) => campaign_details(&pool, campaign).await.map(Some),
+        Ok(None) => Ok(None),
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(Some(details)) => Ok((StatusCode::OK, Json(details))),
+        Ok(None) => Err(api_error(StatusCode::NOT_FOUND, "Campaign not found")),
+        Err(e) => {
+            error!("Failed to fetch campaign: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ))
+        }
+    }
+}
+
+/// Adds leads to a campaign's list. Leads added to a started campaign are
+/// enqueued right away.
+pub async fn add_campaign_leads(
+    State(pool): State<SqlitePool>,
+    Path(campaign_id): Path<i64>,
+    Json(payload): Json<AddCampaignLeadsRequest>,
+) -> ApiResult<CampaignWithDetails> {
+    info!("Adding leads to campaign {}: {:?}", campaign_id, payload);
+
+    let campaign = match campaigns::fetch(&pool, campaign_id).await {
+        Ok(Some(campaign)) => campaign,
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Campaign not found")),
+        Err(e) => {
+            error!("Failed to fetch campaign: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    };
+
+    let status = campaign.status.parse::<CampaignStatus>().ok();
+    if status == Some(CampaignStatus::Archived) {
+        return Err(api_error(StatusCode::CONFLICT, "Campaign is archived"));
+    }
+
+    let mut lead_ids = payload.lead_ids.clone();
+    if let Some(segment_id) = payload.segment_id {
+        let (segment, filter) = segment_filter(&pool, segment_id).await?;
+        if segment.workspace_id != campaign.workspace_id {
+            return Err(api_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Segment belongs to another workspace",
+            ));
+        }
+        match segments::lead_ids(&pool, segment.workspace_id, &filter).await {
+            Ok(segment_leads) => lead_ids.extend(segment_leads),
+            Err(e) => {
+                error!("Failed to find the leads of segment {}: {}", segment_id, e);
+                return Err(api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Database error",
+                ));
+            }
+        }
+    }
+
+    check_campaign_leads(&pool, campaign.workspace_id, &lead_ids).await?;
+
+    let result = async {
+        let mut tx = pool.begin().await?;
+        campaigns::add_leads(&mut tx, campaign.id, &lead_ids).await?;
+        tx.commit().await?;
+
+        if matches!(
+            status,
+            Some(CampaignStatus::Active | CampaignStatus::Paused)
+        ) {
+            enqueue_campaign_leads(&pool, &campaign).await?;
+        }
+        campaign_details(&pool, campaign).await
+    }
+    .await;
+
+    match result {
+        Ok(details) => Ok((StatusCode::OK, Json(details))),
+        Err(e) => {
+            error!("Failed to add leads to campaign {}: {}", campaign_id, e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to add leads",
+            ))
+        }
+    }
+}
+
+/// Starts a draft campaign, enqueueing a message for every lead on its list.
+pub async fn start_campaign(
+    State(pool): State<SqlitePool>,
+    Path(campaign_id): Path<i64>,
+) -> ApiResult<CampaignWithDetails> {
+    change_campaign_status(&pool, campaign_id, CampaignStatus::Active, "start").await
+}
+
+/// Pausing freezes the campaign: nothing is sent, followed up or closed for
+/// its messages until it is resumed.
+pub async fn pause_campaign(
+    State(pool): State<SqlitePool>,
+    Path(campaign_id): Path<i64>,
+) -> ApiResult<CampaignWithDetails> {
+    change_campaign_status(&pool, campaign_id, CampaignStatus::Paused, "pause").await
+}
+
+pub async fn resume_campaign(
+    State(pool): State<SqlitePool>,
+    Path(campaign_id): Path<i64>,
+) -> ApiResult<CampaignWithDetails> {
+    change_campaign_status(&pool, campaign_id, CampaignStatus::Active, "resume").await
+}
+
+/// Archived campaigns send nothing more and cannot be restarted.
+pub async fn archive_campaign(
+    State(pool): State<SqlitePool>,
+    Path(campaign_id): Path<i64>,
+) -> ApiResult<CampaignWithDetails> {
+    change_campaign_status(&pool, campaign_id, CampaignStatus::Archived, "archive").await
+}
+
+async fn change_campaign_status(
+    pool: &SqlitePool,
+    campaign_id: i64,
+    to: CampaignStatus,
+    action: &str,
+) -> ApiResult<CampaignWithDetails> {
+    info!("Campaign {}: {}", campaign_id, action);
+
+    let campaign = match campaigns::transition(pool, campaign_id, to).await {
+        Ok(Transition::Done(campaign)) => *campaign,
+        Ok(Transition::NotFound) => {
+            return Err(api_error(StatusCode::NOT_FOUND, "Campaign not found"));
+        }
+        Ok(Transition::Invalid(status)) => {
+            return Err(api_error(
+                StatusCode::CONFLICT,
+                &format!("Cannot {} campaign, it is {}", action, status),
+            ));
+        }
+        Err(e) => {
+            error!("Failed to update campaign {}: {}", campaign_id, e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to update campaign",
+            ));
+        }
+    };
+
+    let result = async {
+        if to == CampaignStatus::Active {
+            enqueue_campaign_leads(pool, &campaign).await?;
+        }
+        campaign_details(pool, campaign).await
+    }
+    .await;
+
+    match result {
+        Ok(details) => Ok((StatusCode::OK, Json(details))),
+        Err(e) => {
+            error!("Failed to enqueue campaign {}: {}", campaign_id, e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to enqueue campaign messages",
+            ))
+        }
+    }
+}
+
+/// Enqueues the campaign's sequence for every lead on its list that has no
+/// message yet. Leads that cannot be sent to (suppressed, no consent, ...)
+/// are skipped with the reason.
+async fn enqueue_campaign_leads(pool: &SqlitePool, campaign: &Campaign) -> Result<(), sqlx::Error> {
+    let sequence = &campaign.sequence.0;
+
+    for lead_id in campaigns::pending_leads(pool, campaign.id).await? {
+        let request = SendMessageRequest {
+            lead_id,
+            message: sequence.message.clone(),
+            subject: sequence.subject.clone(),
+            experiment_id: sequence.experiment_id,
+            sender_id: None,
+            sender_pool_id: campaign.sender_pool_id,
+            follow_up: sequence.follow_up.clone(),
+            channel: Some(sequence.channel),
+            contact_id: None,
+            format: Some(sequence.format),
+            attachment_ids: sequence.attachment_ids.clone(),
+            track: sequence.track,
+        };
+
+        match enqueue_message(pool, &request, Some(campaign.id)).await {
+            Ok(message) => {
+                campaigns::record_enqueued(pool, campaign.id, lead_id, Ok(message.id)).await?;
+            }
+            Err((_, Json(reason))) => {
+                info!(
+                    "Campaign {}: skipping lead {}: {}",
+                    campaign.id, lead_id, reason.error
+                );
+                campaigns::record_enqueued(pool, campaign.id, lead_id, Err(&reason.error))
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that every lead exists and belongs to the campaign's workspace.
+async fn check_campaign_leads(
+    pool: &SqlitePool,
+    workspace_id: i64,
+    lead_ids: &[i64],
+) -> Result<(), (StatusCode, Json<ApiError>)> {
+    for lead_id in lead_ids {
+        match fetch_lead(pool, *lead_id).await {
+            Ok(Some(lead)) if lead.workspace_id == workspace_id => {}
+            Ok(Some(_)) => {
+                return Err(api_error(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "Lead belongs to another workspace",
+                ));
+            }
+            Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Lead not found")),
+            Err(e) => {
+                error!("Failed to fetch lead: {}", e);
+                return Err(api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Database error",
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn campaign_details(
+    pool: &SqlitePool,
+    campaign: Campaign,
+) -> Result<CampaignWithDetails, sqlx::Error> {
+    let leads = campaigns::leads(pool, campaign.id).await?;
+    let messages = campaigns::message_counts(pool, campaign.id).await?;
+
+    Ok(CampaignWithDetails {
+        campaign,
+        leads,
+        messages,
+    })
+}
+
+pub async fn create_experiment(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<CreateExperimentRequest>,
+) -> ApiResult<ExperimentWithStats> {
+    info!("Creating experiment: {:?}", payload);
+
+    if payload.name.trim().is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Name is required"));
+    }
+
+    if payload.variants.len() < 2 {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "At least two variants are required",
+        ));
+    }
+
+    for variant in &payload.variants {
+        if variant.name.trim().is_empty() {
+            return Err(api_error(StatusCode::BAD_REQUEST, "Variant name is required"));
+        }
+        if variant.subject.is_none()
+            && variant.message.is_none()
+            && variant.follow_up.is_none()
+            && variant.attachment_ids.is_none()
+        {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                "A variant needs a subject, message, follow-up or attachments",
+            ));
+        }
+        if variant.weight.is_some_and(|w| w <= 0) {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                "Variant weight must be positive",
+            ));
+        }
+        check_attachments(&pool, variant.attachment_ids.as_deref().unwrap_or_default()).await?;
+    }
+
+    if payload.auto_promote_after.is_some_and(|n| n <= 0) {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "auto_promote_after must be positive",
+        ));
+    }
+
+    if payload.settle_days.is_some_and(|days| days < 0) {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "settle_days cannot be negative",
+        ));
+    }
+
+    let result = async {
+        let mut tx = pool.begin().await?;
+        let now = Utc::now().to_rfc3339();
+
+        let experiment = sqlx::query_as::<_, Experiment>(
+            r#"
+            INSERT INTO experiments (name, auto_promote_after, settle_days, created_at)
+            VALUES (?, ?, ?, ?)
+            RETURNING id, name, auto_promote_after, settle_days, winner_variant_id, promoted_at, created_at
+            "#,
+        )
+        .bind(&payload.name)
+        .bind(payload.auto_promote_after)
+        .bind(payload.settle_days.unwrap_or(experiments::DEFAULT_SETTLE_DAYS))
+        .bind(&now)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        for variant in &payload.variants {
+            sqlx::query(
+                r#"
+                INSERT INTO experiment_variants (experiment_id, name, subject, message, follow_up, attachment_ids, weight, created_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(experiment.id)
+            .bind(&variant.name)
+            .bind(&variant.subject)
+            .bind(&variant.message)
+            .bind(&variant.follow_up)
+            .bind(variant.attachment_ids.as_ref().map(SqlJson))
+            .bind(variant.weight.unwrap_or(1))
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        let variants = experiments::variant_stats(&pool, experiment.id).await?;
+        Ok::<_, sqlx::Error>(ExperimentWithStats {
+            experiment,
+            variants,
+        })
+    }
+    .await;
+
+    match result {
+        Ok(experiment) => {
+            info!("Experiment created with id: {}", experiment.experiment.id);
+            Ok((StatusCode::CREATED, Json(experiment)))
+        }
+        Err(e) => {
+            error!("Failed to create experiment: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create experiment",
+            ))
+        }
+    }
+}
+
+/// An experiment with per-variant send, reply, open and click rates.
+pub async fn get_experiment(
+    State(pool): State<SqlitePool>,
+    Path(experiment_id): Path<i64>,
+) -> ApiResult<ExperimentWithStats> {
+    let experiment = match experiments::fetch(&pool, experiment_id).await {
+        Ok(Some(experiment)) => experiment,
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Experiment not found")),
+        Err(e) => {
+            error!("Failed to fetch experiment: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    };
+
+    match experiments::variant_stats(&pool, experiment_id).await {
+        Ok(variants) => Ok((
+            StatusCode::OK,
+            Json(ExperimentWithStats {
+                experiment,
+                variants,
+            }),
+        )),
+        Err(e) => {
+            error!("Failed to compute experiment stats: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ))
+        }
+    }
+}
+
+/// Picks the winner by hand; later sends of the experiment all use it.
+pub async fn promote_experiment_variant(
+    State(pool): State<SqlitePool>,
+    Path(experiment_id): Path<i64>,
+    Json(payload): Json<PromoteVariantRequest>,
+) -> ApiResult<Experiment> {
+    info!(
+        "Promoting variant {} of experiment {}",
+        payload.variant_id, experiment_id
+    );
+
+    let variants = match experiments::variants(&pool, experiment_id).await {
+        Ok(variants) => variants,
+        Err(e) => {
+            error!("Failed to fetch experiment variants: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    };
+
+    if !variants.iter().any(|v| v.id == payload.variant_id) {
+        return Err(api_error(StatusCode::NOT_FOUND, "Variant not found"));
+    }
+
+    match experiments::promote(&pool, experiment_id, payload.variant_id).await {
+        Ok(Some(experiment)) => Ok((StatusCode::OK, Json(experiment))),
+        Ok(None) => Err(api_error(StatusCode::NOT_FOUND, "Experiment not found")),
+        Err(e) => {
+            error!("Failed to promote variant: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to promote variant",
+            ))
+        }
+    }
+}
+
+/// Open-tracking pixel. The GIF is served for any token so a bad or
+/// replayed link never shows a broken image.
+pub async fn track_open(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(message_id) = tracking::open_message_id(&config, &token) {
+        let user_agent = headers
+            .get(header::USER_AGENT)
+            .and_then(|v| v.to_str().ok());
+        match tracking::record_event(&pool, message_id, TrackingEvent::Opened, user_agent, None)
+            .await
+        {
+            Ok(true) => info!("Message {} opened", message_id),
+            Ok(false) => {}
+            Err(e) => error!("Failed to record open for message {}: {}", message_id, e),
+        }
+    }
+
+    (
+        [
+            (header::CONTENT_TYPE, "image/gif"),
+            (header::CACHE_CONTROL, "no-store, max-age=0"),
+        ],
+        tracking::PIXEL_GIF,
+    )
+        .into_response()
+}
+
+/// Click-tracking redirect to the link's original destination.
+pub async fn track_click(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let Some((message_id, url)) = tracking::click_target(&config, &token)
+        .filter(|(_, url)| tracking::is_trackable(url))
+    else {
+        return (
+            StatusCode::NOT_FOUND,
+            Html("<p>This link is not valid.</p>".to_string()),
+        )
+            .into_response();
+    };
+
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok());
+    match tracking::record_event(
+        &pool,
+        message_id,
+        TrackingEvent::Clicked,
+        user_agent,
+        Some(&url),
+    )
+    .await
+    {
+        Ok(true) => info!("Message {} link clicked: {}", message_id, url),
+        Ok(false) => {}
+        Err(e) => error!("Failed to record click for message {}: {}", message_id, e),
+    }
+
+    Redirect::to(&url).into_response()
+}
+
+pub async fn upload_attachment(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Query(query): Query<UploadAttachmentQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> ApiResult<Attachment> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("application/octet-stream");
+
+    info!(
+        "Uploading attachment {} ({}, {} bytes)",
+        query.filename,
+        content_type,
+        body.len()
+    );
+
+    match attachments::store(&pool, &config, &query.filename, content_type, &body).await {
+        Ok(attachment) => {
+            info!("Attachment created with id: {}", attachment.id);
+            Ok((StatusCode::CREATED, Json(attachment)))
+        }
+        Err(e @ AttachmentError::TooLarge(_)) => {
+            Err(api_error(StatusCode::PAYLOAD_TOO_LARGE, &e.to_string()))
+        }
+        Err(e @ AttachmentError::UnsupportedType(_)) => Err(api_error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            &e.to_string(),
+        )),
+        Err(e @ (AttachmentError::Empty | AttachmentError::ContentMismatch(_))) => {
+            Err(api_error(StatusCode::UNPROCESSABLE_ENTITY, &e.to_string()))
+        }
+        Err(e) => {
+            error!("Failed to store attachment: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to store attachment",
+            ))
+        }
+    }
+}
+
+pub async fn create_chat_channel(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<CreateChatChannelRequest>,
+) -> ApiResult<ChatChannel> {
+    info!("Creating chat channel: {}", payload.name);
+
+    if payload.name.trim().is_empty() || payload.webhook_url.trim().is_empty() {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Name and webhook_url are required",
+        ));
+    }
+
+    let result = sqlx::query_as::<_, ChatChannel>(
+        r#"
+        INSERT INTO chat_channels (name, webhook_url, secret, created_at)
+        VALUES (?, ?, ?, ?)
+        RETURNING id, name, webhook_url, secret, created_at
+        "#,
+    )
+    .bind(payload.name.trim())
+    .bind(payload.webhook_url.trim())
+    .bind(&payload.secret)
+    .bind(Utc::now().to_rfc3339())
+    .fetch_one(&pool)
+    .await;
+
+    match result {
+        Ok(channel) => {
+            info!("Chat channel created with id: {}", channel.id);
+            Ok((StatusCode::CREATED, Json(channel)))
+        }
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Err(api_error(
+            StatusCode::CONFLICT,
+            "A chat channel with this name already exists",
+        )),
+        Err(e) => {
+            error!("Failed to create chat channel: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create chat channel",
+            ))
+        }
+    }
+}
+
+pub async fn list_chat_channels(State(pool): State<SqlitePool>) -> ApiResult<Vec<ChatChannel>> {
+    let result = sqlx::query_as::<_, ChatChannel>(
+        "SELECT id, name, webhook_url, secret, created_at FROM chat_channels ORDER BY id ASC",
+    )
+    .fetch_all(&pool)
+    .await;
+
+    match result {
+        Ok(channels) => Ok((StatusCode::OK, Json(channels))),
+        Err(e) => {
+            error!("Failed to list chat channels: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ))
+        }
+    }
+}
+
+pub async fn create_lead_contact(
+    State(pool): State<SqlitePool>,
+    Path(lead_id): Path<i64>,
+    Json(payload): Json<CreateContactRequest>,
+) -> ApiResult<LeadContact> {
+    info!("Adding contact for lead_id: {}: {:?}", lead_id, payload);
+
+    if payload.handle.trim().is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Handle is required"));
+    }
+
+    match fetch_lead(&pool, lead_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Lead not found")),
+        Err(e) => {
+            error!("Failed to fetch lead: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    }
+
+    match chat::fetch_channel(&pool, payload.chat_channel_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(api_error(StatusCode::NOT_FOUND, "Chat channel not found")),
+        Err(e) => {
+            error!("Failed to fetch chat channel: {}", e);
+            return Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ));
+        }
+    }
+
+    let result = sqlx::query_as::<_, LeadContact>(
+        r#"
+        INSERT INTO lead_contacts (lead_id, chat_channel_id, handle, created_at)
+        VALUES (?, ?, ?, ?)
+        RETURNING id, lead_id, chat_channel_id, handle, created_at
+        "#,
+    )
+    .bind(lead_id)
+    .bind(payload.chat_channel_id)
+    .bind(payload.handle.trim())
+    .bind(Utc::now().to_rfc3339())
+    .fetch_one(&pool)
+    .await;
+
+    match result {
+        Ok(contact) => Ok((StatusCode::CREATED, Json(contact))),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Err(api_error(
+            StatusCode::CONFLICT,
+            "This handle already belongs to a lead",
+        )),
+        Err(e) => {
+            error!("Failed to create contact: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create contact",
+            ))
+        }
+    }
+}
+
+pub async fn create_workspace(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<WorkspaceRequest>,
+) -> ApiResult<Workspace> {
+    info!("Creating workspace: {:?}", payload);
+
+    if payload.name.trim().is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Name is required"));
+    }
+
+    if let Err(e) = guardrails::validate(&payload.ai_policy) {
+        return Err(api_error(StatusCode::BAD_REQUEST, &e));
+    }
+    if payload.ai_monthly_budget.is_some_and(|budget| budget < 0.0) {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "AI monthly budget cannot be negative",
+        ));
+    }
+    if let Err(e) = scoring::validate(&payload.lead_scoring) {
+        return Err(api_error(StatusCode::BAD_REQUEST, &e));
+    }
+
+    let result = sqlx::query_as::<_, Workspace>(
+        r#"
+        INSERT INTO workspaces (name, physical_address, footer, strict_consent, created_at, ai_policy, ai_monthly_budget, lead_scoring)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        RETURNING id, name, physical_address, footer, strict_consent, created_at, ai_policy, ai_monthly_budget, lead_scoring
+        "#,
+    )
+    .bind(&payload.name)
+    .bind(&payload.physical_address)
+    .bind(&payload.footer)
+    .bind(payload.strict_consent)
+    .bind(Utc::now().to_rfc3339())
+    .bind(SqlJson(&payload.ai_policy))
+    .bind(payload.ai_monthly_budget)
+    .bind(SqlJson(&payload.lead_scoring))
+    .fetch_one(&pool)
+    .await;
+
+    match result {
+        Ok(workspace) => {
+            info!("Workspace created with id: {}", workspace.id);
+            Ok((StatusCode::CREATED, Json(workspace)))
+        }
+        Err(e) => {
+            error!("Failed to create workspace: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create workspace",
+            ))
+        }
+    }
+}
+
+pub async fn list_workspaces(State(pool): State<SqlitePool>) -> ApiResult<Vec<Workspace>> {
+    let result = sqlx::query_as::<_, Workspace>(
+        "SELECT id, name, physical_address, footer, strict_consent, created_at, ai_policy, ai_monthly_budget, lead_scoring FROM workspaces ORDER BY id ASC",
+    )
+    .fetch_all(&pool)
+    .await;
+
+    match result {
+        Ok(workspaces) => Ok((StatusCode::OK, Json(workspaces))),
+        Err(e) => {
+            error!("Failed to list workspaces: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error",
+            ))
+        }
+    }
+}
+
+pub async fn update_workspace(
+    State(pool): State<SqlitePool>,
+    Path(workspace_id): Path<i64>,
+    Json(payload): Json<WorkspaceRequest>,
+) -> ApiResult<Workspace> {
+    info!("Updating workspace {}: {:?}", workspace_id, payload);
+
+    if payload.name.trim().is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Name is required"));
+    }
+
+    if let Err(e) = guardrails::validate(&payload.ai_policy) {
+        return Err(api_error(StatusCode::BAD_REQUEST, &e));
+    }
+    if payload.ai_monthly_budget.is_some_and(|budget| budget < 0.0) {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "AI monthly budget cannot be negative",
+        ));
+    }
+    if let Err(e) = scoring::validate(&payload.lead_scoring) {
+        return Err(api_error(StatusCode::BAD_REQUEST, &e));
+    }
+
+    let result = sqlx::query_as::<_, Workspace>(
+        r#"
+        UPDATE workspaces
+        SET name = ?, physical_address = ?, footer = ?, strict_consent = ?, ai_policy = ?, ai_monthly_budget = ?, lead_scoring = ?
+        WHERE id = ?
+        RETURNING id, name, physical_address, footer, strict_consent, created_at, ai_policy, ai_monthly_budget, lead_scoring
+        "#,
+    )
+    .bind(&payload.name)
+    .bind(&payload.physical_address)
+    .bind(&payload.footer)
+    .bind(payload.strict_consent)
+    .bind(SqlJson(&payload.ai_policy))
+    .bind(payload.ai_monthly_budget)
+    .bind(SqlJson(&payload.lead_scoring))
+    .bind(workspace_id)
+    .fetch_optional(&pool)
+    .await;
+
+    match result {
+        Ok(Some(workspace)) => {
+            // Rescoring every lead can take a while, the response does not wait.
+            let workspace_id = workspace.id;
+            tokio::spawn(async move { scoring::rescore_all(&pool, Some(workspace_id)).await });
+            Ok((StatusCode::OK, Json(workspace)))
+        }
+        Ok(None) => Err(api_error(StatusCode::NOT_FOUND, "Workspace not found")),
+        Err(e) => {
+            error!("Failed to update workspace: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to update workspace",
+            ))
+        }
+    }
+}
+
+pub async fn fetch_workspace(
+    pool: &SqlitePool,
+    workspace_id: i64,
+) -> Result<Option<Workspace>, sqlx::Error> {
+    sqlx::query_as::<_, Workspace>(
+        "SELECT id, name, physical_address, footer, strict_consent, created_at, ai_policy, ai_monthly_budget, lead_scoring FROM workspaces WHERE id = ?",
+    )
+    .bind(workspace_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn fetch_lead(pool: &SqlitePool, lead_id: i64) -> Result<Option<Lead>, sqlx::Error> {
+    sqlx::query_as::<_, Lead>(
+        r#"
+        SELECT id, name, email, phone, email_undeliverable_at, email_undeliverable_reason, workspace_id, consent_status, consent_source, consent_at, score, score_updated_at, fields
+        FROM leads
+        WHERE id = ?
+        "#,
+    )
+    .bind(lead_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn fetch_message(
+    pool: &SqlitePool,
+    message_id: i64,
+) -> Result<Option<Message>, sqlx::Error> {
+    sqlx::query_as::<_, Message>(
+        r#"
+        SELECT id, leads_id, message_sent, sent_at, reply_received, reply_received_at, ai_reply, ai_reply_sent, created_at, status, follow_up_at, closed_at, sender_id, scheduled_at, email_message_id, follow_up_body, bounced_at, bounce_type, bounce_reason, channel, contact_id, body_format, message_html, tracking, open_count, click_count, subject, variant_id, campaign_id, reply_intent, reply_intent_source, snoozed_until, prompt_template_id
+        FROM messages
+        WHERE id = ?
+        "#,
+    )
+    .bind(message_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn fetch_sender(
+    pool: &SqlitePool,
+    sender_id: i64,
+) -> Result<Option<SenderIdentity>, sqlx::Error> {
+    sqlx::query_as::<_, SenderIdentity>(
+        r#"
+        SELECT id, name, address, daily_cap, hourly_cap, min_spacing_seconds, created_at, signature, reply_to, transport, workspace_id
+        FROM sender_identities
+        WHERE id = ?
+        "#,
+    )
+    .bind(sender_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn log_outreach(pool: &SqlitePool, message_id: i64, status: MessageStatus) {
+    log_outreach_body(pool, message_id, status, None).await
+}
+
+/// Logs an outreach step with the text that came with it.
+pub async fn log_outreach_body(
+    pool: &SqlitePool,
+    message_id: i64,
+    status: MessageStatus,
+    body: Option<&str>,
+) {
+    let now = Utc::now().to_rfc3339();
+    let step = status.as_str();
+
+    let result = sqlx::query(
+        "INSERT INTO outreach_log (message_id, log_at, step, body) VALUES (?, ?, ?, ?)",
+    )
+    .bind(message_id)
+    .bind(&now)
+    .bind(step)
+    .bind(body)
+    .execute(pool)
+    .await;
+
+    if let Err(e) = result {
         error!(
             "Failed to log outreach for message_id {}: {}",
             message_id, e
         );
+        return;
     }
+
+    // Rescoring reads every event of the lead, the request does not wait.
+    let pool = pool.clone();
+    tokio::spawn(async move { scoring::rescore_message_lead(&pool, message_id).await });
 }
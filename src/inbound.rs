@@ -0,0 +1,295 @@
+use std::path::Path;
+
+use mail_parser::{Address, MessageParser};
+use sqlx::SqlitePool;
+use tracing::{error, info, warn};
+
+use crate::bounce::{find_message_for_address, parse_dsn, record_bounce};
+use crate::config::Config;
+use crate::autoreply::has_auto_reply_headers;
+use crate::handlers::{record_auto_reply, record_reply};
+use crate::models::InboundResult;
+
+/// The parts of a raw inbound email needed to match and record a reply.
+#[derive(Debug, Clone, Default)]
+pub struct InboundEmail {
+    pub in_reply_to: Vec<String>,
+    pub references: Vec<String>,
+    pub recipients: Vec<String>,
+    pub text: String,
+    /// Sent by an auto-responder according to its headers.
+    pub auto_submitted: bool,
+}
+
+#[derive(Debug)]
+pub enum IngestError {
+    Unparseable,
+    NoMatch,
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for IngestError {
+    fn from(e: sqlx::Error) -> Self {
+        IngestError::Database(e)
+    }
+}
+
+/// Parses a raw RFC 822 email and matches it to the message it concerns.
+/// Delivery status notifications are recorded as bounces and emails with
+/// auto-responder headers as auto-replies; anything else is treated as a
+/// reply and its stripped text recorded the same way `POST /reply` does.
+pub async fn ingest(
+    pool: &SqlitePool,
+    config: &Config,
+    raw: &[u8],
+) -> Result<InboundResult, IngestError> {
+    let parsed = MessageParser::default()
+        .parse(raw)
+        .ok_or(IngestError::Unparseable)?;
+
+    if let Some(bounce) = parse_dsn(&parsed) {
+        let message_id = find_message_for_address(
+            pool,
+            &bounce.original_message_ids,
+            bounce.recipient.as_deref(),
+        )
+        .await?
+        .ok_or(IngestError::NoMatch)?;
+
+        let message = record_bounce(pool, message_id, &bounce)
+            .await?
+            .ok_or(IngestError::NoMatch)?;
+
+        return Ok(InboundResult {
+            message,
+            reply: None,
+            auto_reply: false,
+            bounce_type: Some(bounce.bounce_type),
+        });
+    }
+
+    let email = InboundEmail::from_parsed(&parsed);
+
+    let message_id = find_message(pool, &email)
+        .await?
+        .ok_or(IngestError::NoMatch)?;
+
+    let reply = strip_quoted(&email.text);
+    let message = if email.auto_submitted {
+        record_auto_reply(pool, message_id, &reply, "headers").await?
+    } else {
+        record_reply(pool, config, message_id, &reply).await?
+    }
+    .ok_or(IngestError::NoMatch)?;
+
+    Ok(InboundResult {
+        auto_reply: message.reply_received_at.is_none(),
+        message,
+        reply: Some(reply),
+        bounce_type: None,
+    })
+}
+
+impl InboundEmail {
+    pub fn from_parsed(parsed: &mail_parser::Message<'_>) -> Self {
+        let ids = |list: Option<&[std::borrow::Cow<'_, str>]>| -> Vec<String> {
+            list.unwrap_or_default()
+                .iter()
+                .map(|id| normalize_message_id(id))
+                .collect()
+        };
+
+        let mut recipients = Vec::new();
+        for address in [parsed.to(), parsed.cc()].into_iter().flatten() {
+            collect_addresses(address, &mut recipients);
+        }
+        for header in ["Delivered-To", "X-Original-To"] {
+            if let Some(value) = parsed.header_raw(header) {
+                recipients.push(value.trim().trim_matches(['<', '>']).to_lowercase());
+            }
+        }
+
+        InboundEmail {
+            in_reply_to: ids(parsed.in_reply_to().as_text_list()),
+            references: ids(parsed.references().as_text_list()),
+            recipients,
+            text: parsed
+                .body_text(0)
+                .map(|text| text.into_owned())
+                .unwrap_or_default(),
+            auto_submitted: has_auto_reply_headers(parsed),
+        }
+    }
+}
+
+/// Looks up the originating message, first through the `In-Reply-To` and
+/// `References` headers, then through a `+m<id>` tag on the reply-to address.
+pub async fn find_message(
+    pool: &SqlitePool,
+    email: &InboundEmail,
+) -> Result<Option<i64>, sqlx::Error> {
+    for header_id in email
+        .in_reply_to
+        .iter()
+        .chain(email.references.iter().rev())
+    {
+        let found = sqlx::query_scalar::<_, i64>(
+            "SELECT message_id FROM sent_emails WHERE email_message_id = ? ORDER BY id DESC LIMIT 1",
+        )
+        .bind(header_id)
+        .fetch_optional(pool)
+        .await?;
+
+        if found.is_some() {
+            return Ok(found);
+        }
+    }
+
+    for message_id in email.recipients.iter().filter_map(|r| tagged_message_id(r)) {
+        let found = sqlx::query_scalar::<_, i64>("SELECT id FROM messages WHERE id = ?")
+            .bind(message_id)
+            .fetch_optional(pool)
+            .await?;
+
+        if found.is_some() {
+            return Ok(found);
+        }
+    }
+
+    Ok(None)
+}
+
+/// Adds the `+m<id>` tag to the local part of a reply-to address.
+pub fn tag_address(address: &str, message_id: i64) -> String {
+    match address.split_once('@') {
+        Some((local, domain)) => {
+            let local = local.split_once('+').map_or(local, |(base, _)| base);
+            format!("{}+m{}@{}", local, message_id, domain)
+        }
+        None => address.to_string(),
+    }
+}
+
+fn tagged_message_id(address: &str) -> Option<i64> {
+    let (local, _) = address.split_once('@')?;
+    let (_, tag) = local.split_once('+')?;
+    tag.strip_prefix('m')?.parse().ok()
+}
+
+fn normalize_message_id(id: &str) -> String {
+    format!("<{}>", id.trim().trim_matches(['<', '>']))
+}
+
+fn collect_addresses(address: &Address<'_>, out: &mut Vec<String>) {
+    for addr in address.iter() {
+        if let Some(address) = addr.address() {
+            out.push(address.to_lowercase());
+        }
+    }
+}
+
+/// Keeps only the new text of a reply, dropping quoted history, reply
+/// attributions, forwarded headers and the sender's signature.
+pub fn strip_quoted(text: &str) -> String {
+    let mut kept: Vec<&str> = Vec::new();
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+
+        if trimmed == "wrote:" {
+            // Attribution wrapped over two lines: drop its first half too.
+            if kept
+                .last()
+                .is_some_and(|l| l.trim_start().starts_with("On "))
+            {
+                kept.pop();
+            }
+            break;
+        }
+
+        if is_history_marker(trimmed) || (trimmed.starts_with("From:") && !kept.is_empty()) {
+            break;
+        }
+
+        if trimmed == "--" || line == "-- " || trimmed.starts_with("Sent from my ") {
+            break;
+        }
+
+        if trimmed.starts_with('>') {
+            continue;
+        }
+
+        kept.push(line.trim_end());
+    }
+
+    kept.join("\n").trim().to_string()
+}
+
+fn is_history_marker(line: &str) -> bool {
+    (line.starts_with("On ") && line.ends_with("wrote:"))
+        || line.starts_with("-----Original Message-----")
+        || line.starts_with("________________________________")
+        || line.starts_with("---------- Forwarded message")
+}
+
+/// Ingests every file in the `new/` folder of a Maildir and moves it to
+/// `cur/` so it is only processed once.
+pub async fn poll_maildir(pool: &SqlitePool, config: &Config, maildir: &Path) {
+    let new_dir = maildir.join("new");
+    let cur_dir = maildir.join("cur");
+
+    let mut entries = match tokio::fs::read_dir(&new_dir).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Failed to read maildir {}: {}", new_dir.display(), e);
+            return;
+        }
+    };
+
+    if let Err(e) = tokio::fs::create_dir_all(&cur_dir).await {
+        error!("Failed to create maildir {}: {}", cur_dir.display(), e);
+        return;
+    }
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+
+        let raw = match tokio::fs::read(&path).await {
+            Ok(raw) => raw,
+            Err(e) => {
+                error!("Failed to read {}: {}", path.display(), e);
+                continue;
+            }
+        };
+
+        match ingest(pool, config, &raw).await {
+            Ok(result) => info!(
+                "Recorded {} from {} on message {}",
+                if result.bounce_type.is_some() {
+                    "bounce"
+                } else {
+                    "reply"
+                },
+                path.display(),
+                result.message.id
+            ),
+            Err(IngestError::NoMatch) => {
+                warn!("No matching message for {}", path.display());
+            }
+            Err(IngestError::Unparseable) => {
+                warn!("Could not parse {}", path.display());
+            }
+            Err(IngestError::Database(e)) => {
+                // Leave the file in new/ so it is retried on the next run.
+                error!("Failed to record {}: {}", path.display(), e);
+                continue;
+            }
+        }
+
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let target = cur_dir.join(format!("{}:2,S", name));
+        if let Err(e) = tokio::fs::rename(&path, &target).await {
+            error!("Failed to move {} to cur/: {}", path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,370 @@
+use sqlx::SqlitePool;
+use tracing::{error, info, warn};
+
+use crate::autoreply;
+use crate::config::Config;
+use crate::email::{OutgoingEmail, deliver};
+use crate::handlers::{fetch_lead, fetch_sender};
+use crate::models::{Message, ReplyIntent};
+use crate::suppression::is_opt_out;
+use crate::usage::{self, AiCall};
+
+const SYSTEM_PROMPT: &str = "You classify replies to sales outreach emails. \
+Answer with exactly one of these labels and nothing else: \
+interested, not_interested, out_of_office, wrong_person, unsubscribe, question.";
+
+const WRONG_PERSON_PHRASES: &[&str] = &[
+    "wrong person",
+    "not the right person",
+    "not the person",
+    "no longer with",
+    "no longer work",
+    "left the company",
+    "not responsible for",
+    "you should contact",
+    "you should reach out",
+];
+
+/// Checked before the interest rules, so negated interest ("not sure",
+/// "definitely not") is not read as interest.
+const NOT_INTERESTED_PHRASES: &[&str] = &[
+    "not interested",
+    "uninterested",
+    "not sure",
+    "definitely not",
+    "absolutely not",
+    "not for us",
+    "no thanks",
+    "no thank you",
+    "not a fit",
+    "not a good fit",
+    "not looking",
+    "all set",
+    "not at this time",
+    "not right now",
+    "no need",
+];
+
+const INTERESTED_PHRASES: &[&str] = &[
+    "interested",
+    "sounds good",
+    "sounds great",
+    "let's talk",
+    "lets talk",
+    "let's chat",
+    "happy to chat",
+    "love to",
+    "tell me more",
+    "book a",
+    "schedule a",
+    "set up a call",
+];
+
+/// Affirmations, counted only when they open the reply ("Sure, send it")
+/// and not in passing ("I'll make sure to reply").
+const INTERESTED_WORDS: &[&str] = &["yes", "sure", "absolutely", "definitely"];
+
+pub struct Classification {
+    pub intent: ReplyIntent,
+    /// `ai` or `rules`.
+    pub source: &'static str,
+}
+
+/// Classifies the reply on a message with the AI provider when one is
+/// configured, falling back to keyword rules when it is not, fails, gives an
+/// unknown answer or the workspace is over its AI budget.
+pub async fn classify(
+    pool: &SqlitePool,
+    config: &Config,
+    message_id: i64,
+    reply: &str,
+) -> Option<Classification> {
+    let workspace_id = match usage::message_workspace(pool, message_id).await {
+        Ok(workspace_id) => workspace_id,
+        Err(e) => {
+            error!(
+                "Failed to fetch the workspace of message {}: {}",
+                message_id, e
+            );
+            None
+        }
+    };
+
+    if let (Some(provider), Some(workspace_id)) = (config.ai_provider.as_deref(), workspace_id) {
+        let call = AiCall {
+            workspace_id,
+            purpose: "classify",
+            message_id: Some(message_id),
+        };
+        match usage::complete(pool, config, provider, &call, SYSTEM_PROMPT, reply).await {
+            Ok(answer) => match parse_label(&answer.text) {
+                Some(intent) => {
+                    return Some(Classification {
+                        intent,
+                        source: "ai",
+                    });
+                }
+                None => warn!(
+                    "AI provider answered with an unknown intent: {}",
+                    answer.text
+                ),
+            },
+            Err(e) => warn!("Failed to classify reply with AI, using rules: {}", e),
+        }
+    }
+
+    classify_with_rules(reply).map(|intent| Classification {
+        intent,
+        source: "rules",
+    })
+}
+
+/// Keyword rules, checked from the most to the least specific intent.
+pub fn classify_with_rules(reply: &str) -> Option<ReplyIntent> {
+    let text = reply.to_lowercase();
+    let words = words(&text);
+    let contains = |phrases: &[&str]| contains_phrase(&words, phrases);
+
+    if is_opt_out(reply) {
+        return Some(ReplyIntent::Unsubscribe);
+    }
+    if is_auto_reply_text(reply) {
+        return Some(ReplyIntent::OutOfOffice);
+    }
+    if contains(WRONG_PERSON_PHRASES) {
+        return Some(ReplyIntent::WrongPerson);
+    }
+    if contains(NOT_INTERESTED_PHRASES) {
+        return Some(ReplyIntent::NotInterested);
+    }
+    if shows_interest(&words) {
+        return Some(ReplyIntent::Interested);
+    }
+    if text.contains('?') {
+        return Some(ReplyIntent::Question);
+    }
+
+    None
+}
+
+/// Whether the reply's text reads as an out-of-office notice and neither
+/// shows interest nor asks anything. Only such replies are handled as
+/// auto-replies instead of replies, whoever classified them.
+pub fn is_auto_reply_text(reply: &str) -> bool {
+    let text = reply.to_lowercase();
+    autoreply::looks_like_out_of_office(&text)
+        && !shows_interest(&words(&text))
+        && !text.contains('?')
+}
+
+fn shows_interest(words: &[&str]) -> bool {
+    !contains_phrase(words, NOT_INTERESTED_PHRASES)
+        && (contains_phrase(words, INTERESTED_PHRASES)
+            || words
+                .first()
+                .is_some_and(|word| INTERESTED_WORDS.contains(word)))
+}
+
+fn words(text: &str) -> Vec<&str> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Whether any phrase appears as whole words, so "interested" does not
+/// match "uninterested".
+fn contains_phrase(words: &[&str], phrases: &[&str]) -> bool {
+    phrases.iter().any(|phrase| {
+        let phrase = self::words(phrase);
+        words.windows(phrase.len()).any(|window| window == phrase)
+    })
+}
+
+/// Reads the label out of the provider's answer, tolerating case, quotes,
+/// spaces for underscores and text around the label.
+fn parse_label(answer: &str) -> Option<ReplyIntent> {
+    let answer = answer
+        .trim()
+        .trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+        .replace([' ', '-'], "_");
+
+    if let Ok(intent) = answer.parse() {
+        return Some(intent);
+    }
+
+    // Longest labels first so `not_interested` wins over `interested`.
+    let mut labels = ReplyIntent::ALL;
+    labels.sort_by_key(|intent| std::cmp::Reverse(intent.as_str().len()));
+    labels
+        .into_iter()
+        .find(|intent| answer.contains(intent.as_str()))
+}
+
+/// Emails the sender identity that owns the message that the lead is
+/// interested. Messages without a sender are only logged.
+pub async fn notify_owner(pool: &SqlitePool, message: &Message, reply: &str) {
+    let Some(sender_id) = message.sender_id else {
+        info!(
+            "Lead replied with interest on message {}, no sender to notify",
+            message.id
+        );
+        return;
+    };
+
+    let result = async {
+        let sender = fetch_sender(pool, sender_id)
+            .await?
+            .ok_or("sender not found")?;
+        let lead = fetch_lead(pool, message.leads_id)
+            .await?
+            .ok_or("lead not found")?;
+
+        let subject = format!("{} is interested", lead.name);
+        let body = format!(
+            "{} ({}) replied to message {}:\n\n{}",
+            lead.name,
+            lead.email
+                .as_deref()
+                .or(lead.phone.as_deref())
+                .unwrap_or("no contact"),
+            message.id,
+            reply.trim()
+        );
+        let email = OutgoingEmail::notification(&sender, &subject, &body);
+        deliver(&sender.transport.0, &email).await?;
+
+        info!(
+            "Notified {} that lead {} is interested",
+            sender.address, lead.id
+        );
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+    }
+    .await;
+
+    if let Err(e) = result {
+        error!(
+            "Failed to notify the owner of message {}: {}",
+            message.id, e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_of_office_notices() {
+        for reply in [
+            "I am out of the office until March 3 with limited access to email.",
+            "Automatic reply: I'm on vacation and will respond when I return.",
+            "Out of office. For urgent matters contact support@example.com.",
+        ] {
+            assert_eq!(
+                classify_with_rules(reply),
+                Some(ReplyIntent::OutOfOffice),
+                "{}",
+                reply
+            );
+        }
+    }
+
+    #[test]
+    fn real_replies_are_not_out_of_office() {
+        assert_eq!(
+            classify_with_rules("Interested! I'll be back to you Monday with times."),
+            Some(ReplyIntent::Interested)
+        );
+        assert_eq!(
+            classify_with_rules("I'm on vacation next week, but sounds good, let's talk after."),
+            Some(ReplyIntent::Interested)
+        );
+        assert_eq!(
+            classify_with_rules("Out of office today, what does pricing look like?"),
+            Some(ReplyIntent::Question)
+        );
+        assert!(!is_auto_reply_text(
+            "Interested! I'll be back to you Monday."
+        ));
+    }
+
+    #[test]
+    fn intents_by_priority() {
+        assert_eq!(
+            classify_with_rules("Please unsubscribe me."),
+            Some(ReplyIntent::Unsubscribe)
+        );
+        assert_eq!(
+            classify_with_rules("I'm not the right person, you should contact Dana."),
+            Some(ReplyIntent::WrongPerson)
+        );
+        assert_eq!(
+            classify_with_rules("Not interested, thanks."),
+            Some(ReplyIntent::NotInterested)
+        );
+        assert_eq!(
+            classify_with_rules("Yes, send over a few times."),
+            Some(ReplyIntent::Interested)
+        );
+        assert_eq!(
+            classify_with_rules("How does this work with Salesforce?"),
+            Some(ReplyIntent::Question)
+        );
+        assert_eq!(classify_with_rules("Noted."), None);
+    }
+
+    #[test]
+    fn negated_interest_is_not_interest() {
+        for reply in [
+            "I'm not sure this is for us.",
+            "Definitely not.",
+            "Totally uninterested, thanks.",
+            "Absolutely not, please stop.",
+        ] {
+            assert_eq!(
+                classify_with_rules(reply),
+                Some(ReplyIntent::NotInterested),
+                "{}",
+                reply
+            );
+        }
+        assert_eq!(
+            classify_with_rules("Our schedule is full this quarter."),
+            None
+        );
+        assert_eq!(
+            classify_with_rules("Sure, let's set up a call."),
+            Some(ReplyIntent::Interested)
+        );
+        assert_eq!(
+            classify_with_rules("Can we schedule a demo next week"),
+            Some(ReplyIntent::Interested)
+        );
+    }
+
+    #[test]
+    fn out_of_office_wording_is_not_interest() {
+        let notice = "Out of office until Monday. I'll make sure to reply when I'm back, \
+            my schedule is packed until then.";
+        assert!(is_auto_reply_text(notice));
+        assert_eq!(classify_with_rules(notice), Some(ReplyIntent::OutOfOffice));
+    }
+
+    #[test]
+    fn parses_provider_labels() {
+        assert_eq!(
+            parse_label("Not interested."),
+            Some(ReplyIntent::NotInterested)
+        );
+        assert_eq!(
+            parse_label("\"out_of_office\""),
+            Some(ReplyIntent::OutOfOffice)
+        );
+        assert_eq!(
+            parse_label("Label: wrong person"),
+            Some(ReplyIntent::WrongPerson)
+        );
+        assert_eq!(parse_label("maybe"), None);
+    }
+}
@@ -1,8 +1,37 @@
+mod ai;
+mod analytics;
+mod attachments;
+mod autoreply;
+mod bounce;
+mod campaigns;
+mod chat;
+mod config;
 mod db;
+mod drafts;
+mod email;
+mod experiments;
+mod fields;
+mod guardrails;
 mod handlers;
+mod inbound;
+mod intent;
 mod models;
+mod prompts;
+mod reviews;
+mod richtext;
 mod routes;
 mod scheduler;
+mod scoring;
+mod segments;
+mod signing;
+mod sms;
+mod suppression;
+mod tags;
+mod throttle;
+mod tracking;
+mod usage;
+
+use std::sync::Arc;
 
 use tracing::info;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -19,12 +48,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("Starting Sales App");
 
+    let config = Arc::new(config::Config::from_env());
+
     let database_url = "sqlite:sales_app.db?mode=rwc";
     let pool = db::init_db(database_url).await?;
 
-    let _scheduler = scheduler::start_scheduler(pool.clone()).await?;
+    let _scheduler = scheduler::start_scheduler(pool.clone(), config.clone()).await?;
 
-    let app = routes::create_router(pool);
+    let app = routes::create_router(config::AppState { pool, config });
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3010").await?;
     info!("Server listening on http://0.0.0.0:3000");
@@ -1,5 +1,9 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use sqlx::FromRow;
+use sqlx::types::Json;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -11,6 +15,14 @@ pub enum MessageStatus {
     AiReplied,
     FollowUp,
     Closed,
+    Bounced,
+    Complained,
+    /// The AI reply broke the workspace's policy and waits for a human.
+    AiReview,
+    /// Only logged: auto-replies leave the message's status unchanged.
+    AutoReplied,
+    /// Only logged: soft bounces leave the message's status unchanged.
+    SoftBounced,
 }
 
 impl MessageStatus {
@@ -23,6 +35,127 @@ impl MessageStatus {
             MessageStatus::AiReplied => "ai_replied",
             MessageStatus::FollowUp => "follow_up",
             MessageStatus::Closed => "closed",
+            MessageStatus::Bounced => "bounced",
+            MessageStatus::Complained => "complained",
+            MessageStatus::AiReview => "ai_review",
+            MessageStatus::AutoReplied => "auto_replied",
+            MessageStatus::SoftBounced => "soft_bounced",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplyIntent {
+    Interested,
+    NotInterested,
+    OutOfOffice,
+    WrongPerson,
+    Unsubscribe,
+    Question,
+}
+
+impl ReplyIntent {
+    pub const ALL: [ReplyIntent; 6] = [
+        ReplyIntent::Interested,
+        ReplyIntent::NotInterested,
+        ReplyIntent::OutOfOffice,
+        ReplyIntent::WrongPerson,
+        ReplyIntent::Unsubscribe,
+        ReplyIntent::Question,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReplyIntent::Interested => "interested",
+            ReplyIntent::NotInterested => "not_interested",
+            ReplyIntent::OutOfOffice => "out_of_office",
+            ReplyIntent::WrongPerson => "wrong_person",
+            ReplyIntent::Unsubscribe => "unsubscribe",
+            ReplyIntent::Question => "question",
+        }
+    }
+}
+
+impl std::str::FromStr for ReplyIntent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ReplyIntent::ALL
+            .into_iter()
+            .find(|intent| intent.as_str() == s)
+            .ok_or_else(|| format!("unknown reply intent: {}", s))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailKind {
+    Initial,
+    FollowUp,
+    AiReply,
+}
+
+impl EmailKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailKind::Initial => "initial",
+            EmailKind::FollowUp => "follow_up",
+            EmailKind::AiReply => "ai_reply",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    #[default]
+    Email,
+    Sms,
+    Chat,
+}
+
+impl Channel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Channel::Email => "email",
+            Channel::Sms => "sms",
+            Channel::Chat => "chat",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BodyFormat {
+    #[default]
+    Text,
+    Markdown,
+    Html,
+}
+
+impl BodyFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BodyFormat::Text => "text",
+            BodyFormat::Markdown => "markdown",
+            BodyFormat::Html => "html",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BounceType {
+    Hard,
+    Soft,
+}
+
+impl BounceType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BounceType::Hard => "hard",
+            BounceType::Soft => "soft",
         }
     }
 }
@@ -33,6 +166,17 @@ pub struct Lead {
     pub name: String,
     pub email: Option<String>,
     pub phone: Option<String>,
+    pub email_undeliverable_at: Option<String>,
+    pub email_undeliverable_reason: Option<String>,
+    pub workspace_id: i64,
+    pub consent_status: String,
+    pub consent_source: Option<String>,
+    pub consent_at: Option<String>,
+    /// Computed from the workspace's scoring rules, between 0 and 100.
+    pub score: i64,
+    pub score_updated_at: Option<String>,
+    /// Values of the workspace's custom fields, by key.
+    pub fields: Json<BTreeMap<String, Value>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -40,6 +184,141 @@ pub struct CreateLeadRequest {
     pub name: String,
     pub email: Option<String>,
     pub phone: Option<String>,
+    pub workspace_id: Option<i64>,
+    pub consent_status: Option<ConsentStatus>,
+    pub consent_source: Option<String>,
+    pub consent_at: Option<String>,
+    #[serde(default)]
+    pub fields: BTreeMap<String, Value>,
+}
+
+/// Replaces the values of a lead's custom fields.
+#[derive(Debug, Deserialize)]
+pub struct UpdateLeadFieldsRequest {
+    pub fields: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CustomFieldType {
+    Text,
+    Number,
+    Boolean,
+    /// `YYYY-MM-DD`.
+    Date,
+    Url,
+    /// One of the field's options.
+    Enum,
+}
+
+impl CustomFieldType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CustomFieldType::Text => "text",
+            CustomFieldType::Number => "number",
+            CustomFieldType::Boolean => "boolean",
+            CustomFieldType::Date => "date",
+            CustomFieldType::Url => "url",
+            CustomFieldType::Enum => "enum",
+        }
+    }
+}
+
+impl std::str::FromStr for CustomFieldType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(CustomFieldType::Text),
+            "number" => Ok(CustomFieldType::Number),
+            "boolean" => Ok(CustomFieldType::Boolean),
+            "date" => Ok(CustomFieldType::Date),
+            "url" => Ok(CustomFieldType::Url),
+            "enum" => Ok(CustomFieldType::Enum),
+            other => Err(format!("unknown custom field type: {}", other)),
+        }
+    }
+}
+
+/// A lead field a workspace defines beyond name, email and phone.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CustomField {
+    pub id: i64,
+    pub workspace_id: i64,
+    /// Name of the field in lead values, filters and template variables.
+    pub key: String,
+    pub label: String,
+    pub field_type: String,
+    /// Whether leads must have a value when created or updated.
+    pub required: bool,
+    /// Allowed values of an `enum` field.
+    pub options: Json<Vec<String>>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CustomFieldRequest {
+    pub workspace_id: Option<i64>,
+    pub key: String,
+    pub label: Option<String>,
+    pub field_type: CustomFieldType,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CustomFieldQuery {
+    pub workspace_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsentStatus {
+    Unknown,
+    Granted,
+    LegitimateInterest,
+    Withdrawn,
+}
+
+impl ConsentStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentStatus::Unknown => "unknown",
+            ConsentStatus::Granted => "granted",
+            ConsentStatus::LegitimateInterest => "legitimate_interest",
+            ConsentStatus::Withdrawn => "withdrawn",
+        }
+    }
+
+    /// Whether this status is a lawful basis for contacting the lead.
+    pub fn is_lawful_basis(&self) -> bool {
+        matches!(
+            self,
+            ConsentStatus::Granted | ConsentStatus::LegitimateInterest
+        )
+    }
+}
+
+impl std::str::FromStr for ConsentStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unknown" => Ok(ConsentStatus::Unknown),
+            "granted" => Ok(ConsentStatus::Granted),
+            "legitimate_interest" => Ok(ConsentStatus::LegitimateInterest),
+            "withdrawn" => Ok(ConsentStatus::Withdrawn),
+            other => Err(format!("unknown consent status: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateConsentRequest {
+    pub consent_status: ConsentStatus,
+    pub consent_source: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -56,12 +335,61 @@ pub struct Message {
     pub status: String,
     pub follow_up_at: Option<String>,
     pub closed_at: Option<String>,
+    pub sender_id: Option<i64>,
+    pub scheduled_at: Option<String>,
+    pub email_message_id: Option<String>,
+    pub follow_up_body: Option<String>,
+    pub bounced_at: Option<String>,
+    pub bounce_type: Option<String>,
+    pub bounce_reason: Option<String>,
+    pub channel: String,
+    pub contact_id: Option<i64>,
+    /// Format the initial message was submitted in.
+    pub body_format: String,
+    /// Sanitized HTML of the initial message; `message_sent` holds its
+    /// plain-text version.
+    pub message_html: Option<String>,
+    /// Whether opens and clicks are tracked for this message.
+    pub tracking: bool,
+    pub open_count: i64,
+    pub click_count: i64,
+    /// Email subject; follow-ups and AI replies are sent as `Re:` of it.
+    pub subject: Option<String>,
+    /// Experiment variant the message was written from.
+    pub variant_id: Option<i64>,
+    pub campaign_id: Option<i64>,
+    pub reply_intent: Option<String>,
+    /// Whether the intent came from the AI provider or the fallback rules.
+    pub reply_intent_source: Option<String>,
+    /// Follow-ups and closing wait until then, after an out-of-office reply.
+    pub snoozed_until: Option<String>,
+    /// Prompt template version the AI reply was generated with.
+    pub prompt_template_id: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct SendMessageRequest {
     pub lead_id: i64,
+    /// Required unless the experiment's variant provides the message.
+    #[serde(default)]
     pub message: String,
+    pub subject: Option<String>,
+    /// Picks the lead's variant of an experiment, which overrides the
+    /// subject, message and follow-up it defines.
+    pub experiment_id: Option<i64>,
+    pub sender_id: Option<i64>,
+    pub sender_pool_id: Option<i64>,
+    pub follow_up: Option<String>,
+    pub channel: Option<Channel>,
+    /// Chat handle to message on, defaults to the lead's first one.
+    pub contact_id: Option<i64>,
+    /// How `message` is written, plain text by default.
+    pub format: Option<BodyFormat>,
+    /// Uploaded attachments to include with the initial email.
+    pub attachment_ids: Option<Vec<i64>>,
+    /// Adds an open-tracking pixel and rewrites links to track clicks.
+    #[serde(default)]
+    pub track: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -81,16 +409,809 @@ pub struct OutreachLog {
     pub message_id: i64,
     pub log_at: String,
     pub step: String,
+    pub user_agent: Option<String>,
+    pub url: Option<String>,
+    /// Text of an auto-reply.
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackingEvent {
+    Opened,
+    Clicked,
+}
+
+impl TrackingEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrackingEvent::Opened => "opened",
+            TrackingEvent::Clicked => "clicked",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Experiment {
+    pub id: i64,
+    pub name: String,
+    /// Sends per variant after which the best variant is promoted.
+    pub auto_promote_after: Option<i64>,
+    /// Days a send must have had for replies before it counts towards
+    /// auto-promotion.
+    pub settle_days: i64,
+    pub winner_variant_id: Option<i64>,
+    pub promoted_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ExperimentVariant {
+    pub id: i64,
+    pub experiment_id: i64,
+    pub name: String,
+    pub subject: Option<String>,
+    pub message: Option<String>,
+    pub follow_up: Option<String>,
+    pub attachment_ids: Option<Json<Vec<i64>>>,
+    pub weight: i64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateExperimentRequest {
+    pub name: String,
+    pub variants: Vec<CreateVariantRequest>,
+    pub auto_promote_after: Option<i64>,
+    pub settle_days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateVariantRequest {
+    pub name: String,
+    pub subject: Option<String>,
+    pub message: Option<String>,
+    pub follow_up: Option<String>,
+    /// Replaces the attachments of the send with these uploads.
+    pub attachment_ids: Option<Vec<i64>>,
+    pub weight: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PromoteVariantRequest {
+    pub variant_id: i64,
+}
+
+/// A variant with the outcome of the messages written from it. Rates are
+/// over sent messages; the open and click rates over tracked ones.
+#[derive(Debug, Clone, Serialize)]
+pub struct VariantStats {
+    #[serde(flatten)]
+    pub variant: ExperimentVariant,
+    pub sent: i64,
+    pub replied: i64,
+    pub tracked: i64,
+    pub opened: i64,
+    pub clicked: i64,
+    pub reply_rate: f64,
+    pub open_rate: f64,
+    pub click_rate: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExperimentWithStats {
+    pub experiment: Experiment,
+    pub variants: Vec<VariantStats>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CampaignStatus {
+    Draft,
+    Active,
+    Paused,
+    Archived,
+}
+
+impl CampaignStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CampaignStatus::Draft => "draft",
+            CampaignStatus::Active => "active",
+            CampaignStatus::Paused => "paused",
+            CampaignStatus::Archived => "archived",
+        }
+    }
+}
+
+impl std::str::FromStr for CampaignStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "draft" => Ok(CampaignStatus::Draft),
+            "active" => Ok(CampaignStatus::Active),
+            "paused" => Ok(CampaignStatus::Paused),
+            "archived" => Ok(CampaignStatus::Archived),
+            other => Err(format!("unknown campaign status: {}", other)),
+        }
+    }
+}
+
+/// What a campaign sends to each of its leads: the initial message and its
+/// follow-up, with the same options as `/send`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CampaignSequence {
+    #[serde(default)]
+    pub channel: Channel,
+    #[serde(default)]
+    pub format: BodyFormat,
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub message: String,
+    pub follow_up: Option<String>,
+    pub experiment_id: Option<i64>,
+    pub attachment_ids: Option<Vec<i64>>,
+    #[serde(default)]
+    pub track: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Campaign {
+    pub id: i64,
+    pub name: String,
+    pub workspace_id: i64,
+    pub status: String,
+    pub sender_pool_id: Option<i64>,
+    pub sequence: Json<CampaignSequence>,
+    pub created_at: String,
+    pub started_at: Option<String>,
+    pub paused_at: Option<String>,
+    pub archived_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCampaignRequest {
+    pub name: String,
+    pub workspace_id: Option<i64>,
+    pub sender_pool_id: Option<i64>,
+    pub sequence: CampaignSequence,
+    pub lead_ids: Option<Vec<i64>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddCampaignLeadsRequest {
+    #[serde(default)]
+    pub lead_ids: Vec<i64>,
+    /// Adds the leads the segment matches when the request is made.
+    pub segment_id: Option<i64>,
+}
+
+/// A lead on a campaign's list, with the message enqueued for it once the
+/// campaign started, or why it was skipped.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CampaignLead {
+    pub campaign_id: i64,
+    pub lead_id: i64,
+    pub message_id: Option<i64>,
+    pub skipped_reason: Option<String>,
+    pub added_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CampaignWithDetails {
+    pub campaign: Campaign,
+    pub leads: Vec<CampaignLead>,
+    /// Number of the campaign's messages in each status.
+    pub messages: BTreeMap<String, i64>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct LeadWithDetails {
     pub lead: Lead,
+    pub contacts: Vec<LeadContact>,
     pub messages: Vec<Message>,
+    pub attachments: Vec<MessageAttachment>,
     pub outreach_logs: Vec<OutreachLog>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SenderIdentity {
+    pub id: i64,
+    pub name: String,
+    pub address: String,
+    pub daily_cap: Option<i64>,
+    pub hourly_cap: Option<i64>,
+    pub min_spacing_seconds: i64,
+    pub created_at: String,
+    pub signature: Option<String>,
+    pub reply_to: Option<String>,
+    pub transport: Json<TransportSettings>,
+    pub workspace_id: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TransportSettings {
+    /// Only logs the rendered email, nothing leaves the machine.
+    #[default]
+    Log,
+    /// Writes each rendered email as an `.eml` file into `dir`.
+    Outbox { dir: String },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSenderRequest {
+    pub name: String,
+    pub address: String,
+    pub daily_cap: Option<i64>,
+    pub hourly_cap: Option<i64>,
+    pub min_spacing_seconds: Option<i64>,
+    pub signature: Option<String>,
+    pub reply_to: Option<String>,
+    pub transport: Option<TransportSettings>,
+    pub workspace_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Workspace {
+    pub id: i64,
+    pub name: String,
+    pub physical_address: Option<String>,
+    pub footer: Option<String>,
+    pub strict_consent: bool,
+    pub created_at: String,
+    pub ai_policy: Json<AiPolicy>,
+    /// Monthly spend on AI calls after which AI generation is blocked.
+    pub ai_monthly_budget: Option<f64>,
+    pub lead_scoring: Json<LeadScoring>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WorkspaceRequest {
+    pub name: String,
+    pub physical_address: Option<String>,
+    pub footer: Option<String>,
+    #[serde(default)]
+    pub strict_consent: bool,
+    #[serde(default)]
+    pub ai_policy: AiPolicy,
+    pub ai_monthly_budget: Option<f64>,
+    #[serde(default)]
+    pub lead_scoring: LeadScoring,
+}
+
+/// What generated AI replies are checked against before they are sent.
+/// Replies breaking any rule are held for human review.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AiPolicy {
+    /// Phrases the reply must not contain, matched case-insensitively.
+    pub banned_phrases: Vec<String>,
+    /// Regular expressions the reply must not match.
+    pub banned_patterns: Vec<String>,
+    /// Longest reply, in characters.
+    pub max_length: Option<usize>,
+    /// Text the reply must contain, e.g. the rep's sign-off.
+    pub required_signature: Option<String>,
+    /// Holds replies that contain email addresses, phone numbers, card or
+    /// social security numbers other than the lead's own.
+    pub block_pii: bool,
+    /// Lowest confidence, between 0 and 1, the model may report.
+    pub min_confidence: Option<f64>,
+}
+
+impl Default for AiPolicy {
+    fn default() -> Self {
+        AiPolicy {
+            banned_phrases: Vec::new(),
+            banned_patterns: Vec::new(),
+            max_length: None,
+            required_signature: None,
+            block_pii: true,
+            min_confidence: None,
+        }
+    }
+}
+
+/// How the scores of a workspace's leads are computed. Event and intent
+/// points decay with age; the total is clamped between 0 and 100.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LeadScoring {
+    /// Points per `outreach_log` step of the lead's messages, e.g. `opened`,
+    /// `clicked` or `replied`, counted for each time it happened.
+    pub events: BTreeMap<String, f64>,
+    /// Points per reply intent of the lead's messages.
+    pub intents: BTreeMap<String, f64>,
+    pub profile: Vec<ProfileRule>,
+    /// Days after which event and intent points count half. No decay when
+    /// unset.
+    pub half_life_days: Option<f64>,
+}
+
+impl Default for LeadScoring {
+    fn default() -> Self {
+        let points = |entries: &[(&str, f64)]| {
+            entries
+                .iter()
+                .map(|(key, points)| (key.to_string(), *points))
+                .collect()
+        };
+
+        LeadScoring {
+            events: points(&[
+                ("opened", 2.0),
+                ("clicked", 5.0),
+                ("replied", 10.0),
+                ("bounced", -10.0),
+                ("complained", -30.0),
+            ]),
+            intents: points(&[
+                ("interested", 30.0),
+                ("question", 15.0),
+                ("wrong_person", -10.0),
+                ("not_interested", -20.0),
+                ("unsubscribe", -40.0),
+            ]),
+            profile: Vec::new(),
+            half_life_days: Some(30.0),
+        }
+    }
+}
+
+/// Points for leads whose `field` matches every condition set. A rule
+/// without conditions matches leads that have the field.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileRule {
+    /// `name`, `email`, `phone`, `email_domain` or a custom field key.
+    pub field: String,
+    /// Values the field must equal, case-insensitively.
+    #[serde(default)]
+    pub any_of: Vec<String>,
+    /// Text the field must contain, case-insensitively.
+    pub contains: Option<String>,
+    /// Lowest numeric value of the field.
+    pub min: Option<f64>,
+    /// Highest numeric value of the field.
+    pub max: Option<f64>,
+    pub points: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SenderPool {
+    pub id: i64,
+    pub name: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSenderPoolRequest {
+    pub name: String,
+    pub sender_ids: Vec<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SenderPoolWithMembers {
+    pub pool: SenderPool,
+    pub senders: Vec<SenderIdentity>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SenderUsage {
+    pub sender_id: i64,
+    pub sent_today: i64,
+    pub sent_last_hour: i64,
+    pub daily_cap: Option<i64>,
+    pub hourly_cap: Option<i64>,
+    pub min_spacing_seconds: i64,
+    pub last_sent_at: Option<String>,
+    pub next_available_at: Option<String>,
+    pub pending: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InboundResult {
+    pub message: Message,
+    pub reply: Option<String>,
+    /// The reply came from an auto-responder and did not count as a reply.
+    pub auto_reply: bool,
+    pub bounce_type: Option<BounceType>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Attachment {
+    pub id: i64,
+    pub sha256: String,
+    pub filename: String,
+    pub content_type: String,
+    pub size: i64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadAttachmentQuery {
+    pub filename: String,
+}
+
+/// An attachment together with the message it is sent with.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct MessageAttachment {
+    pub message_id: i64,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub attachment: Attachment,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ChatChannel {
+    pub id: i64,
+    pub name: String,
+    pub webhook_url: String,
+    #[serde(skip_serializing)]
+    pub secret: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateChatChannelRequest {
+    pub name: String,
+    pub webhook_url: String,
+    /// Signs outbound webhooks and authenticates inbound ones.
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct LeadContact {
+    pub id: i64,
+    pub lead_id: i64,
+    pub chat_channel_id: i64,
+    pub handle: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateContactRequest {
+    pub chat_channel_id: i64,
+    pub handle: String,
+}
+
+/// Inbound chat webhook payload.
+#[derive(Debug, Deserialize)]
+pub struct InboundChatRequest {
+    /// The lead's handle on the chat channel.
+    pub from: String,
+    pub text: String,
+    pub external_id: Option<String>,
+}
+
+/// Inbound SMS webhook payload, in the form-encoded shape Twilio posts.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct InboundSmsRequest {
+    pub from: String,
+    pub to: Option<String>,
+    pub body: String,
+    pub message_sid: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmsKeyword {
+    Stop,
+    Help,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InboundSmsResult {
+    pub message: Option<Message>,
+    pub keyword: Option<SmsKeyword>,
+    pub reply: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ComplaintRequest {
+    pub email: String,
+    pub email_message_id: Option<String>,
+    pub feedback_type: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct MboxImportSummary {
+    pub processed: usize,
+    pub matched: usize,
+    pub unmatched: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuppressionKind {
+    Email,
+    Phone,
+    Domain,
+}
+
+impl SuppressionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionKind::Email => "email",
+            SuppressionKind::Phone => "phone",
+            SuppressionKind::Domain => "domain",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Suppression {
+    pub id: i64,
+    pub kind: String,
+    pub value: String,
+    pub reason: Option<String>,
+    pub source: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSuppressionRequest {
+    pub kind: SuppressionKind,
+    pub value: String,
+    pub reason: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct ApiError {
     pub error: String,
 }
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeadSort {
+    #[default]
+    Id,
+    /// Highest score first.
+    Score,
+}
+
+/// Filters of `GET /leads`.
+#[derive(Debug, Default, Deserialize)]
+pub struct LeadQuery {
+    pub workspace_id: Option<i64>,
+    pub min_score: Option<i64>,
+    pub max_score: Option<i64>,
+    /// Custom field values the leads must have, as `key:value` pairs
+    /// separated by commas, e.g. `industry:saas,company:Acme`.
+    pub fields: Option<String>,
+    /// A filter expression, e.g. `tag:webinar AND status:follow_up AND score>50`.
+    pub filter: Option<String>,
+    /// Only the leads of this saved segment.
+    pub segment_id: Option<i64>,
+    #[serde(default)]
+    pub sort: LeadSort,
+}
+
+/// A lead with an overview of its messages.
+#[derive(Debug, Serialize, FromRow)]
+pub struct LeadSummary {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub lead: Lead,
+    pub message_count: i64,
+    /// Status of the lead's latest message.
+    pub latest_status: Option<String>,
+    pub tags: Json<Vec<String>>,
+}
+
+/// Tags to put on a lead, or on every lead of a segment.
+#[derive(Debug, Deserialize)]
+pub struct TagsRequest {
+    pub tags: Vec<String>,
+}
+
+/// Outcome of tagging a segment's leads.
+#[derive(Debug, Serialize)]
+pub struct TagLeadsResult {
+    /// Leads the segment matched.
+    pub leads: usize,
+    /// Tags added, leaving out those the leads already had.
+    pub tagged: u64,
+}
+
+/// A saved lead filter. Its leads are found again each time it is used, so
+/// they follow tag, score and status changes.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Segment {
+    pub id: i64,
+    pub workspace_id: i64,
+    pub name: String,
+    /// Filter expression, e.g. `tag:webinar AND status:follow_up AND score>50`.
+    pub filter: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SegmentRequest {
+    pub workspace_id: Option<i64>,
+    pub name: String,
+    pub filter: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SegmentQuery {
+    pub workspace_id: Option<i64>,
+}
+
+/// Filters of `GET /analytics/funnel`. The date range applies to when
+/// messages were enqueued; `from` is inclusive and `to` exclusive.
+#[derive(Debug, Default, Deserialize)]
+pub struct FunnelQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub campaign_id: Option<i64>,
+    /// Owner of the messages, i.e. the sender identity they go out from.
+    /// Messages have no other owner, so `owner` is accepted too.
+    #[serde(alias = "owner")]
+    pub sender_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FunnelStage {
+    pub step: String,
+    /// Messages that reached the step.
+    pub count: i64,
+    /// Stage the conversion rate is measured from.
+    pub from_step: Option<String>,
+    pub conversion_rate: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FunnelReport {
+    pub stages: Vec<FunnelStage>,
+    /// Median time between a message being sent and the lead replying.
+    pub median_time_to_reply_seconds: Option<f64>,
+}
+
+/// What a generated outreach message is about.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DraftBrief {
+    pub product: String,
+    pub goal: String,
+    /// e.g. "friendly", "formal"; defaults to a concise, friendly tone.
+    pub tone: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AiDraftRequest {
+    pub lead_id: i64,
+    pub brief: DraftBrief,
+    pub channel: Option<Channel>,
+    /// Enqueues the draft the same way `/send` does.
+    #[serde(default)]
+    pub enqueue: bool,
+    pub sender_id: Option<i64>,
+    pub sender_pool_id: Option<i64>,
+    pub follow_up: Option<String>,
+    #[serde(default)]
+    pub track: bool,
+}
+
+/// A generated draft, with the prompt and model that produced it.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AiDraft {
+    pub id: i64,
+    pub lead_id: i64,
+    pub channel: String,
+    pub subject: Option<String>,
+    pub body: String,
+    pub system_prompt: String,
+    pub prompt: String,
+    pub model: String,
+    /// The message the draft was enqueued as.
+    pub message_id: Option<i64>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AiDraftResult {
+    pub draft: AiDraft,
+    pub message: Option<Message>,
+}
+
+/// A version of the prompt AI replies are generated with. A campaign's
+/// latest version wins over its workspace's.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PromptTemplate {
+    pub id: i64,
+    pub workspace_id: i64,
+    pub campaign_id: Option<i64>,
+    /// Counts up from 1 per workspace or campaign.
+    pub version: i64,
+    pub system_prompt: String,
+    /// Who the AI writes as, e.g. "Jane, account executive at Acme".
+    pub persona: Option<String>,
+    pub tone: Option<String>,
+    /// Topics the reply must not bring up, e.g. pricing or discounts.
+    pub forbidden_topics: Json<Vec<String>>,
+    /// Longest reply, in characters.
+    pub max_length: Option<i64>,
+    pub created_at: String,
+}
+
+/// Creates the next version of a workspace's or, with `campaign_id`, a
+/// campaign's prompt template.
+#[derive(Debug, Deserialize)]
+pub struct PromptTemplateRequest {
+    pub workspace_id: Option<i64>,
+    pub campaign_id: Option<i64>,
+    pub system_prompt: String,
+    pub persona: Option<String>,
+    pub tone: Option<String>,
+    #[serde(default)]
+    pub forbidden_topics: Vec<String>,
+    pub max_length: Option<i64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PromptTemplateQuery {
+    pub workspace_id: Option<i64>,
+    pub campaign_id: Option<i64>,
+}
+
+/// An AI reply held for review, with the policy rules it broke.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AiReview {
+    pub id: i64,
+    pub message_id: i64,
+    pub ai_reply: String,
+    pub violations: Json<Vec<String>>,
+    pub confidence: Option<f64>,
+    /// `pending`, `approved` or `rejected`.
+    pub status: String,
+    pub created_at: String,
+    pub reviewed_at: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AiReviewQuery {
+    /// Defaults to `pending`.
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ApproveReviewRequest {
+    /// Edited reply to send instead of the generated one.
+    pub ai_reply: Option<String>,
+}
+
+/// Filters of `GET /ai/usage`.
+#[derive(Debug, Default, Deserialize)]
+pub struct AiUsageQuery {
+    pub workspace_id: Option<i64>,
+    /// `YYYY-MM`, the current month by default.
+    pub month: Option<String>,
+}
+
+/// AI calls of one model for one purpose.
+#[derive(Debug, Serialize, FromRow)]
+pub struct AiUsageLine {
+    pub model: String,
+    /// `reply`, `draft` or `classify`.
+    pub purpose: String,
+    pub calls: i64,
+    pub failed_calls: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cost: f64,
+    pub avg_latency_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AiUsageReport {
+    pub workspace_id: i64,
+    pub month: String,
+    pub budget: Option<f64>,
+    pub spent: f64,
+    pub remaining: Option<f64>,
+    pub usage: Vec<AiUsageLine>,
+}
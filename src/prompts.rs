@@ -0,0 +1,142 @@
+use chrono::Utc;
+use sqlx::SqlitePool;
+use sqlx::types::Json;
+
+use crate::models::{Lead, Message, PromptTemplate, PromptTemplateQuery};
+
+const TEMPLATE_COLUMNS: &str = "id, workspace_id, campaign_id, version, system_prompt, persona, tone, forbidden_topics, max_length, created_at";
+
+/// Used when neither the campaign nor the workspace has a template.
+const DEFAULT_SYSTEM_PROMPT: &str = "You are a sales representative answering a lead who \
+replied to your outreach. Answer their reply helpfully and briefly, and suggest a next step.";
+
+/// The fields of a new template version.
+pub struct NewTemplate<'a> {
+    pub workspace_id: i64,
+    pub campaign_id: Option<i64>,
+    pub system_prompt: &'a str,
+    pub persona: Option<&'a str>,
+    pub tone: Option<&'a str>,
+    pub forbidden_topics: &'a [String],
+    pub max_length: Option<i64>,
+}
+
+/// Stores `template` as the next version of its workspace's or campaign's
+/// template.
+pub async fn create(
+    pool: &SqlitePool,
+    template: &NewTemplate<'_>,
+) -> Result<PromptTemplate, sqlx::Error> {
+    sqlx::query_as::<_, PromptTemplate>(&format!(
+        r#"
+        INSERT INTO prompt_templates (workspace_id, campaign_id, version, system_prompt, persona, tone, forbidden_topics, max_length, created_at)
+        SELECT ?1, ?2, COALESCE(MAX(version), 0) + 1, ?3, ?4, ?5, ?6, ?7, ?8
+        FROM prompt_templates
+        WHERE workspace_id = ?1 AND campaign_id IS ?2
+        RETURNING {}
+        "#,
+        TEMPLATE_COLUMNS
+    ))
+    .bind(template.workspace_id)
+    .bind(template.campaign_id)
+    .bind(template.system_prompt)
+    .bind(template.persona)
+    .bind(template.tone)
+    .bind(Json(template.forbidden_topics))
+    .bind(template.max_length)
+    .bind(Utc::now().to_rfc3339())
+    .fetch_one(pool)
+    .await
+}
+
+/// Template versions matching the filters, newest first.
+pub async fn list(
+    pool: &SqlitePool,
+    filter: &PromptTemplateQuery,
+) -> Result<Vec<PromptTemplate>, sqlx::Error> {
+    sqlx::query_as::<_, PromptTemplate>(&format!(
+        r#"
+        SELECT {} FROM prompt_templates
+        WHERE (?1 IS NULL OR workspace_id = ?1)
+          AND (?2 IS NULL OR campaign_id = ?2)
+        ORDER BY id DESC
+        "#,
+        TEMPLATE_COLUMNS
+    ))
+    .bind(filter.workspace_id)
+    .bind(filter.campaign_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// The template an AI reply to `message` is generated with: the latest
+/// version of its campaign's, else of its lead's workspace's.
+pub async fn select(
+    pool: &SqlitePool,
+    message: &Message,
+    lead: &Lead,
+) -> Result<Option<PromptTemplate>, sqlx::Error> {
+    if let Some(campaign_id) = message.campaign_id {
+        let template = sqlx::query_as::<_, PromptTemplate>(&format!(
+            "SELECT {} FROM prompt_templates WHERE campaign_id = ? ORDER BY version DESC LIMIT 1",
+            TEMPLATE_COLUMNS
+        ))
+        .bind(campaign_id)
+        .fetch_optional(pool)
+        .await?;
+
+        if template.is_some() {
+            return Ok(template);
+        }
+    }
+
+    sqlx::query_as::<_, PromptTemplate>(&format!(
+        r#"
+        SELECT {} FROM prompt_templates
+        WHERE workspace_id = ? AND campaign_id IS NULL
+        ORDER BY version DESC LIMIT 1
+        "#,
+        TEMPLATE_COLUMNS
+    ))
+    .bind(lead.workspace_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// The system prompt for an AI reply, built from the template's fields.
+pub fn system_prompt(template: Option<&PromptTemplate>) -> String {
+    let Some(template) = template else {
+        return DEFAULT_SYSTEM_PROMPT.to_string();
+    };
+
+    let mut prompt = template.system_prompt.trim().to_string();
+    if let Some(persona) = template.persona.as_deref() {
+        prompt.push_str(&format!("\nYou write as {}.", persona.trim()));
+    }
+    if let Some(tone) = template.tone.as_deref() {
+        prompt.push_str(&format!("\nTone: {}.", tone.trim()));
+    }
+    if !template.forbidden_topics.is_empty() {
+        prompt.push_str(&format!(
+            "\nNever mention or discuss: {}.",
+            template.forbidden_topics.join(", ")
+        ));
+    }
+    if let Some(max_length) = template.max_length {
+        prompt.push_str(&format!(
+            "\nKeep the reply under {} characters.",
+            max_length
+        ));
+    }
+    prompt
+}
+
+/// The conversation so far, as the prompt the reply is written from.
+pub fn reply_prompt(message: &Message, lead: &Lead) -> String {
+    format!(
+        "Lead name: {}\n\nOur message:\n{}\n\nTheir reply:\n{}\n",
+        lead.name,
+        message.message_sent.as_deref().unwrap_or_default().trim(),
+        message.reply_received.as_deref().unwrap_or_default().trim()
+    )
+}
@@ -0,0 +1,66 @@
+use chrono::Utc;
+use sqlx::{SqliteConnection, SqlitePool};
+use sqlx::types::Json;
+
+use crate::models::AiReview;
+
+const REVIEW_COLUMNS: &str =
+    "id, message_id, ai_reply, violations, confidence, status, created_at, reviewed_at";
+
+pub const PENDING: &str = "pending";
+pub const APPROVED: &str = "approved";
+pub const REJECTED: &str = "rejected";
+
+pub async fn create(
+    pool: &SqlitePool,
+    message_id: i64,
+    ai_reply: &str,
+    violations: &[String],
+    confidence: Option<f64>,
+) -> Result<AiReview, sqlx::Error> {
+    sqlx::query_as::<_, AiReview>(&format!(
+        r#"
+        INSERT INTO ai_reviews (message_id, ai_reply, violations, confidence, status, created_at)
+        VALUES (?, ?, ?, ?, ?, ?)
+        RETURNING {}
+        "#,
+        REVIEW_COLUMNS
+    ))
+    .bind(message_id)
+    .bind(ai_reply)
+    .bind(Json(violations))
+    .bind(confidence)
+    .bind(PENDING)
+    .bind(Utc::now().to_rfc3339())
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn list(pool: &SqlitePool, status: &str) -> Result<Vec<AiReview>, sqlx::Error> {
+    sqlx::query_as::<_, AiReview>(&format!(
+        "SELECT {} FROM ai_reviews WHERE status = ? ORDER BY id ASC",
+        REVIEW_COLUMNS
+    ))
+    .bind(status)
+    .fetch_all(pool)
+    .await
+}
+
+/// Moves a pending review to `status`. `None` when the review does not
+/// exist or was already decided.
+pub async fn decide(
+    conn: &mut SqliteConnection,
+    review_id: i64,
+    status: &str,
+) -> Result<Option<AiReview>, sqlx::Error> {
+    sqlx::query_as::<_, AiReview>(&format!(
+        "UPDATE ai_reviews SET status = ?, reviewed_at = ? WHERE id = ? AND status = ? RETURNING {}",
+        REVIEW_COLUMNS
+    ))
+    .bind(status)
+    .bind(Utc::now().to_rfc3339())
+    .bind(review_id)
+    .bind(PENDING)
+    .fetch_optional(conn)
+    .await
+}
@@ -0,0 +1,271 @@
+use pulldown_cmark::{Options, Parser, html};
+
+use crate::models::BodyFormat;
+
+/// A message body in both representations. `html` is `None` for plain text.
+#[derive(Debug, Clone)]
+pub struct RichBody {
+    pub text: String,
+    pub html: Option<String>,
+}
+
+/// Renders a submitted body: Markdown is converted to HTML, HTML is
+/// sanitized, and a plain-text version is generated from the result.
+pub fn render(body: &str, format: BodyFormat) -> RichBody {
+    let html = match format {
+        BodyFormat::Text => {
+            return RichBody {
+                text: body.to_string(),
+                html: None,
+            };
+        }
+        BodyFormat::Markdown => sanitize(&markdown_to_html(body)),
+        BodyFormat::Html => sanitize(body),
+    };
+
+    RichBody {
+        text: html_to_text(&html),
+        html: Some(html),
+    }
+}
+
+/// Strips scripts, styles, event handlers and anything else that is not
+/// safe to put in front of a lead.
+pub fn sanitize(html: &str) -> String {
+    ammonia::clean(html).trim().to_string()
+}
+
+pub fn markdown_to_html(markdown: &str) -> String {
+    let parser = Parser::new_ext(
+        markdown,
+        Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES,
+    );
+    let mut out = String::new();
+    html::push_html(&mut out, parser);
+    out
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Escapes plain text and keeps its line breaks.
+pub fn text_to_html(text: &str) -> String {
+    escape(text).replace("\r\n", "\n").replace('\n', "<br>\n")
+}
+
+/// Generates the plain-text alternative of sanitized HTML: block elements
+/// become line breaks, list items get a bullet and links keep their URL.
+pub fn html_to_text(html: &str) -> String {
+    let mut out = String::new();
+    let mut links: Vec<(Option<String>, usize)> = Vec::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        push_text(&mut out, &rest[..start]);
+
+        let Some(end) = rest[start..].find('>') else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        match (name.as_str(), closing) {
+            ("br", _) => out.push('\n'),
+            (
+                "p" | "ul" | "ol" | "blockquote" | "pre" | "table" | "h1" | "h2" | "h3" | "h4"
+                | "h5" | "h6" | "hr",
+                _,
+            ) => blank_line(&mut out),
+            ("div" | "tr", _) => new_line(&mut out),
+            ("td" | "th", true) => out.push(' '),
+            ("li", false) => {
+                new_line(&mut out);
+                out.push_str("- ");
+            }
+            ("a", false) => links.push((attribute(tag, "href"), out.len())),
+            ("a", true) => {
+                if let Some((Some(href), at)) = links.pop()
+                    && !href.starts_with('#')
+                    && out[at..].trim() != href
+                {
+                    out.push_str(&format!(" ({})", href));
+                }
+            }
+            ("img", _) => {
+                if let Some(alt) = attribute(tag, "alt") {
+                    push_text(&mut out, &alt);
+                }
+            }
+            _ => {}
+        }
+    }
+    push_text(&mut out, rest);
+
+    let mut text = String::new();
+    let mut blank = 0;
+    for line in out.lines().map(str::trim_end) {
+        if line.trim().is_empty() {
+            blank += 1;
+            continue;
+        }
+        if !text.is_empty() {
+            text.push_str(if blank > 0 { "\n\n" } else { "\n" });
+        }
+        text.push_str(line.trim_start());
+        blank = 0;
+    }
+    text
+}
+
+fn push_text(out: &mut String, raw: &str) {
+    let decoded = decode_entities(raw);
+    let words: Vec<&str> = decoded.split_whitespace().collect();
+
+    if decoded.starts_with(char::is_whitespace) && needs_space(out) {
+        out.push(' ');
+    }
+    if words.is_empty() {
+        return;
+    }
+    out.push_str(&words.join(" "));
+    if decoded.ends_with(char::is_whitespace) {
+        out.push(' ');
+    }
+}
+
+fn needs_space(out: &str) -> bool {
+    !(out.is_empty() || out.ends_with([' ', '\n']))
+}
+
+fn new_line(out: &mut String) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+fn blank_line(out: &mut String) {
+    new_line(out);
+    if !out.is_empty() && !out.ends_with("\n\n") {
+        out.push('\n');
+    }
+}
+
+/// Reads a quoted attribute value, as written by the sanitizer.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let pattern = format!("{}=\"", name);
+    let start = tag.find(&pattern)? + pattern.len();
+    let end = tag[start..].find('"')?;
+    Some(decode_entities(&tag[start..start + end]))
+}
+
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest.find(';').filter(|end| *end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_html_to_text() {
+        let html = "<p>Hi <b>Ada</b>,</p>\
+            <p>See <a href=\"https://example.com/a?b=1&amp;c=2\">our deck</a> &amp; \
+            <a href=\"https://example.com\">https://example.com</a>.</p>\
+            <ul><li>One</li><li>Two</li></ul>\
+            <p>Line<br>break &lt;3 &#8212; &#x2713;</p>";
+
+        assert_eq!(
+            html_to_text(html),
+            "Hi Ada,\n\n\
+             See our deck (https://example.com/a?b=1&c=2) & https://example.com.\n\n\
+             - One\n- Two\n\n\
+             Line\nbreak <3 \u{2014} \u{2713}"
+        );
+    }
+
+    #[test]
+    fn keeps_table_cells_and_image_text_apart() {
+        let html = "<table><tr><td>Plan</td><td>Price</td></tr><tr><td>Pro</td><td>$10</td></tr></table>\
+            <div><img src=\"logo.png\" alt=\"Acme\"><a href=\"#top\">Top</a></div>";
+
+        assert_eq!(html_to_text(html), "Plan Price\nPro $10\n\nAcmeTop");
+    }
+
+    #[test]
+    fn renders_markdown_and_sanitizes_html() {
+        let body = render("**Hi** [deck](https://example.com)", BodyFormat::Markdown);
+        assert_eq!(
+            body.html.as_deref(),
+            Some(
+                "<p><strong>Hi</strong> <a href=\"https://example.com\" rel=\"noopener noreferrer\">deck</a></p>"
+            )
+        );
+        assert_eq!(body.text, "Hi deck (https://example.com)");
+
+        let body = render(
+            "<p onclick=\"steal()\">Hi</p><script>alert(1)</script>",
+            BodyFormat::Html,
+        );
+        assert_eq!(body.html.as_deref(), Some("<p>Hi</p>"));
+        assert_eq!(body.text, "Hi");
+
+        let body = render("Hi <b>", BodyFormat::Text);
+        assert_eq!((body.text.as_str(), body.html), ("Hi <b>", None));
+    }
+}
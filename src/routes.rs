@@ -1,17 +1,416 @@
 use axum::{
-    routing::{get, post},
+    extract::DefaultBodyLimit,
+    routing::{delete, get, post, put},
     Router,
 };
-use sqlx::SqlitePool;
 
-use crate::handlers::{ai_reply, create_lead, get_lead, reply_to_message, send_message};
+use crate::config::AppState;
+use crate::handlers::{
+    add_campaign_leads, add_lead_tags, ai_draft, ai_reply, approve_ai_review, archive_campaign,
+    create_campaign, create_chat_channel, create_custom_field, create_experiment, create_lead,
+    create_lead_contact, create_prompt_template, create_segment, create_sender, create_sender_pool,
+    create_suppression, create_workspace, delete_segment, delete_suppression, get_ai_usage,
+    get_campaign, get_experiment, get_funnel, get_lead, get_sender_usage, import_mbox,
+    list_ai_reviews, list_campaigns, list_chat_channels, list_custom_fields, list_leads,
+    list_prompt_templates, list_segments, list_senders, list_suppressions, list_workspaces,
+    pause_campaign, promote_experiment_variant, receive_complaint, receive_inbound_chat,
+    receive_inbound_email, receive_inbound_sms, reject_ai_review, remove_lead_tag, reply_to_message,
+    resume_campaign, send_message, start_campaign, tag_segment_leads, track_click, track_open,
+    unsubscribe, unsubscribe_page, update_lead_consent, update_lead_fields, update_workspace,
+    upload_attachment,
+};
+
+pub fn create_router(state: AppState) -> Router {
+    let max_attachment_bytes = state.config.max_attachment_bytes;
 
-pub fn create_router(pool: SqlitePool) -> Router {
     Router::new()
         .route("/lead", post(create_lead))
         .route("/lead/{id}", get(get_lead))
+        .route("/leads", get(list_leads))
+        .route("/custom-fields", post(create_custom_field).get(list_custom_fields))
+        .route("/segments", post(create_segment).get(list_segments))
+        .route("/segments/{id}", delete(delete_segment))
+        .route("/segments/{id}/tags", post(tag_segment_leads))
+        .route("/lead/{id}/consent", put(update_lead_consent))
+        .route("/lead/{id}/fields", put(update_lead_fields))
+        .route("/lead/{id}/tags", post(add_lead_tags))
+        .route("/lead/{id}/tags/{tag}", delete(remove_lead_tag))
+        .route("/lead/{id}/contacts", post(create_lead_contact))
         .route("/send", post(send_message))
         .route("/reply", post(reply_to_message))
+        .route("/inbound/email", post(receive_inbound_email))
+        .route("/inbound/sms", post(receive_inbound_sms))
+        .route("/inbound/chat/{channel}", post(receive_inbound_chat))
+        .route("/inbound/mbox", post(import_mbox))
+        .route("/webhooks/complaint", post(receive_complaint))
         .route("/ai/reply", post(ai_reply))
-        .with_state(pool)
+        .route("/ai/draft", post(ai_draft))
+        .route("/ai/reviews", get(list_ai_reviews))
+        .route("/ai/usage", get(get_ai_usage))
+        .route("/ai/reviews/{id}/approve", post(approve_ai_review))
+        .route("/ai/reviews/{id}/reject", post(reject_ai_review))
+        .route(
+            "/prompt-templates",
+            post(create_prompt_template).get(list_prompt_templates),
+        )
+        .route(
+            "/attachments",
+            post(upload_attachment).layer(DefaultBodyLimit::max(max_attachment_bytes + 1)),
+        )
+        .route("/analytics/funnel", get(get_funnel))
+        .route("/campaigns", post(create_campaign).get(list_campaigns))
+        .route("/campaigns/{id}", get(get_campaign))
+        .route("/campaigns/{id}/leads", post(add_campaign_leads))
+        .route("/campaigns/{id}/start", post(start_campaign))
+        .route("/campaigns/{id}/pause", post(pause_campaign))
+        .route("/campaigns/{id}/resume", post(resume_campaign))
+        .route("/campaigns/{id}/archive", post(archive_campaign))
+        .route("/experiments", post(create_experiment))
+        .route("/experiments/{id}", get(get_experiment))
+        .route("/experiments/{id}/winner", put(promote_experiment_variant))
+        .route("/chat-channels", post(create_chat_channel).get(list_chat_channels))
+        .route("/workspaces", post(create_workspace).get(list_workspaces))
+        .route("/workspaces/{id}", put(update_workspace))
+        .route("/senders", post(create_sender).get(list_senders))
+        .route("/senders/{id}/usage", get(get_sender_usage))
+        .route("/sender-pools", post(create_sender_pool))
+        .route("/suppressions", post(create_suppression).get(list_suppressions))
+        .route("/suppressions/{id}", delete(delete_suppression))
+        .route("/unsubscribe/{token}", get(unsubscribe_page).post(unsubscribe))
+        .route("/t/open/{token}", get(track_open))
+        .route("/t/click/{token}", get(track_click))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use axum::body::{Body, to_bytes};
+    use axum::http::{Request, StatusCode};
+    use serde_json::{Value, json};
+    use sqlx::SqlitePool;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::ai::{self, MockFixtures};
+    use crate::config::{AiSettings, Config, SmsSettings};
+
+    /// The app on an in-memory database, answering AI calls from the mock
+    /// provider's fixtures.
+    async fn app() -> Router {
+        app_with_pool().await.0
+    }
+
+    /// The app along with its pool, for tests that change rows behind it.
+    async fn app_with_pool() -> (Router, SqlitePool) {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+
+        let fixtures = MockFixtures::load(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("mock_ai_fixtures.json"),
+        )
+        .unwrap();
+        let ai = AiSettings::Mock {
+            model: "mock".to_string(),
+            fixtures,
+            latency_ms: 0,
+            fail_every: None,
+        };
+        let config = Config {
+            public_url: "http://localhost:3010".to_string(),
+            signing_secret: "secret".to_string(),
+            inbound_maildir: None,
+            attachments_dir: PathBuf::from("attachments"),
+            max_attachment_bytes: 1024,
+            sms: SmsSettings::Log,
+            sms_from: None,
+            ai_provider: ai::provider(&ai),
+            ai,
+            ai_prices: Default::default(),
+        };
+
+        let app = create_router(AppState {
+            pool: pool.clone(),
+            config: Arc::new(config),
+        });
+        (app, pool)
+    }
+
+    async fn call(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    /// Sends a message to a new lead and records the lead's reply to it.
+    async fn replied_message(app: &Router, reply: &str) -> Value {
+        let (status, lead) = call(
+            app,
+            "POST",
+            "/lead",
+            Some(json!({"name": "Ada", "email": "ada@example.org"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, message) = call(
+            app,
+            "POST",
+            "/send",
+            Some(json!({"lead_id": lead["id"], "message": "Hi Ada, want to see a demo?"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, message) = call(
+            app,
+            "POST",
+            "/reply",
+            Some(json!({"message_id": message["id"], "reply": reply})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        message
+    }
+
+    #[tokio::test]
+    async fn ai_reply_enqueues_the_fixture_answer() {
+        let app = app().await;
+        let message = replied_message(&app, "Could you share your pricing for 20 seats?").await;
+        assert_eq!(message["reply_intent"], "question");
+
+        let (status, message) = call(
+            &app,
+            "POST",
+            "/ai/reply",
+            Some(json!({"message_id": message["id"]})),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(message["status"], "ai_enqueued");
+        assert_eq!(
+            message["ai_reply"],
+            "Our plans start with a free trial, and pricing depends on your team size. \
+             Could we set up a short call to find the right fit?\n\nJane Sales"
+        );
+    }
+
+    #[tokio::test]
+    async fn ai_reply_breaking_the_policy_is_held_for_review() {
+        let app = app().await;
+        let (status, _) = call(
+            &app,
+            "PUT",
+            "/workspaces/1",
+            Some(json!({
+                "name": "Default",
+                "ai_policy": {"banned_phrases": ["free trial"], "min_confidence": 0.95}
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let message = replied_message(&app, "What does it cost?").await;
+        let (status, message) = call(
+            &app,
+            "POST",
+            "/ai/reply",
+            Some(json!({"message_id": message["id"]})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(message["status"], "ai_review");
+
+        let (status, reviews) = call(&app, "GET", "/ai/reviews", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(reviews[0]["message_id"], message["id"]);
+        assert_eq!(reviews[0]["confidence"], 0.9);
+        assert_eq!(
+            reviews[0]["violations"],
+            json!([
+                "contains banned phrase \"free trial\"",
+                "has confidence 0.90, below the 0.95 threshold"
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn ai_review_stays_pending_when_its_message_moved_on() {
+        let (app, pool) = app_with_pool().await;
+        call(
+            &app,
+            "PUT",
+            "/workspaces/1",
+            Some(json!({"name": "Default", "ai_policy": {"banned_phrases": ["free trial"]}})),
+        )
+        .await;
+        let message = replied_message(&app, "What does it cost?").await;
+        call(
+            &app,
+            "POST",
+            "/ai/reply",
+            Some(json!({"message_id": message["id"]})),
+        )
+        .await;
+        let (_, reviews) = call(&app, "GET", "/ai/reviews", None).await;
+        let review_id = reviews[0]["id"].as_i64().unwrap();
+
+        sqlx::query("UPDATE messages SET status = 'closed' WHERE id = ?")
+            .bind(message["id"].as_i64())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let uri = format!("/ai/reviews/{}/approve", review_id);
+        let (status, _) = call(&app, "POST", &uri, Some(json!({}))).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (_, reviews) = call(&app, "GET", "/ai/reviews", None).await;
+        assert_eq!(reviews[0]["id"], review_id);
+        assert_eq!(reviews[0]["reviewed_at"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn ai_calls_stop_at_the_monthly_budget() {
+        let (app, pool) = app_with_pool().await;
+        call(
+            &app,
+            "PUT",
+            "/workspaces/1",
+            Some(json!({"name": "Default", "ai_monthly_budget": 1.0})),
+        )
+        .await;
+
+        // The fixtures fail replies to "timeout"; the failed call is recorded.
+        let message = replied_message(&app, "Sorry, timeout on my side").await;
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/ai/reply",
+            Some(json!({"message_id": message["id"]})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+
+        let (_, report) = call(&app, "GET", "/ai/usage?workspace_id=1", None).await;
+        let replies = |report: &Value| {
+            report["usage"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|line| line["purpose"] == "reply")
+                .cloned()
+                .unwrap()
+        };
+        assert_eq!(replies(&report)["calls"], 1);
+        assert_eq!(replies(&report)["failed_calls"], 1);
+
+        sqlx::query(
+            "INSERT INTO ai_usage (workspace_id, purpose, model, prompt_tokens, completion_tokens, latency_ms, cost, created_at) VALUES (1, 'draft', 'mock', 0, 0, 0, 1.5, ?)",
+        )
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let message = replied_message(&app, "What does it cost?").await;
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/ai/reply",
+            Some(json!({"message_id": message["id"]})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+
+        let (_, report) = call(&app, "GET", "/ai/usage?workspace_id=1", None).await;
+        assert_eq!(report["spent"], 1.5);
+        assert_eq!(report["remaining"], 0.0);
+        assert_eq!(replies(&report)["calls"], 1);
+    }
+
+    #[tokio::test]
+    async fn consent_is_stamped_when_it_is_granted() {
+        let app = app().await;
+        let (_, lead) = call(
+            &app,
+            "POST",
+            "/lead",
+            Some(json!({"name": "Ada", "email": "ada@example.org"})),
+        )
+        .await;
+        assert_eq!(lead["consent_at"], Value::Null);
+        let uri = format!("/lead/{}/consent", lead["id"]);
+        let consent = |status: &str| json!({"consent_status": status, "consent_source": "form"});
+
+        let (status, granted) = call(&app, "PUT", &uri, Some(consent("granted"))).await;
+        assert_eq!(status, StatusCode::OK);
+        let granted_at = granted["consent_at"].as_str().unwrap().to_string();
+
+        let (_, regranted) = call(&app, "PUT", &uri, Some(consent("granted"))).await;
+        assert_eq!(regranted["consent_at"], granted_at.as_str());
+
+        let (_, withdrawn) = call(&app, "PUT", &uri, Some(consent("withdrawn"))).await;
+        assert_eq!(withdrawn["consent_status"], "withdrawn");
+        assert_eq!(withdrawn["consent_at"], granted_at.as_str());
+
+        let (_, granted_again) = call(&app, "PUT", &uri, Some(consent("granted"))).await;
+        assert_ne!(granted_again["consent_at"], granted_at.as_str());
+    }
+
+    #[tokio::test]
+    async fn ai_reply_needs_a_reply_to_answer() {
+        let app = app().await;
+        let (_, lead) = call(
+            &app,
+            "POST",
+            "/lead",
+            Some(json!({"name": "Ada", "email": "ada@example.org"})),
+        )
+        .await;
+        let (_, message) = call(
+            &app,
+            "POST",
+            "/send",
+            Some(json!({"lead_id": lead["id"], "message": "Hi Ada"})),
+        )
+        .await;
+
+        let (status, error) = call(
+            &app,
+            "POST",
+            "/ai/reply",
+            Some(json!({"message_id": message["id"]})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error["error"], "Message has no reply to answer");
+
+        let (status, _) = call(&app, "POST", "/ai/reply", Some(json!({"message_id": 999}))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
 }
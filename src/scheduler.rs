@@ -1,37 +1,62 @@
-use chrono::{Duration, Utc};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::error::Error;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
 use sqlx::SqlitePool;
 use tokio_cron_scheduler::{Job, JobScheduler};
 use tracing::{error, info, warn};
 
-use crate::handlers::log_outreach;
-use crate::models::MessageStatus;
-
-pub async fn start_scheduler(pool: SqlitePool) -> Result<JobScheduler, Box<dyn std::error::Error>> {
+use crate::attachments;
+use crate::chat;
+use crate::config::Config;
+use crate::email::{OutgoingEmail, deliver};
+use crate::handlers::{fetch_lead, fetch_message, fetch_sender, fetch_workspace, log_outreach};
+use crate::inbound::poll_maildir;
+use crate::models::{Channel, EmailKind, MessageStatus};
+use crate::scoring;
+use crate::sms;
+use crate::suppression::{lead_suppression, unsubscribe_url};
+use crate::throttle::Throttle;
+
+const DEFAULT_FOLLOW_UP: &str = "Just following up on my previous message. Would you be open to a quick chat?";
+
+pub async fn start_scheduler(
+    pool: SqlitePool,
+    config: Arc<Config>,
+) -> Result<JobScheduler, Box<dyn std::error::Error>> {
     info!("Starting scheduler");
 
     let sched = JobScheduler::new().await?;
 
     let pool_clone = pool.clone();
+    let config_clone = config.clone();
     let process_enqueued_job = Job::new_async("0 * * * * *", move |_uuid, _l| {
         let pool = pool_clone.clone();
+        let config = config_clone.clone();
         Box::pin(async move {
-            process_enqueued_messages(&pool).await;
+            process_enqueued_messages(&pool, &config).await;
         })
     })?;
 
     let pool_clone = pool.clone();
+    let config_clone = config.clone();
     let process_ai_enqueued_job = Job::new_async("0 * * * * *", move |_uuid, _l| {
         let pool = pool_clone.clone();
+        let config = config_clone.clone();
         Box::pin(async move {
-            process_ai_enqueued_messages(&pool).await;
+            process_ai_enqueued_messages(&pool, &config).await;
         })
     })?;
 
     let pool_clone = pool.clone();
+    let config_clone = config.clone();
     let process_follow_up_job = Job::new_async("0 * * * * *", move |_uuid, _l| {
         let pool = pool_clone.clone();
+        let config = config_clone.clone();
         Box::pin(async move {
-            process_follow_up_messages(&pool).await;
+            process_follow_up_messages(&pool, &config).await;
         })
     })?;
 
@@ -43,10 +68,34 @@ pub async fn start_scheduler(pool: SqlitePool) -> Result<JobScheduler, Box<dyn s
         })
     })?;
 
+    let pool_clone = pool.clone();
+    let rescore_leads_job = Job::new_async("0 0 * * * *", move |_uuid, _l| {
+        let pool = pool_clone.clone();
+        Box::pin(async move {
+            scoring::rescore_all(&pool, None).await;
+        })
+    })?;
+
+    if let Some(maildir) = config.inbound_maildir.clone() {
+        let pool_clone = pool.clone();
+        let config_clone = config.clone();
+        info!("Polling inbound maildir at {}", maildir.display());
+        let poll_maildir_job = Job::new_async("30 * * * * *", move |_uuid, _l| {
+            let pool = pool_clone.clone();
+            let config = config_clone.clone();
+            let maildir = maildir.clone();
+            Box::pin(async move {
+                poll_maildir(&pool, &config, &maildir).await;
+            })
+        })?;
+        sched.add(poll_maildir_job).await?;
+    }
+
     sched.add(process_enqueued_job).await?;
     sched.add(process_ai_enqueued_job).await?;
     sched.add(process_follow_up_job).await?;
     sched.add(process_closed_job).await?;
+    sched.add(rescore_leads_job).await?;
 
     sched.start().await?;
 
@@ -55,15 +104,26 @@ pub async fn start_scheduler(pool: SqlitePool) -> Result<JobScheduler, Box<dyn s
     Ok(sched)
 }
 
-async fn process_enqueued_messages(pool: &SqlitePool) {
+async fn process_enqueued_messages(pool: &SqlitePool, config: &Config) {
     info!("Processing enqueued messages");
 
-    let messages: Vec<(i64,)> =
-        sqlx::query_as("SELECT id FROM messages WHERE status = ?")
-            .bind(MessageStatus::Enqueued.as_str())
-            .fetch_all(pool)
-            .await
-            .unwrap_or_default();
+    let now = Utc::now();
+
+    let messages: Vec<(i64,)> = sqlx::query_as(
+        r#"
+        SELECT id FROM messages
+        WHERE status = ?
+          AND (scheduled_at IS NULL OR scheduled_at <= ?)
+          AND (channel != 'email' OR leads_id NOT IN (SELECT id FROM leads WHERE email_undeliverable_at IS NOT NULL))
+          AND (campaign_id IS NULL OR campaign_id IN (SELECT id FROM campaigns WHERE status = 'active'))
+        ORDER BY created_at ASC, id ASC
+        "#,
+    )
+    .bind(MessageStatus::Enqueued.as_str())
+    .bind(now.to_rfc3339())
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
 
     if messages.is_empty() {
         info!("No enqueued messages to process");
@@ -72,13 +132,32 @@ async fn process_enqueued_messages(pool: &SqlitePool) {
 
     info!("Found {} enqueued messages to process", messages.len());
 
-    let now = Utc::now().to_rfc3339();
+    let sent_at = now.to_rfc3339();
     let new_status = MessageStatus::Sent.as_str();
+    let mut throttles: HashMap<i64, Throttle> = HashMap::new();
 
     for (message_id,) in messages {
-        let result = sqlx::query("UPDATE messages SET status = ?, sent_at = ? WHERE id = ?")
+        let email_message_id =
+            match send_outreach(pool, config, &mut throttles, now, message_id, EmailKind::Initial).await {
+                Ok(SendOutcome::Sent(email_message_id)) => email_message_id,
+                Ok(SendOutcome::Throttled { sender_id, slot }) => {
+                    defer_message(pool, message_id, sender_id, slot).await;
+                    continue;
+                }
+                Ok(SendOutcome::Suppressed(reason)) => {
+                    close_suppressed(pool, message_id, &reason).await;
+                    continue;
+                }
+                Err(e) => {
+                    error!("Failed to send message {}: {}", message_id, e);
+                    continue;
+                }
+            };
+
+        let result = sqlx::query("UPDATE messages SET status = ?, sent_at = ?, scheduled_at = NULL, email_message_id = ? WHERE id = ?")
             .bind(new_status)
-            .bind(&now)
+            .bind(&sent_at)
+            .bind(&email_message_id)
             .bind(message_id)
             .execute(pool)
             .await;
@@ -95,15 +174,209 @@ async fn process_enqueued_messages(pool: &SqlitePool) {
     }
 }
 
-async fn process_ai_enqueued_messages(pool: &SqlitePool) {
+enum SendOutcome {
+    /// The message was handed to its channel's transport. Emails carry
+    /// their Message-ID.
+    Sent(Option<String>),
+    /// The sender is at its limit until `slot`.
+    Throttled { sender_id: i64, slot: DateTime<Utc> },
+    /// The lead is on the suppression list, nothing was sent.
+    Suppressed(String),
+}
+
+type SendResult = Result<SendOutcome, Box<dyn Error + Send + Sync>>;
+
+/// Composes the email of the given kind for a message, threads it under the
+/// emails already sent for that message, hands it to the sender's transport
+/// and records it in `sent_emails`. SMS messages are texted to the lead's
+/// phone and chat messages posted to the contact's chat channel instead.
+async fn send_outreach(
+    pool: &SqlitePool,
+    config: &Config,
+    throttles: &mut HashMap<i64, Throttle>,
+    now: DateTime<Utc>,
+    message_id: i64,
+    kind: EmailKind,
+) -> SendResult {
+    let message = fetch_message(pool, message_id)
+        .await?
+        .ok_or("message not found")?;
+
+    let mut sender = None;
+    if let Some(sender_id) = message.sender_id {
+        let throttle = match throttles.entry(sender_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(load_throttle(pool, sender_id, now).await?),
+        };
+
+        if let Some(slot) = throttle.next_slot(now) {
+            return Ok(SendOutcome::Throttled { sender_id, slot });
+        }
+
+        sender = Some(throttle.sender().clone());
+    }
+
+    let body = match kind {
+        EmailKind::Initial => message.message_sent.clone(),
+        EmailKind::FollowUp => message
+            .follow_up_body
+            .clone()
+            .or_else(|| Some(DEFAULT_FOLLOW_UP.to_string())),
+        EmailKind::AiReply => message.ai_reply.clone(),
+    }
+    .unwrap_or_default();
+
+    let lead = fetch_lead(pool, message.leads_id)
+        .await?
+        .ok_or("lead not found")?;
+
+    if let Some(suppression) = lead_suppression(pool, &lead).await? {
+        return Ok(SendOutcome::Suppressed(format!(
+            "{} {} is suppressed",
+            suppression.kind, suppression.value
+        )));
+    }
+
+    if message.channel == Channel::Sms.as_str() {
+        let phone = lead.phone.as_deref().ok_or("lead has no phone number")?;
+        let text = sms::compose(&body, kind);
+        sms::send(pool, config, Some(message_id), Some(kind), phone, &text).await?;
+        return Ok(SendOutcome::Sent(None));
+    }
+
+    if message.channel == Channel::Chat.as_str() {
+        let contact_id = message.contact_id.ok_or("message has no chat contact")?;
+        chat::send(pool, message_id, kind, contact_id, &body).await?;
+        return Ok(SendOutcome::Sent(None));
+    }
+
+    let thread: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT email_message_id FROM sent_emails
+        WHERE message_id = ? AND email_message_id IS NOT NULL
+        ORDER BY id ASC
+        "#,
+    )
+    .bind(message_id)
+    .fetch_all(pool)
+    .await?;
+
+    let workspace = fetch_workspace(pool, lead.workspace_id).await?;
+
+    let (html, attachments) = match kind {
+        EmailKind::Initial => (
+            message.message_html.as_deref(),
+            attachments::load_for_message(pool, config, message_id).await?,
+        ),
+        _ => (None, Vec::new()),
+    };
+
+    let mut email = OutgoingEmail::compose(message_id, &body, html, &lead, sender.as_ref())
+        .ok_or("lead has no email address")?
+        .in_thread(&thread)
+        .with_subject(message.subject.as_deref(), kind);
+    // Tracking goes before the footer so the unsubscribe link is left as is.
+    if message.tracking {
+        email = email.with_tracking(config, message_id);
+    }
+    let email = email
+        .with_compliance_footer(workspace.as_ref(), &unsubscribe_url(config, lead.id))
+        .with_attachments(attachments);
+
+    let transport = sender
+        .as_ref()
+        .map(|s| s.transport.0.clone())
+        .unwrap_or_default();
+    deliver(&transport, &email).await?;
+
+    if let Some(throttle) = message.sender_id.and_then(|id| throttles.get_mut(&id)) {
+        throttle.record_send(now);
+    }
+
+    let recorded = sqlx::query(
+        "INSERT INTO sent_emails (message_id, sender_id, kind, email_message_id, sent_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(message_id)
+    .bind(message.sender_id)
+    .bind(kind.as_str())
+    .bind(&email.message_id)
+    .bind(now.to_rfc3339())
+    .execute(pool)
+    .await;
+
+    if let Err(e) = recorded {
+        error!(
+            "Failed to record sent email {} for message {}: {}",
+            email.message_id, message_id, e
+        );
+    }
+
+    Ok(SendOutcome::Sent(Some(email.message_id)))
+}
+
+async fn load_throttle(
+    pool: &SqlitePool,
+    sender_id: i64,
+    now: DateTime<Utc>,
+) -> Result<Throttle, Box<dyn Error + Send + Sync>> {
+    let sender = fetch_sender(pool, sender_id)
+        .await?
+        .ok_or_else(|| format!("sender {} not found", sender_id))?;
+
+    Ok(Throttle::load(pool, sender, now).await?)
+}
+
+async fn defer_message(pool: &SqlitePool, message_id: i64, sender_id: i64, slot: DateTime<Utc>) {
+    let slot = slot.to_rfc3339();
+
+    let result = sqlx::query("UPDATE messages SET scheduled_at = ? WHERE id = ?")
+        .bind(&slot)
+        .bind(message_id)
+        .execute(pool)
+        .await;
+
+    match result {
+        Ok(_) => info!(
+            "Message {} deferred to {} (sender {} at its sending limit)",
+            message_id, slot, sender_id
+        ),
+        Err(e) => error!("Failed to defer message {}: {}", message_id, e),
+    }
+}
+
+/// Closes a message whose lead is suppressed so no job picks it up again.
+async fn close_suppressed(pool: &SqlitePool, message_id: i64, reason: &str) {
+    let result = sqlx::query("UPDATE messages SET status = ?, closed_at = ? WHERE id = ?")
+        .bind(MessageStatus::Closed.as_str())
+        .bind(Utc::now().to_rfc3339())
+        .bind(message_id)
+        .execute(pool)
+        .await;
+
+    match result {
+        Ok(_) => {
+            log_outreach(pool, message_id, MessageStatus::Closed).await;
+            warn!("Message {} closed without sending: {}", message_id, reason);
+        }
+        Err(e) => error!("Failed to close message {}: {}", message_id, e),
+    }
+}
+
+async fn process_ai_enqueued_messages(pool: &SqlitePool, config: &Config) {
     info!("Processing AI enqueued messages");
 
-    let messages: Vec<(i64,)> =
-        sqlx::query_as("SELECT id FROM messages WHERE status = ?")
-            .bind(MessageStatus::AiEnqueued.as_str())
-            .fetch_all(pool)
-            .await
-            .unwrap_or_default();
+    let messages: Vec<(i64,)> = sqlx::query_as(
+        r#"
+        SELECT id FROM messages
+        WHERE status = ?
+          AND (channel != 'email' OR leads_id NOT IN (SELECT id FROM leads WHERE email_undeliverable_at IS NOT NULL))
+          AND (campaign_id IS NULL OR campaign_id IN (SELECT id FROM campaigns WHERE status = 'active'))
+        "#,
+    )
+    .bind(MessageStatus::AiEnqueued.as_str())
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
 
     if messages.is_empty() {
         info!("No AI enqueued messages to process");
@@ -112,13 +385,34 @@ async fn process_ai_enqueued_messages(pool: &SqlitePool) {
 
     info!("Found {} AI enqueued messages to process", messages.len());
 
-    let now = Utc::now().to_rfc3339();
+    let now = Utc::now();
+    let sent_at = now.to_rfc3339();
     let new_status = MessageStatus::AiReplied.as_str();
+    let mut throttles: HashMap<i64, Throttle> = HashMap::new();
 
     for (message_id,) in messages {
+        match send_outreach(pool, config, &mut throttles, now, message_id, EmailKind::AiReply).await {
+            Ok(SendOutcome::Sent(_)) => {}
+            Ok(SendOutcome::Throttled { sender_id, slot }) => {
+                info!(
+                    "AI reply for message {} waits until {} (sender {} at its sending limit)",
+                    message_id, slot, sender_id
+                );
+                continue;
+            }
+            Ok(SendOutcome::Suppressed(reason)) => {
+                close_suppressed(pool, message_id, &reason).await;
+                continue;
+            }
+            Err(e) => {
+                error!("Failed to send AI reply for message {}: {}", message_id, e);
+                continue;
+            }
+        }
+
         let result = sqlx::query("UPDATE messages SET status = ?, ai_reply_sent = ? WHERE id = ?")
             .bind(new_status)
-            .bind(&now)
+            .bind(&sent_at)
             .bind(message_id)
             .execute(pool)
             .await;
@@ -135,7 +429,7 @@ async fn process_ai_enqueued_messages(pool: &SqlitePool) {
     }
 }
 
-async fn process_follow_up_messages(pool: &SqlitePool) {
+async fn process_follow_up_messages(pool: &SqlitePool, config: &Config) {
     info!("Processing messages for follow-up (sent_at > 24h with no reply)");
 
     let cutoff = (Utc::now() - Duration::hours(24)).to_rfc3339();
@@ -150,9 +444,16 @@ async fn process_follow_up_messages(pool: &SqlitePool) {
           AND reply_received_at IS NULL
           AND follow_up_at IS NULL
           AND closed_at IS NULL
+          AND bounced_at IS NULL
+          AND status != ?
+          AND (channel != 'email' OR leads_id NOT IN (SELECT id FROM leads WHERE email_undeliverable_at IS NOT NULL))
+          AND (campaign_id IS NULL OR campaign_id IN (SELECT id FROM campaigns WHERE status = 'active'))
+          AND (snoozed_until IS NULL OR snoozed_until <= ?)
         "#,
     )
     .bind(&cutoff)
+    .bind(MessageStatus::Complained.as_str())
+    .bind(Utc::now().to_rfc3339())
     .fetch_all(pool)
     .await
     .unwrap_or_default();
@@ -167,8 +468,10 @@ async fn process_follow_up_messages(pool: &SqlitePool) {
         messages.len()
     );
 
-    let now = Utc::now().to_rfc3339();
+    let now = Utc::now();
+    let follow_up_at = now.to_rfc3339();
     let new_status = MessageStatus::FollowUp.as_str();
+    let mut throttles: HashMap<i64, Throttle> = HashMap::new();
 
     for (message_id,) in messages {
         info!(
@@ -176,9 +479,28 @@ async fn process_follow_up_messages(pool: &SqlitePool) {
             message_id
         );
 
+        match send_outreach(pool, config, &mut throttles, now, message_id, EmailKind::FollowUp).await {
+            Ok(SendOutcome::Sent(_)) => {}
+            Ok(SendOutcome::Throttled { sender_id, slot }) => {
+                info!(
+                    "Follow-up for message {} waits until {} (sender {} at its sending limit)",
+                    message_id, slot, sender_id
+                );
+                continue;
+            }
+            Ok(SendOutcome::Suppressed(reason)) => {
+                close_suppressed(pool, message_id, &reason).await;
+                continue;
+            }
+            Err(e) => {
+                error!("Failed to send follow-up for message {}: {}", message_id, e);
+                continue;
+            }
+        }
+
         let result = sqlx::query("UPDATE messages SET status = ?, follow_up_at = ? WHERE id = ?")
             .bind(new_status)
-            .bind(&now)
+            .bind(&follow_up_at)
             .bind(message_id)
             .execute(pool)
             .await;
@@ -188,7 +510,7 @@ async fn process_follow_up_messages(pool: &SqlitePool) {
                 log_outreach(pool, message_id, MessageStatus::FollowUp).await;
                 info!(
                     "Message {} marked for follow-up at {}",
-                    message_id, now
+                    message_id, follow_up_at
                 );
             }
             Err(e) => {
@@ -217,9 +539,15 @@ async fn process_closed_messages(pool: &SqlitePool) {
           AND reply_received IS NULL
           AND reply_received_at IS NULL
           AND closed_at IS NULL
+          AND bounced_at IS NULL
+          AND status != ?
+          AND (campaign_id IS NULL OR campaign_id IN (SELECT id FROM campaigns WHERE status = 'active'))
+          AND (snoozed_until IS NULL OR snoozed_until <= ?)
         "#,
     )
     .bind(&cutoff)
+    .bind(MessageStatus::Complained.as_str())
+    .bind(Utc::now().to_rfc3339())
     .fetch_all(pool)
     .await
     .unwrap_or_default();
@@ -0,0 +1,384 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::SqlitePool;
+use sqlx::types::Json;
+use tracing::{error, info};
+
+use crate::fields;
+use crate::handlers::{fetch_lead, fetch_workspace};
+use crate::models::{Lead, LeadScoring, ProfileRule, ReplyIntent};
+
+const MAX_SCORE: f64 = 100.0;
+
+/// Checks the rules can be applied.
+pub fn validate(scoring: &LeadScoring) -> Result<(), String> {
+    if let Some(intent) = scoring
+        .intents
+        .keys()
+        .find(|intent| intent.parse::<ReplyIntent>().is_err())
+    {
+        return Err(format!("Unknown reply intent {:?}", intent));
+    }
+    if scoring.half_life_days.is_some_and(|days| days <= 0.0) {
+        return Err("Half-life must be a positive number of days".to_string());
+    }
+    if scoring
+        .profile
+        .iter()
+        .any(|rule| rule.field.trim().is_empty())
+    {
+        return Err("Profile rules need a field".to_string());
+    }
+    Ok(())
+}
+
+/// The lead's score from its profile, the `(step, at)` events of its
+/// messages and the `(intent, at)` of their replies.
+pub fn score(
+    scoring: &LeadScoring,
+    lead: &Lead,
+    events: &[(String, String)],
+    intents: &[(String, String)],
+    now: DateTime<Utc>,
+) -> i64 {
+    let decay = |at: &str| match (scoring.half_life_days, parse_time(at)) {
+        (Some(half_life), Some(at)) => {
+            let age_days = (now - at).num_seconds().max(0) as f64 / 86_400.0;
+            0.5_f64.powf(age_days / half_life)
+        }
+        _ => 1.0,
+    };
+
+    let engagement: f64 = events
+        .iter()
+        .filter_map(|(step, at)| scoring.events.get(step).map(|points| points * decay(at)))
+        .sum();
+    let replies: f64 = intents
+        .iter()
+        .filter_map(|(intent, at)| scoring.intents.get(intent).map(|points| points * decay(at)))
+        .sum();
+    let profile: f64 = scoring
+        .profile
+        .iter()
+        .filter(|rule| matches(rule, lead))
+        .map(|rule| rule.points)
+        .sum();
+
+    (engagement + replies + profile)
+        .clamp(0.0, MAX_SCORE)
+        .round() as i64
+}
+
+/// Recomputes and stores a lead's score. `None` when the lead does not
+/// exist.
+pub async fn rescore_lead(pool: &SqlitePool, lead_id: i64) -> Result<Option<Lead>, sqlx::Error> {
+    let Some(lead) = fetch_lead(pool, lead_id).await? else {
+        return Ok(None);
+    };
+    let scoring = fetch_workspace(pool, lead.workspace_id)
+        .await?
+        .map(|workspace| workspace.lead_scoring.0)
+        .unwrap_or_default();
+
+    store_score(pool, &scoring, &lead).await
+}
+
+/// Scores the lead with its workspace's rules and stores the score.
+async fn store_score(
+    pool: &SqlitePool,
+    scoring: &LeadScoring,
+    lead: &Lead,
+) -> Result<Option<Lead>, sqlx::Error> {
+    let events: Vec<(String, String)> = sqlx::query_as(
+        r#"
+        SELECT o.step, o.log_at FROM outreach_log o
+        JOIN messages m ON m.id = o.message_id
+        WHERE m.leads_id = ?
+        "#,
+    )
+    .bind(lead.id)
+    .fetch_all(pool)
+    .await?;
+
+    let intents: Vec<(String, String)> = sqlx::query_as(
+        r#"
+        SELECT reply_intent, COALESCE(reply_received_at, created_at) FROM messages
+        WHERE leads_id = ? AND reply_intent IS NOT NULL
+        "#,
+    )
+    .bind(lead.id)
+    .fetch_all(pool)
+    .await?;
+
+    let now = Utc::now();
+    let score = score(scoring, lead, &events, &intents, now);
+
+    sqlx::query_as::<_, Lead>(
+        r#"
+        UPDATE leads SET score = ?, score_updated_at = ?
+        WHERE id = ?
+        RETURNING id, name, email, phone, email_undeliverable_at, email_undeliverable_reason, workspace_id, consent_status, consent_source, consent_at, score, score_updated_at, fields
+        "#,
+    )
+    .bind(score)
+    .bind(now.to_rfc3339())
+    .bind(lead.id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Rescores the lead a message was sent to, after an event on it.
+pub async fn rescore_message_lead(pool: &SqlitePool, message_id: i64) {
+    let lead_id: Option<i64> =
+        match sqlx::query_scalar("SELECT leads_id FROM messages WHERE id = ?")
+            .bind(message_id)
+            .fetch_optional(pool)
+            .await
+        {
+            Ok(lead_id) => lead_id,
+            Err(e) => {
+                error!("Failed to fetch the lead of message {}: {}", message_id, e);
+                return;
+            }
+        };
+
+    if let Some(lead_id) = lead_id
+        && let Err(e) = rescore_lead(pool, lead_id).await
+    {
+        error!("Failed to score lead {}: {}", lead_id, e);
+    }
+}
+
+/// Rescores the workspace's leads, or every lead, so scores follow rule
+/// changes and decay even without new events. Each workspace's rules are
+/// read once for all of its leads.
+pub async fn rescore_all(pool: &SqlitePool, workspace_id: Option<i64>) {
+    info!("Rescoring leads");
+
+    let workspaces: Vec<(i64, Json<LeadScoring>)> =
+        match sqlx::query_as("SELECT id, lead_scoring FROM workspaces WHERE ?1 IS NULL OR id = ?1")
+            .bind(workspace_id)
+            .fetch_all(pool)
+            .await
+        {
+            Ok(workspaces) => workspaces,
+            Err(e) => {
+                error!("Failed to fetch scoring rules: {}", e);
+                return;
+            }
+        };
+
+    let mut scored = 0;
+    let mut failed = 0;
+    for (workspace_id, scoring) in &workspaces {
+        let leads = match sqlx::query_as::<_, Lead>(
+            r#"
+            SELECT id, name, email, phone, email_undeliverable_at, email_undeliverable_reason, workspace_id, consent_status, consent_source, consent_at, score, score_updated_at, fields
+            FROM leads
+            WHERE workspace_id = ?
+            ORDER BY id
+            "#,
+        )
+        .bind(workspace_id)
+        .fetch_all(pool)
+        .await
+        {
+            Ok(leads) => leads,
+            Err(e) => {
+                error!("Failed to fetch leads of workspace {} to score: {}", workspace_id, e);
+                continue;
+            }
+        };
+
+        for lead in &leads {
+            match store_score(pool, scoring, lead).await {
+                Ok(_) => scored += 1,
+                Err(e) => {
+                    error!("Failed to score lead {}: {}", lead.id, e);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    info!("Rescored {} leads, {} failed", scored, failed);
+}
+
+fn matches(rule: &ProfileRule, lead: &Lead) -> bool {
+    let Some(value) = profile_value(lead, &rule.field) else {
+        return false;
+    };
+    let value = value.trim().to_lowercase();
+    if value.is_empty() {
+        return false;
+    }
+
+    if !rule.any_of.is_empty() && !rule.any_of.iter().any(|v| v.trim().to_lowercase() == value) {
+        return false;
+    }
+    if let Some(part) = rule.contains.as_deref()
+        && !value.contains(&part.to_lowercase())
+    {
+        return false;
+    }
+    if rule.min.is_some() || rule.max.is_some() {
+        let Ok(number) = value.parse::<f64>() else {
+            return false;
+        };
+        if rule.min.is_some_and(|min| number < min) || rule.max.is_some_and(|max| number > max) {
+            return false;
+        }
+    }
+    true
+}
+
+fn profile_value(lead: &Lead, field: &str) -> Option<String> {
+    match field {
+        "email_domain" => lead
+            .email
+            .as_deref()
+            .and_then(|email| email.rsplit_once('@'))
+            .map(|(_, domain)| domain.to_string()),
+        _ => fields::variable(lead, field),
+    }
+}
+
+/// Reads both the app's RFC 3339 timestamps and SQLite's `CURRENT_TIMESTAMP`.
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").map(|t| t.and_utc()))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+
+    use super::*;
+
+    fn lead() -> Lead {
+        Lead {
+            id: 1,
+            name: "Ada Lovelace".to_string(),
+            email: Some("ada@Example.org".to_string()),
+            phone: None,
+            email_undeliverable_at: None,
+            email_undeliverable_reason: None,
+            workspace_id: 1,
+            consent_status: "unknown".to_string(),
+            consent_source: None,
+            consent_at: None,
+            score: 0,
+            score_updated_at: None,
+            fields: Json(
+                [
+                    ("company".to_string(), json!("Acme Analytics")),
+                    ("employees".to_string(), json!(250)),
+                ]
+                .into(),
+            ),
+        }
+    }
+
+    fn scoring(rules: serde_json::Value) -> LeadScoring {
+        serde_json::from_value(rules).unwrap()
+    }
+
+    fn event(step: &str, at: DateTime<Utc>) -> (String, String) {
+        (step.to_string(), at.to_rfc3339())
+    }
+
+    #[test]
+    fn points_halve_every_half_life() {
+        let scoring = scoring(json!({"events": {"clicked": 40.0}, "half_life_days": 10.0}));
+        let now = Utc::now();
+
+        let score_at = |age| score(&scoring, &lead(), &[event("clicked", now - age)], &[], now);
+        assert_eq!(score_at(Duration::zero()), 40);
+        assert_eq!(score_at(Duration::days(10)), 20);
+        assert_eq!(score_at(Duration::days(20)), 10);
+    }
+
+    #[test]
+    fn points_do_not_decay_without_half_life() {
+        let scoring = scoring(json!({"intents": {"interested": 30.0}, "half_life_days": null}));
+        let now = Utc::now();
+        let intents = [event("interested", now - Duration::days(365))];
+
+        assert_eq!(score(&scoring, &lead(), &[], &intents, now), 30);
+    }
+
+    #[test]
+    fn reads_sqlite_timestamps() {
+        let scoring = scoring(json!({"events": {"opened": 8.0}, "half_life_days": 1.0}));
+        let now = Utc::now();
+        let at = (now - Duration::days(1))
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+
+        let events = [("opened".to_string(), at)];
+        assert_eq!(score(&scoring, &lead(), &events, &[], now), 4);
+    }
+
+    #[test]
+    fn clamps_between_0_and_100() {
+        let scoring = scoring(json!({"events": {"clicked": 60.0, "complained": -30.0}}));
+        let now = Utc::now();
+
+        let clicks = [event("clicked", now), event("clicked", now)];
+        assert_eq!(score(&scoring, &lead(), &clicks, &[], now), 100);
+
+        let complaints = [event("complained", now)];
+        assert_eq!(score(&scoring, &lead(), &complaints, &[], now), 0);
+    }
+
+    #[test]
+    fn ignores_steps_without_points() {
+        let scoring = scoring(json!({"events": {"clicked": 5.0}}));
+        let now = Utc::now();
+
+        let events = [event("sent", now), event("clicked", now)];
+        assert_eq!(score(&scoring, &lead(), &events, &[], now), 5);
+    }
+
+    #[test]
+    fn profile_rules_match_every_condition() {
+        let now = Utc::now();
+        let profile = |rule: serde_json::Value| {
+            let mut rule = rule;
+            rule["points"] = json!(10.0);
+            score(&scoring(json!({"profile": [rule]})), &lead(), &[], &[], now)
+        };
+
+        assert_eq!(
+            profile(json!({"field": "email_domain", "any_of": ["example.org"]})),
+            10
+        );
+        assert_eq!(
+            profile(json!({"field": "email_domain", "any_of": ["acme.com"]})),
+            0
+        );
+        assert_eq!(
+            profile(json!({"field": "company", "contains": "ANALYTICS"})),
+            10
+        );
+        assert_eq!(
+            profile(json!({"field": "company", "contains": "widgets"})),
+            0
+        );
+        assert_eq!(
+            profile(json!({"field": "employees", "min": 100.0, "max": 500.0})),
+            10
+        );
+        assert_eq!(profile(json!({"field": "employees", "min": 500.0})), 0);
+        assert_eq!(profile(json!({"field": "employees", "max": 100.0})), 0);
+        assert_eq!(profile(json!({"field": "company", "min": 1.0})), 0);
+        assert_eq!(
+            profile(json!({"field": "company", "any_of": ["acme analytics"], "contains": "x"})),
+            0
+        );
+        assert_eq!(profile(json!({"field": "phone"})), 0);
+        assert_eq!(profile(json!({"field": "name"})), 10);
+    }
+}
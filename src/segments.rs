@@ -0,0 +1,612 @@
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+use chrono::Utc;
+use serde_json::Value;
+use sqlx::SqlitePool;
+
+use crate::fields;
+use crate::models::{LeadSort, LeadSummary, MessageStatus, Segment, SegmentRequest};
+use crate::tags;
+
+const SEGMENT_COLUMNS: &str = "id, workspace_id, name, filter, created_at";
+
+/// Longest filter expression accepted, in characters.
+const MAX_FILTER_LEN: usize = 1000;
+
+/// Deepest nesting of parentheses and `NOT` accepted, so parsing and
+/// evaluating a filter cannot run out of stack.
+const MAX_DEPTH: usize = 32;
+
+const LATEST_STATUS: &str = "(SELECT m.status FROM messages m WHERE m.leads_id = l.id \
+     ORDER BY m.created_at DESC, m.id DESC LIMIT 1)";
+
+/// Lead columns expressions can match, by key.
+const LEAD_COLUMNS: &[(&str, &str)] = &[
+    ("name", "l.name"),
+    ("email", "l.email"),
+    ("phone", "l.phone"),
+    ("consent", "l.consent_status"),
+    ("email_domain", "SUBSTR(l.email, INSTR(l.email, '@') + 1)"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Op {
+    /// Written as in expressions, longest first so `>=` is not read as `>`.
+    const SYMBOLS: [(&'static str, Op); 7] = [
+        (">=", Op::Ge),
+        ("<=", Op::Le),
+        ("!=", Op::Ne),
+        (":", Op::Eq),
+        ("=", Op::Eq),
+        (">", Op::Gt),
+        ("<", Op::Lt),
+    ];
+
+    fn sql(self) -> &'static str {
+        match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+        }
+    }
+
+    fn is_comparison(self) -> bool {
+        !matches!(self, Op::Eq | Op::Ne)
+    }
+}
+
+/// A parsed filter expression over a workspace's leads.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// `tag`, `status`, `score`, a lead column or a custom field key
+    /// compared to a value.
+    Term {
+        key: String,
+        op: Op,
+        value: String,
+    },
+}
+
+impl Expr {
+    pub fn term(key: &str, op: Op, value: impl ToString) -> Expr {
+        Expr::Term {
+            key: key.to_string(),
+            op,
+            value: value.to_string(),
+        }
+    }
+
+    /// All the conditions, or `None` when there are none.
+    pub fn all(conditions: Vec<Expr>) -> Option<Expr> {
+        conditions
+            .into_iter()
+            .reduce(|all, condition| Expr::And(Box::new(all), Box::new(condition)))
+    }
+}
+
+/// A value bound to a `?` placeholder of the SQL of an expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Param {
+    Text(String),
+    Integer(i64),
+    Number(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Word(String),
+    Quoted(String),
+}
+
+/// Parses a filter expression such as
+/// `tag:webinar AND (status:follow_up OR score>50) AND NOT industry:retail`.
+///
+/// Conditions are `key:value`, `key!=value` or, for `score` and number
+/// fields, `key>value`, `>=`, `<` and `<=`. Keys are `tag`, `status` (of the
+/// latest message), `score`, `name`, `email`, `email_domain`, `phone`,
+/// `consent` or a custom field key. Values with spaces are quoted. `AND`,
+/// `OR` and `NOT` are case-insensitive and `AND` binds tighter than `OR`.
+pub fn parse(input: &str) -> Result<Expr, String> {
+    if input.chars().count() > MAX_FILTER_LEN {
+        return Err(format!(
+            "Filter is too long, the limit is {} characters",
+            MAX_FILTER_LEN
+        ));
+    }
+
+    let mut parser = Parser {
+        tokens: tokenize(input)?.into_iter().peekable(),
+        depth: 0,
+    };
+    let expr = parser.or()?;
+    match parser.tokens.next() {
+        None => Ok(expr),
+        Some(Token::Close) => Err("Unexpected ')'".to_string()),
+        Some(Token::Word(word) | Token::Quoted(word)) => {
+            Err(format!("Expected AND or OR before {:?}", word))
+        }
+        Some(Token::Open) => Err("Expected AND or OR before '('".to_string()),
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => text.push(c),
+                        None => return Err("Missing closing quote".to_string()),
+                    }
+                }
+                tokens.push(Token::Quoted(text));
+            }
+            _ if c.is_whitespace() => {}
+            _ => {
+                let mut word = c.to_string();
+                while let Some(c) =
+                    chars.next_if(|c| !c.is_whitespace() && !matches!(c, '(' | ')' | '"'))
+                {
+                    word.push(c);
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Peekable<IntoIter<Token>>,
+    /// Parentheses and `NOT`s the parser is inside of.
+    depth: usize,
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.keyword("OR") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.keyword("AND") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.keyword("NOT") {
+            let expr = self.nested(Self::unary)?;
+            return Ok(Expr::Not(Box::new(expr)));
+        }
+
+        match self.tokens.next() {
+            Some(Token::Open) => {
+                let expr = self.nested(Self::or)?;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err("Missing closing ')'".to_string()),
+                }
+            }
+            Some(Token::Word(word)) => self.condition(&word),
+            Some(Token::Quoted(text)) => Err(format!(
+                "Unexpected {:?}, expected a condition such as tag:webinar",
+                text
+            )),
+            Some(Token::Close) => Err("Unexpected ')'".to_string()),
+            None => Err("Expected a condition such as tag:webinar".to_string()),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Expr, String>) -> Result<Expr, String> {
+        if self.depth >= MAX_DEPTH {
+            return Err(format!(
+                "Filter is nested too deeply, the limit is {} levels",
+                MAX_DEPTH
+            ));
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        self.tokens
+            .next_if(
+                |token| matches!(token, Token::Word(word) if word.eq_ignore_ascii_case(keyword)),
+            )
+            .is_some()
+    }
+
+    fn condition(&mut self, word: &str) -> Result<Expr, String> {
+        let invalid = || {
+            format!(
+                "Invalid condition {:?}, expected e.g. tag:webinar or score>50",
+                word
+            )
+        };
+
+        let at = word.find([':', '=', '!', '<', '>']).ok_or_else(invalid)?;
+        let (key, rest) = word.split_at(at);
+        let (op, value) = Op::SYMBOLS
+            .iter()
+            .find_map(|(symbol, op)| rest.strip_prefix(symbol).map(|value| (*op, value)))
+            .ok_or_else(invalid)?;
+
+        // `company:"Acme Inc"` is a word followed by a quoted value.
+        let value = match value {
+            "" => match self
+                .tokens
+                .next_if(|token| matches!(token, Token::Quoted(_)))
+            {
+                Some(Token::Quoted(text)) => text,
+                _ => return Err(format!("Missing value in {:?}", word)),
+            },
+            value => value.to_string(),
+        };
+
+        validate_term(&key.to_lowercase(), op, &value)
+    }
+}
+
+fn validate_term(key: &str, op: Op, value: &str) -> Result<Expr, String> {
+    let value = value.trim();
+    let text_only = matches!(key, "tag" | "status") || is_lead_column(key);
+    if text_only && op.is_comparison() {
+        return Err(format!("{} can only be matched with : or !=", key));
+    }
+    if (key == "score" || op.is_comparison())
+        && !value.parse::<f64>().is_ok_and(|number| number.is_finite())
+    {
+        return Err(format!("{} can only be compared to a number", key));
+    }
+
+    match key {
+        "tag" => Ok(Expr::term(key, op, tags::normalize(value)?)),
+        "status" => {
+            let status: MessageStatus =
+                serde_json::from_value(Value::from(value.to_lowercase()))
+                    .map_err(|_| format!("Unknown message status {:?}", value))?;
+            Ok(Expr::term(key, op, status.as_str()))
+        }
+        _ if key == "score" || text_only || fields::is_key(key) => Ok(Expr::term(key, op, value)),
+        _ => Err(format!("Unknown filter key {:?}", key)),
+    }
+}
+
+fn is_lead_column(key: &str) -> bool {
+    LEAD_COLUMNS.iter().any(|(name, _)| *name == key)
+}
+
+/// SQL condition over the lead `l` matching the expression, binding its
+/// values to `?` placeholders in the order they are pushed to `params`.
+fn to_sql(expr: &Expr, params: &mut Vec<Param>) -> String {
+    let (key, op, value) = match expr {
+        Expr::And(left, right) => {
+            return format!("({} AND {})", to_sql(left, params), to_sql(right, params));
+        }
+        Expr::Or(left, right) => {
+            return format!("({} OR {})", to_sql(left, params), to_sql(right, params));
+        }
+        Expr::Not(expr) => return format!("NOT {}", to_sql(expr, params)),
+        Expr::Term { key, op, value } => (key.as_str(), *op, value),
+    };
+
+    // Terms never evaluate to NULL, which NOT would keep as NULL.
+    match key {
+        "tag" => {
+            params.push(Param::Text(value.clone()));
+            let exists = "EXISTS (SELECT 1 FROM lead_tags t WHERE t.lead_id = l.id AND t.tag = ?)";
+            match op {
+                Op::Ne => format!("NOT {}", exists),
+                _ => exists.to_string(),
+            }
+        }
+        "status" => {
+            params.push(Param::Text(value.clone()));
+            format!("IFNULL({}, '') {} ?", LATEST_STATUS, op.sql())
+        }
+        "score" => {
+            params.push(Param::Number(value.parse().unwrap_or_default()));
+            format!("l.score {} ?", op.sql())
+        }
+        _ => {
+            if let Some((_, column)) = LEAD_COLUMNS.iter().find(|(name, _)| *name == key) {
+                params.push(Param::Text(value.clone()));
+                return format!("LOWER(IFNULL({}, '')) {} LOWER(?)", column, op.sql());
+            }
+
+            let path = fields::json_path(key);
+            params.push(Param::Text(path.clone()));
+            params.push(Param::Text(path));
+            if op.is_comparison() {
+                params.push(Param::Number(value.parse().unwrap_or_default()));
+                format!(
+                    "(IFNULL(json_type(l.fields, ?), '') IN ('integer', 'real') \
+                     AND json_extract(l.fields, ?) {} ?)",
+                    op.sql()
+                )
+            } else {
+                // Booleans compare as `true`/`false` and everything else as text.
+                params.push(Param::Text(value.clone()));
+                format!(
+                    "LOWER(IFNULL(CASE json_type(l.fields, ?) WHEN 'true' THEN 'true' \
+                     WHEN 'false' THEN 'false' ELSE CAST(json_extract(l.fields, ?) AS TEXT) END, \
+                     '')) {} LOWER(?)",
+                    op.sql()
+                )
+            }
+        }
+    }
+}
+
+/// `WHERE` clause selecting the leads `l` of the workspace, or of every
+/// workspace, that match the filter.
+fn where_clause(workspace_id: Option<i64>, filter: Option<&Expr>) -> (String, Vec<Param>) {
+    let mut params = Vec::new();
+    let mut conditions = Vec::new();
+    if let Some(workspace_id) = workspace_id {
+        params.push(Param::Integer(workspace_id));
+        conditions.push("l.workspace_id = ?".to_string());
+    }
+    if let Some(filter) = filter {
+        conditions.push(to_sql(filter, &mut params));
+    }
+
+    if conditions.is_empty() {
+        (String::new(), params)
+    } else {
+        (format!("WHERE {}", conditions.join(" AND ")), params)
+    }
+}
+
+/// The leads matching the filter, with an overview of their messages and
+/// their tags.
+pub async fn lead_summaries(
+    pool: &SqlitePool,
+    workspace_id: Option<i64>,
+    filter: Option<&Expr>,
+    sort: LeadSort,
+) -> Result<Vec<LeadSummary>, sqlx::Error> {
+    let (where_clause, params) = where_clause(workspace_id, filter);
+    let order = match sort {
+        LeadSort::Id => "l.id ASC",
+        LeadSort::Score => "l.score DESC, l.id ASC",
+    };
+    let sql = format!(
+        r#"
+        SELECT l.id, l.name, l.email, l.phone, l.email_undeliverable_at, l.email_undeliverable_reason, l.workspace_id, l.consent_status, l.consent_source, l.consent_at, l.score, l.score_updated_at, l.fields,
+               (SELECT COUNT(*) FROM messages m WHERE m.leads_id = l.id) AS message_count,
+               {} AS latest_status,
+               (SELECT json_group_array(tag) FROM (SELECT t.tag FROM lead_tags t WHERE t.lead_id = l.id ORDER BY t.tag)) AS tags
+        FROM leads l
+        {}
+        ORDER BY {}
+        "#,
+        LATEST_STATUS, where_clause, order
+    );
+
+    let mut select = sqlx::query_as::<_, LeadSummary>(&sql);
+    for param in params {
+        select = match param {
+            Param::Text(text) => select.bind(text),
+            Param::Integer(integer) => select.bind(integer),
+            Param::Number(number) => select.bind(number),
+        };
+    }
+    select.fetch_all(pool).await
+}
+
+/// Ids of the leads matching the filter.
+pub async fn lead_ids(
+    pool: &SqlitePool,
+    workspace_id: i64,
+    filter: &Expr,
+) -> Result<Vec<i64>, sqlx::Error> {
+    let (where_clause, params) = where_clause(Some(workspace_id), Some(filter));
+    let sql = format!("SELECT l.id FROM leads l {} ORDER BY l.id", where_clause);
+
+    let mut select = sqlx::query_scalar::<_, i64>(&sql);
+    for param in params {
+        select = match param {
+            Param::Text(text) => select.bind(text),
+            Param::Integer(integer) => select.bind(integer),
+            Param::Number(number) => select.bind(number),
+        };
+    }
+    select.fetch_all(pool).await
+}
+
+pub async fn create(
+    pool: &SqlitePool,
+    workspace_id: i64,
+    request: &SegmentRequest,
+) -> Result<Segment, sqlx::Error> {
+    sqlx::query_as::<_, Segment>(&format!(
+        r#"
+        INSERT INTO segments (workspace_id, name, filter, created_at)
+        VALUES (?, ?, ?, ?)
+        RETURNING {}
+        "#,
+        SEGMENT_COLUMNS
+    ))
+    .bind(workspace_id)
+    .bind(request.name.trim())
+    .bind(request.filter.trim())
+    .bind(Utc::now().to_rfc3339())
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn list(pool: &SqlitePool, workspace_id: i64) -> Result<Vec<Segment>, sqlx::Error> {
+    sqlx::query_as::<_, Segment>(&format!(
+        "SELECT {} FROM segments WHERE workspace_id = ? ORDER BY name ASC",
+        SEGMENT_COLUMNS
+    ))
+    .bind(workspace_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn fetch(pool: &SqlitePool, segment_id: i64) -> Result<Option<Segment>, sqlx::Error> {
+    sqlx::query_as::<_, Segment>(&format!(
+        "SELECT {} FROM segments WHERE id = ?",
+        SEGMENT_COLUMNS
+    ))
+    .bind(segment_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn delete(pool: &SqlitePool, segment_id: i64) -> Result<Option<Segment>, sqlx::Error> {
+    sqlx::query_as::<_, Segment>(&format!(
+        "DELETE FROM segments WHERE id = ? RETURNING {}",
+        SEGMENT_COLUMNS
+    ))
+    .bind(segment_id)
+    .fetch_optional(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(key: &str, op: Op, value: &str) -> Box<Expr> {
+        Box::new(Expr::term(key, op, value))
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            parse("tag:a OR tag:b AND score>50").unwrap(),
+            Expr::Or(
+                term("tag", Op::Eq, "a"),
+                Box::new(Expr::And(
+                    term("tag", Op::Eq, "b"),
+                    term("score", Op::Gt, "50")
+                )),
+            )
+        );
+        assert_eq!(
+            parse("(tag:a or tag:b) and score>=50").unwrap(),
+            Expr::And(
+                Box::new(Expr::Or(term("tag", Op::Eq, "a"), term("tag", Op::Eq, "b"))),
+                term("score", Op::Ge, "50"),
+            )
+        );
+    }
+
+    #[test]
+    fn not_applies_to_the_next_condition() {
+        assert_eq!(
+            parse("NOT tag:a AND status:follow_up").unwrap(),
+            Expr::And(
+                Box::new(Expr::Not(term("tag", Op::Eq, "a"))),
+                term("status", Op::Eq, "follow_up"),
+            )
+        );
+        assert_eq!(
+            parse("not (tag:a OR tag:b)").unwrap(),
+            Expr::Not(Box::new(Expr::Or(
+                term("tag", Op::Eq, "a"),
+                term("tag", Op::Eq, "b")
+            )))
+        );
+    }
+
+    #[test]
+    fn reads_quoted_values_and_normalizes_terms() {
+        assert_eq!(
+            parse(r#"company:"Acme Inc""#).unwrap(),
+            *term("company", Op::Eq, "Acme Inc")
+        );
+        assert_eq!(
+            parse(r#"company!="Acme Inc""#).unwrap(),
+            *term("company", Op::Ne, "Acme Inc")
+        );
+        assert_eq!(
+            parse("TAG:Webinar").unwrap(),
+            *term("tag", Op::Eq, "webinar")
+        );
+        assert_eq!(
+            parse("status:Follow_Up").unwrap(),
+            *term("status", Op::Eq, "follow_up")
+        );
+        assert!(parse(r#"company:"Acme"#).is_err());
+        assert!(parse(r#""Acme""#).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_keys_and_values() {
+        assert!(parse("Bad-Key:1").is_err());
+        assert!(parse("webinar").is_err());
+        assert!(parse("tag:").is_err());
+        assert!(parse("tag>5").is_err());
+        assert!(parse("email<b").is_err());
+        assert!(parse("score>abc").is_err());
+        assert!(parse("score:NaN").is_err());
+        assert!(parse("employees>=many").is_err());
+        assert!(parse("status:followup").is_err());
+        assert!(parse("tag:\"two words\"").is_err());
+        assert_eq!(
+            parse("employees>=100").unwrap(),
+            *term("employees", Op::Ge, "100")
+        );
+    }
+
+    #[test]
+    fn rejects_incomplete_expressions() {
+        assert!(parse("").is_err());
+        assert!(parse("tag:a AND").is_err());
+        assert!(parse("(tag:a").is_err());
+        assert!(parse("tag:a)").is_err());
+        assert!(parse("tag:a tag:b").is_err());
+    }
+
+    #[test]
+    fn limits_nesting_and_length() {
+        let nested = |depth: usize| format!("{}tag:a{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(parse(&nested(1000)).is_err());
+        assert!(parse(&format!("{}tag:a", "NOT ".repeat(200))).is_err());
+        assert!(parse(&format!("{}tag:a", "NOT ".repeat(MAX_DEPTH))).is_ok());
+
+        let long = vec!["tag:a"; 300].join(" OR ");
+        assert!(long.len() > MAX_FILTER_LEN);
+        assert!(parse(&long).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+type HmacSha1 = Hmac<Sha1>;
+
+/// Signs a URL-safe payload, producing a `<payload>.<signature>` token.
+pub fn sign(secret: &str, payload: &str) -> String {
+    format!("{}.{}", payload, hex::encode(signature(secret, payload.as_bytes())))
+}
+
+/// Returns the payload of a token produced by [`sign`] if its signature is valid.
+pub fn verify(secret: &str, token: &str) -> Option<String> {
+    let (payload, signature) = token.rsplit_once('.')?;
+    let signature = hex::decode(signature).ok()?;
+
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).ok()?;
+    mac.update(payload.as_bytes());
+    mac.verify_slice(&signature).ok()?;
+
+    Some(payload.to_string())
+}
+
+/// Hex HMAC-SHA256 of a request body, sent and checked in webhook headers.
+pub fn body_signature(secret: &str, body: &[u8]) -> String {
+    hex::encode(signature(secret, body))
+}
+
+/// Checks a [`body_signature`] in constant time.
+pub fn verify_body(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature.trim()) else {
+        return false;
+    };
+    let Ok(mut mac) = HmacSha256::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Checks Twilio's `X-Twilio-Signature`: the base64 HMAC-SHA1, keyed with
+/// the auth token, of the full webhook URL followed by every form parameter
+/// as name and value, sorted by name.
+pub fn verify_twilio(
+    auth_token: &str,
+    url: &str,
+    params: &[(String, String)],
+    signature: &str,
+) -> bool {
+    let Ok(signature) = STANDARD.decode(signature.trim()) else {
+        return false;
+    };
+    let Ok(mut mac) = HmacSha1::new_from_slice(auth_token.as_bytes()) else {
+        return false;
+    };
+
+    let mut params: Vec<&(String, String)> = params.iter().collect();
+    params.sort();
+    mac.update(url.as_bytes());
+    for (name, value) in params {
+        mac.update(name.as_bytes());
+        mac.update(value.as_bytes());
+    }
+    mac.verify_slice(&signature).is_ok()
+}
+
+fn signature(secret: &str, payload: &[u8]) -> Vec<u8> {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(payload);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The example from Twilio's webhook security documentation.
+    fn twilio_params() -> Vec<(String, String)> {
+        [
+            ("To", "+18005551212"),
+            ("CallSid", "CA1234567890ABCDE"),
+            ("Caller", "+12349013030"),
+            ("Digits", "1234"),
+            ("From", "+12349013030"),
+        ]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn verifies_twilio_signatures() {
+        let url = "https://mycompany.com/myapp.php?foo=1&bar=2";
+        let signature = "0/KCTR6DLpKmkAf8muzZqo1nDgQ=";
+        let params = twilio_params();
+
+        assert!(verify_twilio("12345", url, &params, signature));
+        assert!(!verify_twilio("54321", url, &params, signature));
+        assert!(!verify_twilio(
+            "12345",
+            "https://mycompany.com/",
+            &params,
+            signature
+        ));
+        assert!(!verify_twilio("12345", url, &params, "not base64"));
+
+        let mut changed = twilio_params();
+        changed[3].1 = "4321".to_string();
+        assert!(!verify_twilio("12345", url, &changed, signature));
+    }
+}
@@ -0,0 +1,339 @@
+use std::error::Error;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use tracing::info;
+
+use crate::config::{Config, SmsSettings};
+use crate::models::{EmailKind, SmsKeyword, SuppressionKind};
+use crate::suppression::normalize;
+
+pub type SmsResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Longest text accepted, in segments. Carriers drop or truncate beyond this.
+pub const MAX_SEGMENTS: usize = 10;
+
+/// Appended to the first text sent to a lead.
+pub const OPT_OUT_NOTICE: &str = "Reply STOP to opt out.";
+
+pub const STOP_REPLY: &str = "You have been unsubscribed and will not receive further messages.";
+pub const HELP_REPLY: &str = "Reply STOP to unsubscribe. Contact us by replying to this number.";
+
+/// GSM 03.38 basic character set; each takes one septet.
+const GSM7_BASIC: &str = "@£$¥èéùìòÇ\nØø\rÅåΔ_ΦΓΛΩΠΨΣΘΞÆæßÉ !\"#¤%&'()*+,-./0123456789:;<=>?¡ABCDEFGHIJKLMNOPQRSTUVWXYZÄÖÑÜ§¿abcdefghijklmnopqrstuvwxyzäöñüà";
+
+/// GSM 03.38 extension table; each takes an escape septet plus its own.
+const GSM7_EXTENDED: &str = "^{}\\[~]|€\u{c}";
+
+/// A provider that can deliver a text message.
+#[async_trait]
+pub trait SmsTransport: Send + Sync {
+    /// Sends `body` to `to` and returns the provider's id for the text.
+    async fn send(&self, from: Option<&str>, to: &str, body: &str) -> SmsResult<Option<String>>;
+}
+
+/// Logs texts instead of sending them.
+pub struct LogTransport;
+
+#[async_trait]
+impl SmsTransport for LogTransport {
+    async fn send(&self, from: Option<&str>, to: &str, body: &str) -> SmsResult<Option<String>> {
+        info!(
+            "Delivering SMS from {} to {} via log transport:\n{}",
+            from.unwrap_or("(default)"),
+            to,
+            body
+        );
+        Ok(None)
+    }
+}
+
+/// Header carrying Twilio's signature of an inbound webhook, checked when
+/// texts go through Twilio.
+pub const TWILIO_SIGNATURE_HEADER: &str = "X-Twilio-Signature";
+
+/// How long a send may take before it is given up on.
+const SEND_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Sends through Twilio's Messages API. `api_url` can point at a local stub.
+pub struct TwilioTransport {
+    client: reqwest::Client,
+    api_url: String,
+    account_sid: String,
+    auth_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TwilioMessage {
+    sid: Option<String>,
+}
+
+#[async_trait]
+impl SmsTransport for TwilioTransport {
+    async fn send(&self, from: Option<&str>, to: &str, body: &str) -> SmsResult<Option<String>> {
+        let from = from.ok_or("SMS_FROM_NUMBER is not set")?;
+        let url = format!(
+            "{}/2010-04-01/Accounts/{}/Messages.json",
+            self.api_url, self.account_sid
+        );
+
+        let response = self
+            .client
+            .post(&url)
+            .basic_auth(&self.account_sid, Some(&self.auth_token))
+            .form(&[("From", from), ("To", to), ("Body", body)])
+            .timeout(SEND_TIMEOUT)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(format!("SMS provider returned {}: {}", status, text).into());
+        }
+
+        let message: TwilioMessage = response.json().await?;
+        info!(
+            "Delivered SMS to {} via Twilio ({})",
+            to,
+            message.sid.as_deref().unwrap_or("no sid")
+        );
+
+        Ok(message.sid)
+    }
+}
+
+pub fn transport(settings: &SmsSettings) -> Box<dyn SmsTransport> {
+    match settings {
+        SmsSettings::Log => Box::new(LogTransport),
+        SmsSettings::Twilio {
+            api_url,
+            account_sid,
+            auth_token,
+        } => Box::new(TwilioTransport {
+            client: reqwest::Client::new(),
+            api_url: api_url.clone(),
+            account_sid: account_sid.clone(),
+            auth_token: auth_token.clone(),
+        }),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Gsm7,
+    Ucs2,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SegmentCount {
+    pub encoding: Encoding,
+    /// Septets for GSM-7, UTF-16 code units for UCS-2.
+    pub units: usize,
+    pub segments: usize,
+}
+
+/// Counts the segments a text is split into. GSM-7 fits 160 septets in a
+/// single segment and 153 per segment once concatenated; anything outside
+/// GSM-7 switches the whole text to UCS-2, with 70 and 67 code units.
+pub fn count_segments(body: &str) -> SegmentCount {
+    let mut septets = 0;
+    let mut gsm7 = true;
+
+    for c in body.chars() {
+        if GSM7_BASIC.contains(c) {
+            septets += 1;
+        } else if GSM7_EXTENDED.contains(c) {
+            septets += 2;
+        } else {
+            gsm7 = false;
+            break;
+        }
+    }
+
+    let (encoding, units, single, multi) = if gsm7 {
+        (Encoding::Gsm7, septets, 160, 153)
+    } else {
+        (Encoding::Ucs2, body.encode_utf16().count(), 70, 67)
+    };
+
+    let segments = if units <= single {
+        1
+    } else {
+        units.div_ceil(multi)
+    };
+
+    SegmentCount {
+        encoding,
+        units,
+        segments,
+    }
+}
+
+/// Rejects empty texts and texts longer than `MAX_SEGMENTS`.
+pub fn validate(body: &str) -> Result<SegmentCount, String> {
+    if body.trim().is_empty() {
+        return Err("SMS body is empty".to_string());
+    }
+
+    let count = count_segments(body);
+    if count.segments > MAX_SEGMENTS {
+        return Err(format!(
+            "SMS is {} segments long, at most {} are allowed",
+            count.segments, MAX_SEGMENTS
+        ));
+    }
+
+    Ok(count)
+}
+
+/// Builds the text sent for a message, adding the opt-out notice to the
+/// first one.
+pub fn compose(body: &str, kind: EmailKind) -> String {
+    match kind {
+        EmailKind::Initial => format!("{}\n\n{}", body.trim_end(), OPT_OUT_NOTICE),
+        _ => body.to_string(),
+    }
+}
+
+/// Recognises the carrier-standard STOP and HELP keywords. Only a text that
+/// is the keyword alone counts.
+pub fn keyword(body: &str) -> Option<SmsKeyword> {
+    let word = body
+        .trim()
+        .trim_end_matches(['.', '!'])
+        .to_ascii_uppercase();
+
+    match word.as_str() {
+        "STOP" | "STOPALL" | "UNSUBSCRIBE" | "CANCEL" | "END" | "QUIT" => Some(SmsKeyword::Stop),
+        "HELP" | "INFO" => Some(SmsKeyword::Help),
+        _ => None,
+    }
+}
+
+/// Sends a text through the configured provider and records it in
+/// `sms_messages`. Returns the provider's id for the text.
+pub async fn send(
+    pool: &SqlitePool,
+    config: &Config,
+    message_id: Option<i64>,
+    kind: Option<EmailKind>,
+    to: &str,
+    body: &str,
+) -> SmsResult<Option<String>> {
+    let to = normalize(SuppressionKind::Phone, to);
+    let count = validate(body)?;
+
+    info!(
+        "Sending SMS to {}: {} segment(s), {} {:?} units",
+        to, count.segments, count.units, count.encoding
+    );
+
+    let provider_message_id = transport(&config.sms)
+        .send(config.sms_from.as_deref(), &to, body)
+        .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO sms_messages (message_id, direction, kind, from_number, to_number, body, segments, provider_message_id, created_at)
+        VALUES (?, 'outbound', ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(message_id)
+    .bind(kind.map(|k| k.as_str()))
+    .bind(&config.sms_from)
+    .bind(&to)
+    .bind(body)
+    .bind(count.segments as i64)
+    .bind(&provider_message_id)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+
+    Ok(provider_message_id)
+}
+
+/// Records an inbound text and returns the message last texted to its sender.
+pub async fn record_inbound(
+    pool: &SqlitePool,
+    from: &str,
+    to: Option<&str>,
+    body: &str,
+    provider_message_id: Option<&str>,
+) -> Result<Option<i64>, sqlx::Error> {
+    let from = normalize(SuppressionKind::Phone, from);
+
+    let message_id: Option<i64> = sqlx::query_scalar(
+        r#"
+        SELECT message_id FROM sms_messages
+        WHERE direction = 'outbound' AND to_number = ? AND message_id IS NOT NULL
+        ORDER BY id DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(&from)
+    .fetch_optional(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO sms_messages (message_id, direction, from_number, to_number, body, segments, provider_message_id, created_at)
+        VALUES (?, 'inbound', ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(message_id)
+    .bind(&from)
+    .bind(to)
+    .bind(body)
+    .bind(count_segments(body).segments as i64)
+    .bind(provider_message_id)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+
+    Ok(message_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(body: &str) -> (Encoding, usize, usize) {
+        let count = count_segments(body);
+        (count.encoding, count.units, count.segments)
+    }
+
+    #[test]
+    fn gsm7_segments() {
+        assert_eq!(count(&"a".repeat(160)), (Encoding::Gsm7, 160, 1));
+        assert_eq!(count(&"a".repeat(161)), (Encoding::Gsm7, 161, 2));
+        assert_eq!(count(&"a".repeat(306)), (Encoding::Gsm7, 306, 2));
+        assert_eq!(count(&"a".repeat(307)), (Encoding::Gsm7, 307, 3));
+        // Extension characters take two septets.
+        assert_eq!(count(&"€".repeat(80)), (Encoding::Gsm7, 160, 1));
+        assert_eq!(count(&"[".repeat(81)), (Encoding::Gsm7, 162, 2));
+    }
+
+    #[test]
+    fn ucs2_segments() {
+        assert_eq!(count(&"ą".repeat(70)), (Encoding::Ucs2, 70, 1));
+        assert_eq!(count(&"ą".repeat(71)), (Encoding::Ucs2, 71, 2));
+        // A single character outside GSM-7 switches the whole text.
+        assert_eq!(
+            count(&format!("{}ą", "a".repeat(69))),
+            (Encoding::Ucs2, 70, 1)
+        );
+        // Emoji are surrogate pairs.
+        assert_eq!(count("Hi 👋"), (Encoding::Ucs2, 5, 1));
+    }
+
+    #[test]
+    fn validates_length() {
+        assert!(validate("  ").is_err());
+        assert_eq!(validate(&"a".repeat(1530)).unwrap().segments, MAX_SEGMENTS);
+        assert!(validate(&"a".repeat(1531)).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use chrono::Utc;
+use sqlx::SqlitePool;
+use tracing::info;
+
+use crate::config::Config;
+use crate::models::{Lead, Suppression, SuppressionKind};
+use crate::signing;
+
+/// Phrases that mean the lead wants no further contact.
+const OPT_OUT_PHRASES: &[&str] = &[
+    "unsubscribe",
+    "remove me",
+    "take me off",
+    "opt out",
+    "opt-out",
+    "stop emailing",
+    "stop contacting",
+    "do not contact",
+    "don't contact",
+];
+
+/// Single-word replies that mean the same.
+const OPT_OUT_WORDS: &[&str] = &["stop", "unsubscribe", "remove"];
+
+pub fn normalize(kind: SuppressionKind, value: &str) -> String {
+    let value = value.trim();
+    match kind {
+        SuppressionKind::Email => value.to_lowercase(),
+        SuppressionKind::Domain => value.trim_start_matches('@').to_lowercase(),
+        SuppressionKind::Phone => value
+            .chars()
+            .enumerate()
+            .filter(|(i, c)| c.is_ascii_digit() || (*i == 0 && *c == '+'))
+            .map(|(_, c)| c)
+            .collect(),
+    }
+}
+
+/// Returns the suppression entry that blocks contacting a lead, if any,
+/// matching its email, its email's domain and its phone.
+pub async fn lead_suppression(
+    pool: &SqlitePool,
+    lead: &Lead,
+) -> Result<Option<Suppression>, sqlx::Error> {
+    let mut candidates = Vec::new();
+
+    if let Some(email) = lead.email.as_deref() {
+        candidates.push((SuppressionKind::Email, normalize(SuppressionKind::Email, email)));
+        if let Some((_, domain)) = email.rsplit_once('@') {
+            candidates.push((
+                SuppressionKind::Domain,
+                normalize(SuppressionKind::Domain, domain),
+            ));
+        }
+    }
+    if let Some(phone) = lead.phone.as_deref() {
+        candidates.push((SuppressionKind::Phone, normalize(SuppressionKind::Phone, phone)));
+    }
+
+    for (kind, value) in candidates {
+        let found = sqlx::query_as::<_, Suppression>(
+            "SELECT id, kind, value, reason, source, created_at FROM suppressions WHERE kind = ? AND value = ?",
+        )
+        .bind(kind.as_str())
+        .bind(&value)
+        .fetch_optional(pool)
+        .await?;
+
+        if found.is_some() {
+            return Ok(found);
+        }
+    }
+
+    Ok(None)
+}
+
+pub async fn suppress(
+    pool: &SqlitePool,
+    kind: SuppressionKind,
+    value: &str,
+    reason: Option<&str>,
+    source: &str,
+) -> Result<Suppression, sqlx::Error> {
+    let value = normalize(kind, value);
+
+    sqlx::query(
+        "INSERT OR IGNORE INTO suppressions (kind, value, reason, source, created_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(kind.as_str())
+    .bind(&value)
+    .bind(reason)
+    .bind(source)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+
+    info!("Suppressed {} {} ({})", kind.as_str(), value, source);
+
+    sqlx::query_as::<_, Suppression>(
+        "SELECT id, kind, value, reason, source, created_at FROM suppressions WHERE kind = ? AND value = ?",
+    )
+    .bind(kind.as_str())
+    .bind(&value)
+    .fetch_one(pool)
+    .await
+}
+
+/// Suppresses every contact handle of a lead.
+pub async fn suppress_lead(
+    pool: &SqlitePool,
+    lead: &Lead,
+    reason: &str,
+    source: &str,
+) -> Result<(), sqlx::Error> {
+    if let Some(email) = lead.email.as_deref() {
+        suppress(pool, SuppressionKind::Email, email, Some(reason), source).await?;
+    }
+    if let Some(phone) = lead.phone.as_deref() {
+        suppress(pool, SuppressionKind::Phone, phone, Some(reason), source).await?;
+    }
+    Ok(())
+}
+
+pub fn is_opt_out(text: &str) -> bool {
+    let text = text.to_lowercase();
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    if let [word] = words.as_slice()
+        && OPT_OUT_WORDS.contains(word)
+    {
+        return true;
+    }
+
+    // Whole words only, so "remove meeting notes" is not "remove me".
+    OPT_OUT_PHRASES.iter().any(|phrase| {
+        let phrase: Vec<&str> = phrase
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .collect();
+        words.windows(phrase.len()).any(|window| window == phrase)
+    })
+}
+
+pub fn unsubscribe_url(config: &Config, lead_id: i64) -> String {
+    let token = signing::sign(&config.signing_secret, &format!("u{}", lead_id));
+    format!("{}/unsubscribe/{}", config.public_url, token)
+}
+
+pub fn unsubscribe_lead_id(config: &Config, token: &str) -> Option<i64> {
+    signing::verify(&config.signing_secret, token)?
+        .strip_prefix('u')?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opt_outs() {
+        for reply in [
+            "STOP",
+            "Unsubscribe.",
+            "Please remove me from your list",
+            "Take me off this list!",
+            "I'd like to opt-out",
+            "opt out please",
+            "Stop emailing me.",
+            "Don't contact me again",
+            "please do not contact us",
+        ] {
+            assert!(is_opt_out(reply), "{}", reply);
+        }
+    }
+
+    #[test]
+    fn not_opt_outs() {
+        for reply in [
+            "Can we remove meeting notes from the deck? Interested otherwise.",
+            "Stop by our booth next week",
+            "Removed the old contact, here is the new one",
+            "Our unsubscribed users report looks off",
+            "Take me offline for now, call later",
+            "We are all set to start",
+        ] {
+            assert!(!is_opt_out(reply), "{}", reply);
+        }
+    }
+
+    #[test]
+    fn normalizes_values() {
+        assert_eq!(
+            normalize(SuppressionKind::Email, " Jane@Example.COM "),
+            "jane@example.com"
+        );
+        assert_eq!(
+            normalize(SuppressionKind::Domain, "@Example.com"),
+            "example.com"
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use chrono::Utc;
+use sqlx::SqlitePool;
+
+const MAX_TAG_LEN: usize = 64;
+
+/// Tags are compared lowercase and may contain letters, digits, `-`, `_`
+/// and `.`, so they can be written unquoted in filter expressions.
+pub fn normalize(tag: &str) -> Result<String, String> {
+    let tag = tag.trim().to_lowercase();
+    if tag.is_empty() {
+        return Err("Tags cannot be empty".to_string());
+    }
+    if tag.chars().count() > MAX_TAG_LEN
+        || !tag
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(format!(
+            "Invalid tag {:?}, use up to {} letters, digits, '-', '_' or '.'",
+            tag, MAX_TAG_LEN
+        ));
+    }
+    Ok(tag)
+}
+
+/// Normalizes a list of tags, dropping duplicates.
+pub fn normalize_all(tags: &[String]) -> Result<Vec<String>, String> {
+    if tags.is_empty() {
+        return Err("Tags are required".to_string());
+    }
+    let mut normalized = tags
+        .iter()
+        .map(|tag| normalize(tag))
+        .collect::<Result<Vec<_>, _>>()?;
+    normalized.sort();
+    normalized.dedup();
+    Ok(normalized)
+}
+
+/// Puts the tags on the leads. Returns how many were new.
+pub async fn add(pool: &SqlitePool, lead_ids: &[i64], tags: &[String]) -> Result<u64, sqlx::Error> {
+    let now = Utc::now().to_rfc3339();
+    let mut tx = pool.begin().await?;
+    let mut added = 0;
+
+    for lead_id in lead_ids {
+        for tag in tags {
+            added += sqlx::query(
+                "INSERT OR IGNORE INTO lead_tags (lead_id, tag, created_at) VALUES (?, ?, ?)",
+            )
+            .bind(lead_id)
+            .bind(tag)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+    }
+
+    tx.commit().await?;
+    Ok(added)
+}
+
+/// Takes the tag off the lead. `false` when the lead did not have it.
+pub async fn remove(pool: &SqlitePool, lead_id: i64, tag: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM lead_tags WHERE lead_id = ? AND tag = ?")
+        .bind(lead_id)
+        .bind(tag)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn lead_tags(pool: &SqlitePool, lead_id: i64) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT tag FROM lead_tags WHERE lead_id = ? ORDER BY tag")
+        .bind(lead_id)
+        .fetch_all(pool)
+        .await
+}
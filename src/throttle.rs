@@ -0,0 +1,237 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::SqlitePool;
+
+use crate::models::{MessageStatus, SenderIdentity, SenderUsage};
+
+/// Tracks recent sends for a single sender identity and decides when the
+/// next message may go out under its daily cap, hourly cap and minimum spacing.
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    sender: SenderIdentity,
+    /// Sends since the earlier of the start of the current UTC day and one hour ago, oldest first.
+    recent: Vec<DateTime<Utc>>,
+    last_sent_at: Option<DateTime<Utc>>,
+}
+
+impl Throttle {
+    pub async fn load(
+        pool: &SqlitePool,
+        sender: SenderIdentity,
+        now: DateTime<Utc>,
+    ) -> Result<Self, sqlx::Error> {
+        let window_start = start_of_day(now).min(now - Duration::hours(1));
+
+        let rows: Vec<(String,)> = sqlx::query_as(
+            r#"
+            SELECT sent_at FROM sent_emails
+            WHERE sender_id = ?
+              AND sent_at >= ?
+            ORDER BY sent_at ASC
+            "#,
+        )
+        .bind(sender.id)
+        .bind(window_start.to_rfc3339())
+        .fetch_all(pool)
+        .await?;
+
+        let recent: Vec<DateTime<Utc>> = rows.iter().filter_map(|(s,)| parse_time(s)).collect();
+
+        let last_sent_at = match recent.last() {
+            Some(last) => Some(*last),
+            None => {
+                let last: Option<String> =
+                    sqlx::query_scalar("SELECT MAX(sent_at) FROM sent_emails WHERE sender_id = ?")
+                        .bind(sender.id)
+                        .fetch_one(pool)
+                        .await?;
+                last.as_deref().and_then(parse_time)
+            }
+        };
+
+        Ok(Self {
+            sender,
+            recent,
+            last_sent_at,
+        })
+    }
+
+    pub fn sender(&self) -> &SenderIdentity {
+        &self.sender
+    }
+
+    pub fn sent_today(&self, now: DateTime<Utc>) -> i64 {
+        let day_start = start_of_day(now);
+        self.recent.iter().filter(|t| **t >= day_start).count() as i64
+    }
+
+    pub fn sent_last_hour(&self, now: DateTime<Utc>) -> i64 {
+        let hour_ago = now - Duration::hours(1);
+        self.recent.iter().filter(|t| **t > hour_ago).count() as i64
+    }
+
+    /// Returns `None` when a message can be sent right now, otherwise the
+    /// earliest time at which every limit allows another send.
+    pub fn next_slot(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut slot = now;
+
+        if let Some(cap) = self.sender.daily_cap
+            && self.sent_today(now) >= cap
+        {
+            slot = slot.max(start_of_day(now) + Duration::days(1));
+        }
+
+        if let Some(cap) = self.sender.hourly_cap {
+            let hour_ago = now - Duration::hours(1);
+            let in_hour: Vec<&DateTime<Utc>> =
+                self.recent.iter().filter(|t| **t > hour_ago).collect();
+            if cap <= 0 {
+                slot = slot.max(now + Duration::hours(1));
+            } else if in_hour.len() as i64 >= cap {
+                // The window frees up once enough of the oldest sends age out.
+                let index = in_hour.len() - cap as usize;
+                slot = slot.max(*in_hour[index] + Duration::hours(1));
+            }
+        }
+
+        if let Some(last) = self.last_sent_at {
+            let spaced = last + Duration::seconds(self.sender.min_spacing_seconds);
+            slot = slot.max(spaced);
+        }
+
+        if slot > now { Some(slot) } else { None }
+    }
+
+    pub fn record_send(&mut self, at: DateTime<Utc>) {
+        self.recent.push(at);
+        self.last_sent_at = Some(at);
+    }
+
+    pub async fn usage(
+        &self,
+        pool: &SqlitePool,
+        now: DateTime<Utc>,
+    ) -> Result<SenderUsage, sqlx::Error> {
+        let pending: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE sender_id = ? AND status = ?")
+                .bind(self.sender.id)
+                .bind(MessageStatus::Enqueued.as_str())
+                .fetch_one(pool)
+                .await?;
+
+        Ok(SenderUsage {
+            sender_id: self.sender.id,
+            sent_today: self.sent_today(now),
+            sent_last_hour: self.sent_last_hour(now),
+            daily_cap: self.sender.daily_cap,
+            hourly_cap: self.sender.hourly_cap,
+            min_spacing_seconds: self.sender.min_spacing_seconds,
+            last_sent_at: self.last_sent_at.map(|t| t.to_rfc3339()),
+            next_available_at: self.next_slot(now).map(|t| t.to_rfc3339()),
+            pending,
+        })
+    }
+}
+
+fn start_of_day(now: DateTime<Utc>) -> DateTime<Utc> {
+    now.date_naive()
+        .and_hms_opt(0, 0, 0)
+        .map(|d| d.and_utc())
+        .unwrap_or(now)
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::types::Json;
+
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        parse_time(&format!("2026-03-{}:00Z", time)).unwrap()
+    }
+
+    fn throttle(
+        daily_cap: Option<i64>,
+        hourly_cap: Option<i64>,
+        min_spacing_seconds: i64,
+        sends: &[&str],
+    ) -> Throttle {
+        let recent: Vec<DateTime<Utc>> = sends.iter().map(|t| at(t)).collect();
+        Throttle {
+            sender: SenderIdentity {
+                id: 1,
+                name: "Sales".to_string(),
+                address: "sales@example.com".to_string(),
+                daily_cap,
+                hourly_cap,
+                min_spacing_seconds,
+                created_at: "2026-01-01T00:00:00+00:00".to_string(),
+                signature: None,
+                reply_to: None,
+                transport: Json(Default::default()),
+                workspace_id: 1,
+            },
+            last_sent_at: recent.last().copied(),
+            recent,
+        }
+    }
+
+    #[test]
+    fn sends_right_away_without_limits() {
+        let now = at("04T15:30");
+        assert_eq!(throttle(None, None, 0, &["04T15:29"]).next_slot(now), None);
+        assert_eq!(throttle(Some(3), Some(3), 60, &[]).next_slot(now), None);
+    }
+
+    #[test]
+    fn daily_cap_waits_for_the_next_day() {
+        let throttle = throttle(Some(2), None, 0, &["04T10:00", "04T11:00"]);
+        let now = at("04T15:30");
+
+        assert_eq!(throttle.sent_today(now), 2);
+        assert_eq!(throttle.next_slot(now), Some(at("05T00:00")));
+    }
+
+    #[test]
+    fn hourly_cap_waits_for_the_oldest_sends_to_age_out() {
+        let now = at("04T15:30");
+        let busy = throttle(
+            None,
+            Some(2),
+            0,
+            &["04T14:20", "04T14:40", "04T15:00", "04T15:20"],
+        );
+
+        assert_eq!(busy.sent_last_hour(now), 3);
+        assert_eq!(busy.next_slot(now), Some(at("04T16:00")));
+
+        let closed = throttle(None, Some(0), 0, &[]);
+        assert_eq!(closed.next_slot(now), Some(at("04T16:30")));
+    }
+
+    #[test]
+    fn days_and_hours_roll_over_separately() {
+        let now = at("05T00:20");
+        let throttle = throttle(Some(1), Some(1), 0, &["04T23:50"]);
+
+        assert_eq!(throttle.sent_today(now), 0);
+        assert_eq!(throttle.sent_last_hour(now), 1);
+        assert_eq!(throttle.next_slot(now), Some(at("05T00:50")));
+    }
+
+    #[test]
+    fn spaces_sends() {
+        let mut throttle = throttle(None, None, 300, &["04T15:28"]);
+
+        assert_eq!(throttle.next_slot(at("04T15:30")), Some(at("04T15:33")));
+        assert_eq!(throttle.next_slot(at("04T15:33")), None);
+
+        throttle.record_send(at("04T15:33"));
+        assert_eq!(throttle.next_slot(at("04T15:35")), Some(at("04T15:38")));
+    }
+}
@@ -0,0 +1,230 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::Utc;
+use sqlx::SqlitePool;
+
+use crate::config::Config;
+use crate::models::TrackingEvent;
+use crate::scoring;
+use crate::signing;
+
+/// A transparent 1x1 GIF, served for open-tracking pixels.
+pub const PIXEL_GIF: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+pub fn open_url(config: &Config, message_id: i64) -> String {
+    let token = signing::sign(&config.signing_secret, &format!("o{}", message_id));
+    format!("{}/t/open/{}", config.public_url, token)
+}
+
+/// Redirect URL for a link; the destination is carried in the signed token
+/// so it cannot be swapped for another one.
+pub fn click_url(config: &Config, message_id: i64, url: &str) -> String {
+    let payload = format!("c{}.{}", message_id, URL_SAFE_NO_PAD.encode(url));
+    let token = signing::sign(&config.signing_secret, &payload);
+    format!("{}/t/click/{}", config.public_url, token)
+}
+
+pub fn open_message_id(config: &Config, token: &str) -> Option<i64> {
+    signing::verify(&config.signing_secret, token)?
+        .strip_prefix('o')?
+        .parse()
+        .ok()
+}
+
+/// Message and destination of a click token.
+pub fn click_target(config: &Config, token: &str) -> Option<(i64, String)> {
+    let payload = signing::verify(&config.signing_secret, token)?;
+    let (message_id, url) = payload.strip_prefix('c')?.split_once('.')?;
+    let url = String::from_utf8(URL_SAFE_NO_PAD.decode(url).ok()?).ok()?;
+    Some((message_id.parse().ok()?, url))
+}
+
+pub fn is_trackable(url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// Rewrites the `href` of every http(s) link in sanitized HTML.
+pub fn rewrite_html(html: &str, rewrite: impl Fn(&str) -> String) -> String {
+    const HREF: &str = "href=\"";
+
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find(HREF) {
+        let value_start = start + HREF.len();
+        let Some(len) = rest[value_start..].find('"') else {
+            break;
+        };
+        out.push_str(&rest[..value_start]);
+
+        let href = &rest[value_start..value_start + len];
+        let url = href.replace("&amp;", "&");
+        if is_trackable(&url) {
+            out.push_str(&rewrite(&url).replace('&', "&amp;"));
+        } else {
+            out.push_str(href);
+        }
+        rest = &rest[value_start + len..];
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Rewrites every http(s) URL in plain text. Trailing punctuation is left
+/// outside the link.
+pub fn rewrite_text(text: &str, rewrite: impl Fn(&str) -> String) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = next_url(rest) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"'))
+            .unwrap_or(rest.len());
+        let url = rest[..end].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '\'']);
+        out.push_str(&rewrite(url));
+        rest = &rest[url.len()..];
+    }
+    out.push_str(rest);
+
+    out
+}
+
+fn next_url(text: &str) -> Option<usize> {
+    match (text.find("http://"), text.find("https://")) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Logs an open or click in `outreach_log` and bumps the message's counter.
+pub async fn record_event(
+    pool: &SqlitePool,
+    message_id: i64,
+    event: TrackingEvent,
+    user_agent: Option<&str>,
+    url: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    let counter = match event {
+        TrackingEvent::Opened => "open_count",
+        TrackingEvent::Clicked => "click_count",
+    };
+
+    let updated = sqlx::query(&format!(
+        "UPDATE messages SET {} = {} + 1 WHERE id = ? AND tracking = 1",
+        counter, counter
+    ))
+    .bind(message_id)
+    .execute(pool)
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query(
+        "INSERT INTO outreach_log (message_id, log_at, step, user_agent, url) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(message_id)
+    .bind(Utc::now().to_rfc3339())
+    .bind(event.as_str())
+    .bind(user_agent)
+    .bind(url)
+    .execute(pool)
+    .await?;
+
+    let pool = pool.clone();
+    tokio::spawn(async move { scoring::rescore_message_lead(&pool, message_id).await });
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::config::{AiSettings, SmsSettings};
+
+    fn config() -> Config {
+        Config {
+            public_url: "https://app.example.com".to_string(),
+            signing_secret: "secret".to_string(),
+            inbound_maildir: None,
+            attachments_dir: PathBuf::from("attachments"),
+            max_attachment_bytes: 1024,
+            sms: SmsSettings::Log,
+            sms_from: None,
+            ai: AiSettings::Disabled,
+            ai_provider: None,
+            ai_prices: Default::default(),
+        }
+    }
+
+    fn mark(url: &str) -> String {
+        format!("[{}]", url)
+    }
+
+    #[test]
+    fn rewrites_text_links() {
+        assert_eq!(
+            rewrite_text(
+                "See https://example.com/a?b=1, or (http://x.io/path). Thanks!",
+                mark
+            ),
+            "See [https://example.com/a?b=1], or ([http://x.io/path]). Thanks!"
+        );
+        assert_eq!(
+            rewrite_text("<https://a.io>\n\"https://b.io/'q'\"", mark),
+            "<[https://a.io]>\n\"[https://b.io/'q]'\""
+        );
+        assert_eq!(
+            rewrite_text("Mail me at ftp://x.io or a@b.io", mark),
+            "Mail me at ftp://x.io or a@b.io"
+        );
+    }
+
+    #[test]
+    fn rewrites_html_links() {
+        assert_eq!(
+            rewrite_html(
+                "<a href=\"https://x.io/?a=1&amp;b=2\">x</a> <a href=\"mailto:a@b.io\">mail</a>",
+                |url| {
+                    assert_eq!(url, "https://x.io/?a=1&b=2");
+                    "https://t.io/?m=1&u=2".to_string()
+                }
+            ),
+            "<a href=\"https://t.io/?m=1&amp;u=2\">x</a> <a href=\"mailto:a@b.io\">mail</a>"
+        );
+    }
+
+    #[test]
+    fn tokens_round_trip() {
+        let config = config();
+
+        let open = open_url(&config, 42);
+        let token = open
+            .strip_prefix("https://app.example.com/t/open/")
+            .unwrap();
+        assert_eq!(open_message_id(&config, token), Some(42));
+
+        let click = click_url(&config, 42, "https://example.com/a?b=1");
+        let token = click
+            .strip_prefix("https://app.example.com/t/click/")
+            .unwrap();
+        assert_eq!(
+            click_target(&config, token),
+            Some((42, "https://example.com/a?b=1".to_string()))
+        );
+        // Tokens are not interchangeable and cannot be altered.
+        assert_eq!(open_message_id(&config, token), None);
+        assert_eq!(click_target(&config, &format!("{}x", token)), None);
+    }
+}
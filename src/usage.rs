@@ -0,0 +1,274 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Instant;
+
+use chrono::{Datelike, Months, NaiveDate, Utc};
+use sqlx::SqlitePool;
+use tracing::{error, warn};
+
+use crate::ai::{AiProvider, Completion};
+use crate::config::Config;
+use crate::models::AiUsageLine;
+
+/// What an AI call is made for, recorded with its usage.
+pub struct AiCall {
+    pub workspace_id: i64,
+    /// `reply`, `draft` or `classify`.
+    pub purpose: &'static str,
+    pub message_id: Option<i64>,
+}
+
+#[derive(Debug)]
+pub enum AiCallError {
+    /// The workspace spent its monthly AI budget.
+    BudgetExceeded,
+    Provider(Box<dyn Error + Send + Sync>),
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for AiCallError {
+    fn from(e: sqlx::Error) -> Self {
+        AiCallError::Database(e)
+    }
+}
+
+impl fmt::Display for AiCallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AiCallError::BudgetExceeded => write!(f, "monthly AI budget exceeded"),
+            AiCallError::Provider(e) => write!(f, "{}", e),
+            AiCallError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Calls the provider unless the workspace is over its monthly budget, and
+/// records the call's tokens, latency and cost, failed calls included.
+pub async fn complete(
+    pool: &SqlitePool,
+    config: &Config,
+    provider: &dyn AiProvider,
+    call: &AiCall,
+    system: &str,
+    prompt: &str,
+) -> Result<Completion, AiCallError> {
+    let budget: Option<f64> =
+        sqlx::query_scalar("SELECT ai_monthly_budget FROM workspaces WHERE id = ?")
+            .bind(call.workspace_id)
+            .fetch_optional(pool)
+            .await?
+            .flatten();
+
+    if let Some(budget) = budget {
+        let (start, end) = this_month();
+        let spent = spent(pool, call.workspace_id, start, end).await?;
+        if spent >= budget {
+            warn!(
+                "Workspace {} spent {:.4} of its {:.4} AI budget, blocking {}",
+                call.workspace_id, spent, budget, call.purpose
+            );
+            return Err(AiCallError::BudgetExceeded);
+        }
+    }
+
+    let started = Instant::now();
+    let result = provider.complete(system, prompt).await;
+    let latency_ms = started.elapsed().as_millis() as i64;
+
+    let (prompt_tokens, completion_tokens, failure) = match &result {
+        Ok(completion) => (completion.prompt_tokens, completion.completion_tokens, None),
+        Err(e) => (0, 0, Some(e.to_string())),
+    };
+    let cost = cost(config, provider.model(), prompt_tokens, completion_tokens);
+
+    let recorded = sqlx::query(
+        r#"
+        INSERT INTO ai_usage (workspace_id, purpose, model, message_id, prompt_tokens, completion_tokens, latency_ms, cost, error, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(call.workspace_id)
+    .bind(call.purpose)
+    .bind(provider.model())
+    .bind(call.message_id)
+    .bind(prompt_tokens)
+    .bind(completion_tokens)
+    .bind(latency_ms)
+    .bind(cost)
+    .bind(&failure)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await;
+
+    if let Err(e) = recorded {
+        error!("Failed to record AI usage: {}", e);
+    }
+
+    result.map_err(AiCallError::Provider)
+}
+
+/// Cost of a call from the configured price table, 0 for unknown models.
+pub fn cost(config: &Config, model: &str, prompt_tokens: i64, completion_tokens: i64) -> f64 {
+    match config.ai_prices.get(model) {
+        Some(price) => {
+            (prompt_tokens as f64 * price.prompt + completion_tokens as f64 * price.completion)
+                / 1_000_000.0
+        }
+        None => {
+            warn!("No price configured for AI model {}, costing it 0", model);
+            0.0
+        }
+    }
+}
+
+/// First day of the current month and of the next one.
+fn this_month() -> (NaiveDate, NaiveDate) {
+    let today = Utc::now().date_naive();
+    let start = today.with_day(1).unwrap_or(today);
+    let end = start
+        .checked_add_months(Months::new(1))
+        .unwrap_or(NaiveDate::MAX);
+    (start, end)
+}
+
+/// First day of a `YYYY-MM` month and of the month after it.
+pub fn month_range(month: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let start = NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d").ok()?;
+    let end = start.checked_add_months(Months::new(1))?;
+    Some((start, end))
+}
+
+/// What the workspace spent on AI calls between `start` and `end`.
+pub async fn spent(
+    pool: &SqlitePool,
+    workspace_id: i64,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<f64, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT COALESCE(SUM(cost), 0.0) FROM ai_usage
+        WHERE workspace_id = ?
+          AND julianday(created_at) >= julianday(?)
+          AND julianday(created_at) < julianday(?)
+        "#,
+    )
+    .bind(workspace_id)
+    .bind(start.to_string())
+    .bind(end.to_string())
+    .fetch_one(pool)
+    .await
+}
+
+/// The workspace's AI calls between `start` and `end`, totalled per model
+/// and purpose.
+pub async fn report(
+    pool: &SqlitePool,
+    workspace_id: i64,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<Vec<AiUsageLine>, sqlx::Error> {
+    sqlx::query_as::<_, AiUsageLine>(
+        r#"
+        SELECT model, purpose,
+               COUNT(*) AS calls,
+               COUNT(error) AS failed_calls,
+               SUM(prompt_tokens) AS prompt_tokens,
+               SUM(completion_tokens) AS completion_tokens,
+               SUM(cost) AS cost,
+               AVG(latency_ms) AS avg_latency_ms
+        FROM ai_usage
+        WHERE workspace_id = ?
+          AND julianday(created_at) >= julianday(?)
+          AND julianday(created_at) < julianday(?)
+        GROUP BY model, purpose
+        ORDER BY model, purpose
+        "#,
+    )
+    .bind(workspace_id)
+    .bind(start.to_string())
+    .bind(end.to_string())
+    .fetch_all(pool)
+    .await
+}
+
+/// Workspace of the lead a message was sent to.
+pub async fn message_workspace(
+    pool: &SqlitePool,
+    message_id: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT l.workspace_id FROM messages m JOIN leads l ON l.id = m.leads_id WHERE m.id = ?",
+    )
+    .bind(message_id)
+    .fetch_optional(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::config::{AiSettings, ModelPrice, SmsSettings};
+
+    fn config() -> Config {
+        Config {
+            public_url: "https://app.example.com".to_string(),
+            signing_secret: "secret".to_string(),
+            inbound_maildir: None,
+            attachments_dir: PathBuf::from("attachments"),
+            max_attachment_bytes: 1024,
+            sms: SmsSettings::Log,
+            sms_from: None,
+            ai: AiSettings::Disabled,
+            ai_provider: None,
+            ai_prices: [(
+                "small".to_string(),
+                ModelPrice {
+                    prompt: 0.15,
+                    completion: 0.6,
+                },
+            )]
+            .into(),
+        }
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn costs_tokens_per_million() {
+        let config = config();
+        assert_eq!(cost(&config, "small", 0, 0), 0.0);
+        assert_eq!(cost(&config, "small", 1_000_000, 0), 0.15);
+        assert_eq!(cost(&config, "small", 0, 1_000_000), 0.6);
+        assert!((cost(&config, "small", 2_000, 500) - 0.0006).abs() < 1e-12);
+    }
+
+    #[test]
+    fn unknown_models_cost_nothing() {
+        assert_eq!(cost(&config(), "large", 1_000_000, 1_000_000), 0.0);
+    }
+
+    #[test]
+    fn month_range_ends_on_the_next_month() {
+        assert_eq!(
+            month_range("2026-02"),
+            Some((date("2026-02-01"), date("2026-03-01")))
+        );
+        assert_eq!(
+            month_range("2026-12"),
+            Some((date("2026-12-01"), date("2027-01-01")))
+        );
+    }
+
+    #[test]
+    fn month_range_needs_a_month() {
+        assert_eq!(month_range("2026-13"), None);
+        assert_eq!(month_range("2026"), None);
+        assert_eq!(month_range("february"), None);
+        assert_eq!(month_range("2026-02-01"), None);
+    }
+}